@@ -0,0 +1,356 @@
+use crate::fluid_sim::{real::Real, vec2::Vec2};
+use std::sync::Arc;
+
+/// step used for the finite difference normals, in pixels
+const NORMAL_EPSILON: Real = 0.5;
+
+/// where a boundary is at some point in time. The angle is in radians.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Pose {
+    pub position: Vec2,
+    pub angle: Real,
+}
+
+impl Pose {
+    pub fn new(position: Vec2, angle: Real) -> Self {
+        Self { position, angle }
+    }
+
+    pub fn to_world(self, local: Vec2) -> Vec2 {
+        local.rotated(self.angle) + self.position
+    }
+
+    pub fn to_local(self, world: Vec2) -> Vec2 {
+        (world - self.position).rotated(-self.angle)
+    }
+
+    fn lerp(&self, other: &Pose, t: Real) -> Pose {
+        Pose {
+            position: self.position + (other.position - self.position) * t,
+            angle: self.angle + (other.angle - self.angle) * t,
+        }
+    }
+}
+
+/// obstacle geometry, all in the boundary's local space
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+    Circle {
+        radius: Real,
+    },
+    Rect {
+        half_extents: Vec2,
+    },
+    /// closed polygon, winding doesn't matter
+    Polygon(Vec<Vec2>),
+}
+
+impl Shape {
+    /// signed distance to the surface, negative means inside
+    pub fn distance(&self, p: Vec2) -> Real {
+        match self {
+            Shape::Circle { radius } => p.length() - radius,
+            Shape::Rect { half_extents } => {
+                let dx = p.x.abs() - half_extents.x;
+                let dy = p.y.abs() - half_extents.y;
+                let outside = Vec2 {
+                    x: dx.max(0.),
+                    y: dy.max(0.),
+                };
+                outside.length() + dx.max(dy).min(0.)
+            }
+            Shape::Polygon(points) => polygon_distance(points, p),
+        }
+    }
+
+    /// outward facing normal, found with central differences so every shape gets it for free
+    pub fn normal(&self, p: Vec2) -> Vec2 {
+        let dx = Vec2 {
+            x: NORMAL_EPSILON,
+            y: 0.,
+        };
+        let dy = Vec2 {
+            x: 0.,
+            y: NORMAL_EPSILON,
+        };
+        let gradient = Vec2 {
+            x: self.distance(p + dx) - self.distance(p - dx),
+            y: self.distance(p + dy) - self.distance(p - dy),
+        };
+        let length = gradient.length();
+        if length < 1e-6 {
+            Vec2 { x: 0., y: -1. }
+        } else {
+            gradient / length
+        }
+    }
+}
+
+/// the usual polygon sdf: distance to the closest edge, sign from a crossing count
+fn polygon_distance(points: &[Vec2], p: Vec2) -> Real {
+    if points.len() < 2 {
+        return Real::MAX;
+    }
+
+    let mut closest = (p - points[0]).dot(p - points[0]);
+    let mut inside = false;
+    let mut j = points.len() - 1;
+    for i in 0..points.len() {
+        let edge = points[j] - points[i];
+        let to_point = p - points[i];
+        let t = (to_point.dot(edge) / edge.dot(edge)).clamp(0., 1.);
+        let offset = to_point - edge * t;
+        closest = closest.min(offset.dot(offset));
+
+        let (a, b) = (points[i], points[j]);
+        if (a.y > p.y) != (b.y > p.y) && p.x < (b.x - a.x) * (p.y - a.y) / (b.y - a.y) + a.x {
+            inside = !inside;
+        }
+        j = i;
+    }
+
+    if inside {
+        -closest.sqrt()
+    } else {
+        closest.sqrt()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Keyframe {
+    pub time: Real,
+    pub pose: Pose,
+}
+
+/// how a boundary gets around. Everything is evaluated at simulated time, not wall time.
+#[derive(Clone)]
+pub enum Motion {
+    Static(Pose),
+    /// linear interpolation between frames, which have to be sorted by time
+    Keyframes {
+        frames: Vec<Keyframe>,
+        looping: bool,
+    },
+    Scripted(Arc<dyn Fn(Real) -> Pose + Send + Sync>),
+}
+
+impl std::fmt::Debug for Motion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Motion::Static(pose) => f.debug_tuple("Static").field(pose).finish(),
+            Motion::Keyframes { frames, looping } => f
+                .debug_struct("Keyframes")
+                .field("frames", frames)
+                .field("looping", looping)
+                .finish(),
+            Motion::Scripted(_) => f.write_str("Scripted(..)"),
+        }
+    }
+}
+
+impl Motion {
+    pub fn scripted(path: impl Fn(Real) -> Pose + Send + Sync + 'static) -> Self {
+        Motion::Scripted(Arc::new(path))
+    }
+
+    pub fn pose_at(&self, time: Real) -> Pose {
+        match self {
+            Motion::Static(pose) => *pose,
+            Motion::Scripted(path) => path(time),
+            Motion::Keyframes { frames, looping } => {
+                let (Some(first), Some(last)) = (frames.first(), frames.last()) else {
+                    return Pose::default();
+                };
+
+                let span = last.time - first.time;
+                let time = if *looping && span > 0. {
+                    first.time + (time - first.time).rem_euclid(span)
+                } else {
+                    time
+                };
+
+                if time <= first.time {
+                    return first.pose;
+                }
+                if time >= last.time {
+                    return last.pose;
+                }
+
+                let next = frames.partition_point(|frame| frame.time <= time);
+                let (before, after) = (frames[next - 1], frames[next]);
+                let t = (time - before.time) / (after.time - before.time);
+                before.pose.lerp(&after.pose, t)
+            }
+        }
+    }
+}
+
+/// a wall or obstacle the particles bounce off. Moving ones drag the particles along with them.
+#[derive(Clone, Debug)]
+pub struct Boundary {
+    pub shape: Shape,
+    pub motion: Motion,
+    /// keeps particles inside the shape instead of outside, for tanks and containers
+    pub hollow: bool,
+}
+
+impl Boundary {
+    pub fn solid(shape: Shape, motion: Motion) -> Self {
+        Self {
+            shape,
+            motion,
+            hollow: false,
+        }
+    }
+
+    pub fn container(shape: Shape, motion: Motion) -> Self {
+        Self {
+            shape,
+            motion,
+            hollow: true,
+        }
+    }
+
+    pub fn pose_at(&self, time: Real) -> Pose {
+        self.motion.pose_at(time)
+    }
+
+    /// pushes a particle back to the surface and reflects it in the boundary's frame, so it
+    /// picks up whatever velocity the wall had
+    pub(crate) fn collide(
+        &self,
+        before: &Pose,
+        after: &Pose,
+        delta: Real,
+        restitution: Real,
+        pos: &mut Vec2,
+        vel: &mut Vec2,
+    ) {
+        collide_with_shape(
+            &self.shape,
+            self.hollow,
+            before,
+            after,
+            delta,
+            restitution,
+            pos,
+            vel,
+        );
+    }
+}
+
+/// velocity of a point stuck to something moving from `before` to `after` over `delta` seconds
+pub fn velocity_between(before: &Pose, after: &Pose, delta: Real, world: Vec2) -> Vec2 {
+    if delta <= 0. {
+        return Vec2::default();
+    }
+    let local = after.to_local(world);
+    (after.to_world(local) - before.to_world(local)) / delta
+}
+
+/// the guts of a boundary collision, shared with the rigid bodies. Gives back true if the
+/// particle actually hit the shape.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn collide_with_shape(
+    shape: &Shape,
+    hollow: bool,
+    before: &Pose,
+    after: &Pose,
+    delta: Real,
+    restitution: Real,
+    pos: &mut Vec2,
+    vel: &mut Vec2,
+) -> bool {
+    let local = after.to_local(*pos);
+    let mut distance = shape.distance(local);
+    let mut normal = shape.normal(local);
+    if hollow {
+        distance = -distance;
+        normal = -normal;
+    }
+    if distance >= 0. {
+        return false;
+    }
+
+    let normal = normal.rotated(after.angle);
+    *pos -= normal * distance;
+
+    let wall_velocity = velocity_between(before, after, delta, *pos);
+    let mut relative = *vel - wall_velocity;
+    let into_wall = relative.dot(normal);
+    if into_wall < 0. {
+        relative -= normal * (into_wall * (1. + restitution));
+    }
+    *vel = relative + wall_velocity;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pose(x: Real, y: Real) -> Pose {
+        Pose::new(Vec2 { x, y }, 0.)
+    }
+
+    #[test]
+    fn keyframes_interpolate_and_loop() {
+        let motion = Motion::Keyframes {
+            frames: vec![
+                Keyframe {
+                    time: 0.,
+                    pose: pose(0., 0.),
+                },
+                Keyframe {
+                    time: 2.,
+                    pose: pose(100., 0.),
+                },
+            ],
+            looping: true,
+        };
+
+        assert_eq!(motion.pose_at(1.).position, Vec2 { x: 50., y: 0. });
+        assert_eq!(motion.pose_at(3.).position, Vec2 { x: 50., y: 0. });
+        assert_eq!(motion.pose_at(-1.).position, Vec2 { x: 50., y: 0. });
+    }
+
+    #[test]
+    fn shapes_have_the_right_sign() {
+        let rect = Shape::Rect {
+            half_extents: Vec2 { x: 10., y: 5. },
+        };
+        let square = Shape::Polygon(vec![
+            Vec2 { x: -10., y: -5. },
+            Vec2 { x: 10., y: -5. },
+            Vec2 { x: 10., y: 5. },
+            Vec2 { x: -10., y: 5. },
+        ]);
+
+        for shape in [&rect, &square] {
+            assert!(shape.distance(Vec2::default()) < 0.);
+            assert!((shape.distance(Vec2 { x: 0., y: 8. }) - 3.).abs() < 1e-4);
+            assert!((shape.distance(Vec2 { x: 20., y: 0. }) - 10.).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn moving_wall_drags_particles() {
+        let paddle = Boundary::solid(
+            Shape::Rect {
+                half_extents: Vec2 { x: 10., y: 50. },
+            },
+            Motion::scripted(|t| pose(100. * t, 0.)),
+        );
+        let before = paddle.pose_at(0.);
+        let after = paddle.pose_at(0.1);
+
+        // sitting still just inside the paddle's leading edge
+        let mut pos = Vec2 { x: 19., y: 0. };
+        let mut vel = Vec2::default();
+        paddle.collide(&before, &after, 0.1, 0., &mut pos, &mut vel);
+
+        assert!((pos.x - 20.).abs() < 1e-3);
+        assert!((vel.x - 100.).abs() < 1e-2);
+        assert!(vel.y.abs() < 1e-3);
+    }
+}
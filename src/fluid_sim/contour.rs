@@ -0,0 +1,317 @@
+//! Finding the fluid's free surface as outlines, with marching squares.
+//!
+//! Every particle adds a soft blob to a grid, the same shape the liquid renderer splats, and
+//! the outlines are wherever the total crosses a level. The grid gets a ring of empty nodes
+//! round the outside so every outline closes, fluid against a wall gets outlined along the
+//! wall.
+
+use crate::fluid_sim::{grid::NeighbourGrid, real::Real, vec2::Vec2};
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// how the outlines get found
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ContourConfig {
+    /// grid spacing in pixels, smaller follows the fluid closer but costs more
+    pub cell: Real,
+    /// how far each particle's blob reaches, in pixels
+    pub radius: Real,
+    /// how much blob counts as fluid. A lone particle's blob is 1 in the middle.
+    pub level: Real,
+}
+
+impl Default for ContourConfig {
+    fn default() -> Self {
+        // the same as the liquid renderer, so the outline sits on its edge
+        Self {
+            cell: 4.,
+            radius: 12.,
+            level: 0.5,
+        }
+    }
+}
+
+/// a closed outline in window pixels, the last point joins back up to the first. Fluid's on
+/// the left going round, with y pointing down the screen.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Contour {
+    pub points: Vec<Vec2>,
+}
+
+impl Contour {
+    /// positive for an outline round some fluid, negative for one round a bubble in it
+    pub fn area(&self) -> Real {
+        let n = self.points.len();
+        // the shoelace formula, flipped since y points down
+        -(0..n)
+            .map(|i| self.points[i].cross(self.points[(i + 1) % n]))
+            .sum::<Real>()
+            / 2.
+    }
+}
+
+/// blob totals on a grid of nodes, with the empty ring round the outside
+struct Field {
+    /// nodes across and down, ring included
+    columns: usize,
+    rows: usize,
+    cell: Real,
+    values: Vec<Real>,
+}
+
+impl Field {
+    fn new(
+        positions: &[Vec2],
+        config: &ContourConfig,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> Self {
+        let cell = config.cell.max(0.5);
+        let columns = (size.width as Real / cell).ceil() as usize + 3;
+        let rows = (size.height as Real / cell).ceil() as usize + 3;
+        let grid = NeighbourGrid::new(config.radius, positions);
+        let radius_squared = config.radius * config.radius;
+
+        let values = (0..columns * rows)
+            .into_par_iter()
+            .map(|node| {
+                let (x, y) = (node % columns, node / columns);
+                if x == 0 || y == 0 || x == columns - 1 || y == rows - 1 {
+                    return 0.;
+                }
+                let at = Vec2 {
+                    x: (x - 1) as Real * cell,
+                    y: (y - 1) as Real * cell,
+                };
+                let mut total = 0.;
+                grid.for_each_near(at, |i| {
+                    let offset = positions[i] - at;
+                    let falloff = (1. - offset.dot(offset) / radius_squared).max(0.);
+                    total += falloff * falloff * falloff;
+                });
+                total
+            })
+            .collect();
+
+        Self {
+            columns,
+            rows,
+            cell,
+            values,
+        }
+    }
+
+    fn value(&self, x: usize, y: usize) -> Real {
+        self.values[y * self.columns + x]
+    }
+
+    fn position(&self, x: usize, y: usize) -> Vec2 {
+        Vec2 {
+            x: (x as Real - 1.) * self.cell,
+            y: (y as Real - 1.) * self.cell,
+        }
+    }
+}
+
+/// the outlines of the fluid made by `positions`, clamped to the window
+pub fn extract(
+    positions: &[Vec2],
+    config: &ContourConfig,
+    size: winit::dpi::PhysicalSize<u32>,
+) -> Vec<Contour> {
+    let field = Field::new(positions, config, size);
+    let level = config.level;
+    let width = size.width as Real;
+    let height = size.height as Real;
+
+    // an id for every grid edge, the horizontal one from each node then the vertical one
+    let horizontal = |x: usize, y: usize| 2 * (y * field.columns + x);
+    let vertical = |x: usize, y: usize| 2 * (y * field.columns + x) + 1;
+    // where the level crosses an edge, blended between its two ends
+    let crossing = |edge: usize| {
+        let node = edge / 2;
+        let (x, y) = (node % field.columns, node / field.columns);
+        let (other_x, other_y) = if edge.is_multiple_of(2) {
+            (x + 1, y)
+        } else {
+            (x, y + 1)
+        };
+        let (a, b) = (field.value(x, y), field.value(other_x, other_y));
+        let t = if a == b { 0.5 } else { (level - a) / (b - a) };
+        let (from, to) = (field.position(x, y), field.position(other_x, other_y));
+        let point = from + (to - from) * t.clamp(0., 1.);
+        Vec2 {
+            x: point.x.clamp(0., width),
+            y: point.y.clamp(0., height),
+        }
+    };
+
+    // every cell's bits of outline, as the pair of edges each one runs between
+    let segments: Vec<(usize, usize)> = (0..(field.columns - 1) * (field.rows - 1))
+        .into_par_iter()
+        .flat_map_iter(|cell| {
+            let (x, y) = (cell % (field.columns - 1), cell / (field.columns - 1));
+            let corners = [
+                field.value(x, y),
+                field.value(x + 1, y),
+                field.value(x + 1, y + 1),
+                field.value(x, y + 1),
+            ];
+            let inside = corners.map(|value| value >= level);
+            // top, right, bottom, left, each between the corners either side of it
+            let edges = [
+                horizontal(x, y),
+                vertical(x + 1, y),
+                horizontal(x, y + 1),
+                vertical(x, y),
+            ];
+            let crossed: Vec<usize> = (0..4)
+                .filter(|&side| inside[side] != inside[(side + 1) % 4])
+                .collect();
+
+            let pairs = match crossed.as_slice() {
+                [a, b] => vec![(edges[*a], edges[*b])],
+                // a saddle, the middle decides whether the two inside corners join up
+                [_, _, _, _] => {
+                    let middle = corners.iter().sum::<Real>() / 4. >= level;
+                    // cut off the two corners that aren't joined to each other
+                    if inside[0] == middle {
+                        vec![(edges[0], edges[1]), (edges[2], edges[3])]
+                    } else {
+                        vec![(edges[3], edges[0]), (edges[1], edges[2])]
+                    }
+                }
+                _ => Vec::new(),
+            };
+            pairs.into_iter().map(move |(a, b)| {
+                // point them so the fluid's always on the same side
+                let fluid_first = inside[corner_after(edges, a)];
+                if fluid_first { (a, b) } else { (b, a) }
+            })
+        })
+        .collect();
+
+    // every crossed edge starts exactly one segment and ends exactly one, so following them
+    // round always gets back to the start
+    let next: BTreeMap<usize, usize> = segments.into_iter().collect();
+    let mut visited = std::collections::HashSet::new();
+    let mut contours = Vec::new();
+    for &start in next.keys() {
+        if !visited.insert(start) {
+            continue;
+        }
+        let mut points = vec![crossing(start)];
+        let mut edge = next[&start];
+        while visited.insert(edge) {
+            points.push(crossing(edge));
+            let Some(&after) = next.get(&edge) else {
+                break;
+            };
+            edge = after;
+        }
+        contours.push(Contour { points });
+    }
+    contours
+}
+
+/// which corner of a cell comes right after `edge` going clockwise, `edges` being the cell's
+/// top, right, bottom and left
+fn corner_after(edges: [usize; 4], edge: usize) -> usize {
+    let side = edges.iter().position(|e| *e == edge).unwrap_or(0);
+    (side + 1) % 4
+}
+
+/// the outlines as an svg the size of the window, fluid filled in and bubbles left out
+pub fn to_svg(contours: &[Contour], size: winit::dpi::PhysicalSize<u32>) -> String {
+    let mut path = String::new();
+    for contour in contours {
+        for (i, point) in contour.points.iter().enumerate() {
+            let command = if i == 0 { 'M' } else { 'L' };
+            let _ = write!(path, "{command}{:.2} {:.2} ", point.x, point.y);
+        }
+        path.push_str("Z ");
+    }
+
+    format!(
+        concat!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" ",
+            "viewBox=\"0 0 {w} {h}\">\n",
+            "  <path d=\"{path}\" fill=\"#3377dd\" fill-opacity=\"0.3\" fill-rule=\"evenodd\" ",
+            "stroke=\"#000000\" stroke-width=\"1\"/>\n",
+            "</svg>\n"
+        ),
+        w = size.width,
+        h = size.height,
+        path = path.trim_end(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fluid_sim::real::consts::TAU;
+
+    const SIZE: winit::dpi::PhysicalSize<u32> = winit::dpi::PhysicalSize::new(200, 150);
+
+    #[test]
+    fn a_lone_particle_gets_a_circle() {
+        let centre = Vec2 { x: 100., y: 75. };
+        let config = ContourConfig::default();
+        let contours = extract(&[centre], &config, SIZE);
+        assert_eq!(contours.len(), 1);
+
+        // (1 - r²/R²)³ = level
+        let radius = config.radius * (1. - config.level.cbrt()).sqrt();
+        for point in &contours[0].points {
+            let off = ((*point - centre).length() - radius).abs();
+            assert!(off < 0.5, "{off}");
+        }
+        assert!(contours[0].area() > 0.);
+    }
+
+    #[test]
+    fn bubbles_go_the_other_way_round() {
+        // a ring of particles, thick enough to hold together, round an empty middle
+        let centre = Vec2 { x: 100., y: 75. };
+        let ring: Vec<Vec2> = (0..2)
+            .flat_map(|layer| {
+                let radius = 40. + layer as Real * 6.;
+                (0..60).map(move |i| {
+                    let angle = i as Real / 60. * TAU;
+                    centre
+                        + Vec2 {
+                            x: angle.cos(),
+                            y: angle.sin(),
+                        } * radius
+                })
+            })
+            .collect();
+
+        let mut contours = extract(&ring, &ContourConfig::default(), SIZE);
+        contours.sort_by(|a, b| a.area().total_cmp(&b.area()));
+        assert_eq!(contours.len(), 2);
+        assert!(contours[0].area() < 0. && contours[1].area() > 0.);
+        assert!(contours[1].area() > -contours[0].area());
+    }
+
+    #[test]
+    fn fluid_against_the_walls_closes_along_them() {
+        // a full row along the floor
+        let floor: Vec<Vec2> = (0..=50)
+            .map(|i| Vec2 {
+                x: i as Real * 4.,
+                y: 148.,
+            })
+            .collect();
+        let contours = extract(&floor, &ContourConfig::default(), SIZE);
+        assert_eq!(contours.len(), 1);
+        let points = &contours[0].points;
+        assert!(points.iter().any(|p| p.y == 150.));
+        assert!(points.iter().any(|p| p.x == 0.) && points.iter().any(|p| p.x == 200.));
+
+        let svg = to_svg(&contours, SIZE);
+        assert!(svg.starts_with("<svg") && svg.trim_end().ends_with("</svg>"));
+        assert_eq!(svg.matches('M').count(), 1);
+        assert_eq!(svg.matches('Z').count(), 1);
+    }
+}
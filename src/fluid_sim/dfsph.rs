@@ -0,0 +1,469 @@
+//! Divergence-free SPH, after Bender & Koschier 2015.
+//!
+//! Two implicit pressure solves a step. The first stops the velocity field from squashing the
+//! fluid, the second mops up whatever density error is left over. Both keep going until the
+//! average error is under a tolerance, so the fluid ends up about as incompressible as you ask
+//! for, at the cost of more iterations.
+
+use crate::fluid_sim::{
+    FluidSim, SolverMode,
+    diagnostics::StepDiagnostics,
+    real::Real,
+    simulation::{Parameter, count},
+    sph::{self, Kernel, Walls},
+    vec2::Vec2,
+};
+use rayon::prelude::*;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DfsphConfig {
+    /// kernel radius, in pixels
+    pub smoothing_radius: Real,
+    /// how far apart particles sit when the fluid is at rest, sets the rest density
+    pub particle_spacing: Real,
+    /// average density error the density solve stops at, as a fraction of rest density
+    pub density_tolerance: Real,
+    /// average compression rate the divergence solve stops at, as a fraction of rest density
+    /// per second
+    pub divergence_tolerance: Real,
+    /// cap on the passes either solve gets, whether or not it hit its tolerance
+    pub max_iterations: usize,
+    /// xsph blend toward the neighbours' velocity
+    pub viscosity: Real,
+}
+
+impl Default for DfsphConfig {
+    fn default() -> Self {
+        Self {
+            smoothing_radius: 20.,
+            particle_spacing: 8.,
+            density_tolerance: 0.01,
+            divergence_tolerance: 0.1,
+            max_iterations: 100,
+            viscosity: 0.1,
+        }
+    }
+}
+
+impl DfsphConfig {
+    pub fn rest_density(&self) -> Real {
+        sph::rest_density(&Kernel::new(self.smoothing_radius), self.particle_spacing)
+    }
+
+    pub fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new("smoothing radius", self.smoothing_radius, 5.0..=60.),
+            Parameter::new("particle spacing", self.particle_spacing, 2.0..=30.),
+            Parameter::new("density tolerance", self.density_tolerance, 1e-3..=0.1),
+            Parameter::new("divergence tolerance", self.divergence_tolerance, 1e-2..=1.),
+            Parameter::whole("max iterations", self.max_iterations, 1..=200),
+            Parameter::new("viscosity", self.viscosity, 0.0..=1.),
+        ]
+    }
+
+    pub fn set_parameter(&mut self, name: &str, value: Real) {
+        match name {
+            "smoothing radius" => self.smoothing_radius = value,
+            "particle spacing" => self.particle_spacing = value,
+            "density tolerance" => self.density_tolerance = value,
+            "divergence tolerance" => self.divergence_tolerance = value,
+            "max iterations" => self.max_iterations = count(value),
+            "viscosity" => self.viscosity = value,
+            _ => {}
+        }
+    }
+}
+
+/// something that isn't fluid but is close enough to count: a wall or a body's ghost
+#[derive(Copy, Clone, Debug)]
+struct Contact {
+    gradient: Vec2,
+    velocity: Vec2,
+    /// which body it was and where, walls don't care about being pushed back
+    body: Option<(usize, Vec2)>,
+}
+
+/// what a solver pass started from, so it can be taken back
+struct Pass {
+    velocities: Vec<Vec2>,
+    /// the errors measured on `velocities`, so a retry pushes on the state it went back to
+    errors: Vec<Real>,
+    average: Real,
+    kicks: Vec<(Vec2, Real)>,
+}
+
+/// everything about the particles that stays put while the velocities get solved for
+struct Neighbourhood {
+    /// neighbour index and the kernel gradient toward it
+    fluid: Vec<Vec<(usize, Vec2)>>,
+    contacts: Vec<Vec<Contact>>,
+    densities: Vec<Real>,
+    /// the alpha factor from the paper, folds the pressure solve's diagonal in
+    factors: Vec<Real>,
+}
+
+impl Neighbourhood {
+    /// how fast the density around particle i is growing
+    fn density_change(&self, i: usize, velocities: &[Vec2]) -> Real {
+        let fluid: Real = self.fluid[i]
+            .iter()
+            .map(|(j, gradient)| (velocities[i] - velocities[*j]).dot(*gradient))
+            .sum();
+        let contacts: Real = self.contacts[i]
+            .iter()
+            .map(|contact| (velocities[i] - contact.velocity).dot(contact.gradient))
+            .sum();
+        fluid + contacts
+    }
+
+    /// the shared loop behind both solves. `error` turns a particle's density and density
+    /// change into how far off it is, `stiffness` turns that into a pressure. Gives back the
+    /// passes it took, the average error it finished on, and the impulses the bodies got.
+    #[allow(clippy::too_many_arguments)]
+    fn solve(
+        &self,
+        velocities: &mut [Vec2],
+        delta: Real,
+        tolerance: Real,
+        max_iterations: usize,
+        min_iterations: usize,
+        error: impl Fn(Real, Real) -> Real + Sync,
+        stiffness: Real,
+        bodies: usize,
+    ) -> (usize, Real, Vec<(Vec2, Real)>) {
+        let mut impulses = vec![(Vec2::default(), 0.); bodies];
+        let mut iterations = 0;
+        // the last pass, in case it has to be taken back
+        let mut undo: Option<Pass> = None;
+        let mut relaxation = 1.;
+
+        loop {
+            let mut errors: Vec<Real> = (0..velocities.len())
+                .into_par_iter()
+                .map(|i| error(self.densities[i], self.density_change(i, velocities)))
+                .collect();
+            let mut average = errors.iter().sum::<Real>() / errors.len().max(1) as Real;
+
+            // squash particles together hard enough and the jacobi style passes overshoot and
+            // make things worse instead of better. Take back the pass that did it and try
+            // again from where it started with smaller steps, rather than letting it run off
+            // to infinity.
+            if let Some(pass) = undo.take()
+                && (average > pass.average || average.is_nan())
+            {
+                velocities.copy_from_slice(&pass.velocities);
+                for (total, kick) in impulses.iter_mut().zip(pass.kicks) {
+                    total.0 -= kick.0;
+                    total.1 -= kick.1;
+                }
+                errors = pass.errors;
+                average = pass.average;
+                relaxation *= 0.5;
+                if relaxation < 1e-3 {
+                    return (iterations, average, impulses);
+                }
+            }
+
+            if (average <= tolerance && iterations >= min_iterations)
+                || iterations >= max_iterations
+            {
+                return (iterations, average, impulses);
+            }
+
+            let pressures: Vec<Real> = errors
+                .iter()
+                .zip(&self.factors)
+                .zip(&self.densities)
+                .map(|((error, factor), density)| error * factor * stiffness * relaxation / density)
+                .collect();
+
+            let old_velocities = velocities.to_vec();
+            let kicks = velocities
+                .par_iter_mut()
+                .enumerate()
+                .fold(
+                    || vec![(Vec2::default(), 0.); bodies],
+                    |mut kicks, (i, velocity)| {
+                        let mut change = Vec2::default();
+                        for (j, gradient) in &self.fluid[i] {
+                            change -= *gradient * (pressures[i] + pressures[*j]);
+                        }
+                        for contact in &self.contacts[i] {
+                            let push = contact.gradient * (-pressures[i] * delta);
+                            change += contact.gradient * -pressures[i];
+                            if let Some((body, lever)) = contact.body {
+                                kicks[body].0 -= push;
+                                kicks[body].1 -= lever.cross(push);
+                            }
+                        }
+                        *velocity = old_velocities[i] + change * delta;
+                        kicks
+                    },
+                )
+                .reduce(
+                    || vec![(Vec2::default(), 0.); bodies],
+                    |mut total, part| {
+                        for (total, part) in total.iter_mut().zip(part) {
+                            total.0 += part.0;
+                            total.1 += part.1;
+                        }
+                        total
+                    },
+                );
+            for (total, kick) in impulses.iter_mut().zip(&kicks) {
+                total.0 += kick.0;
+                total.1 += kick.1;
+            }
+            undo = Some(Pass {
+                velocities: old_velocities,
+                errors,
+                average,
+                kicks,
+            });
+
+            iterations += 1;
+        }
+    }
+}
+
+impl FluidSim {
+    /// densities, gradients and alpha factors, all fixed for the rest of the step
+    fn neighbourhood(
+        &self,
+        kernel: &Kernel,
+        neighbours: &[Vec<usize>],
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> Neighbourhood {
+        let walls = Walls::new(kernel, self.dfsph.particle_spacing, size);
+        let positions = &self.current_positions;
+        let ghosts = self.ghosts();
+        let ghost_positions: Vec<Vec2> = ghosts.iter().map(|(_, ghost)| *ghost).collect();
+        let ghost_neighbours =
+            sph::neighbours_among(self.dfsph.smoothing_radius, positions, &ghost_positions);
+
+        let per_particle: Vec<_> = (0..positions.len())
+            .into_par_iter()
+            .map(|i| {
+                let pos = positions[i];
+                let mut density = kernel.poly6(0.);
+                let mut gradient_total = Vec2::default();
+                let mut gradient_squares = 0.;
+
+                let fluid: Vec<(usize, Vec2)> = neighbours[i]
+                    .iter()
+                    .map(|&j| {
+                        let offset = pos - positions[j];
+                        let gradient = kernel.spiky_gradient(offset);
+                        density += kernel.poly6(offset.dot(offset));
+                        gradient_total += gradient;
+                        gradient_squares += gradient.dot(gradient);
+                        (j, gradient)
+                    })
+                    .collect();
+
+                let mut contacts: Vec<Contact> = ghost_neighbours[i]
+                    .iter()
+                    .map(|&g| {
+                        let (body, ghost) = ghosts[g];
+                        let offset = pos - ghost;
+                        density += kernel.poly6(offset.dot(offset));
+                        Contact {
+                            gradient: kernel.spiky_gradient(offset),
+                            velocity: self.bodies[body].velocity_at(ghost),
+                            body: Some((body, ghost - self.bodies[body].position)),
+                        }
+                    })
+                    .collect();
+                for (normal, wall_density, wall_gradient) in walls.near(pos) {
+                    density += wall_density;
+                    contacts.push(Contact {
+                        gradient: normal * wall_gradient,
+                        velocity: Vec2::default(),
+                        body: None,
+                    });
+                }
+                for contact in &contacts {
+                    gradient_total += contact.gradient;
+                }
+
+                let denominator = gradient_total.dot(gradient_total) + gradient_squares;
+                let factor = if denominator > 1e-12 {
+                    density / denominator
+                } else {
+                    0.
+                };
+                (fluid, contacts, density, factor)
+            })
+            .collect();
+
+        let mut neighbourhood = Neighbourhood {
+            fluid: Vec::with_capacity(per_particle.len()),
+            contacts: Vec::with_capacity(per_particle.len()),
+            densities: Vec::with_capacity(per_particle.len()),
+            factors: Vec::with_capacity(per_particle.len()),
+        };
+        for (fluid, contacts, density, factor) in per_particle {
+            neighbourhood.fluid.push(fluid);
+            neighbourhood.contacts.push(contacts);
+            neighbourhood.densities.push(density);
+            neighbourhood.factors.push(factor);
+        }
+        neighbourhood
+    }
+
+    pub(super) fn step_dfsph(&mut self, delta: Real, size: winit::dpi::PhysicalSize<u32>) {
+        let config = self.dfsph;
+        let kernel = Kernel::new(config.smoothing_radius);
+        let rest_density = config.rest_density();
+        let positions = &self.current_positions;
+        let neighbours = sph::neighbours(config.smoothing_radius, positions);
+        let neighbourhood = self.neighbourhood(&kernel, &neighbours, size);
+        let bodies = self.bodies.len();
+
+        // make the velocities divergence free first, only caring about compression since
+        // the surface is allowed to spread out
+        self.next_velocities
+            .copy_from_slice(&self.current_velocities);
+        let (divergence_iterations, divergence_error, divergence_impulses) = neighbourhood.solve(
+            &mut self.next_velocities,
+            delta,
+            config.divergence_tolerance,
+            config.max_iterations,
+            1,
+            |_, change| change.max(0.) / rest_density,
+            rest_density / delta,
+            bodies,
+        );
+
+        // outside forces and viscosity
+        let (forces, time) = (&self.forces, self.time);
+        let velocities = &self.next_velocities;
+        let smoothed: Vec<Vec2> = (0..velocities.len())
+            .into_par_iter()
+            .map(|i| {
+                let mut blend = Vec2::default();
+                for &j in &neighbours[i] {
+                    let offset = positions[i] - positions[j];
+                    blend += (velocities[j] - velocities[i]) * kernel.poly6(offset.dot(offset));
+                }
+                velocities[i]
+                    + blend * (config.viscosity / rest_density)
+                    + forces.acceleration(positions[i], velocities[i], time) * delta
+            })
+            .collect();
+        self.next_velocities.copy_from_slice(&smoothed);
+
+        // then whatever density error the predicted positions would have
+        let (iterations, density_error, density_impulses) = neighbourhood.solve(
+            &mut self.next_velocities,
+            delta,
+            config.density_tolerance,
+            config.max_iterations,
+            2,
+            |density, change| (density + change * delta - rest_density).max(0.) / rest_density,
+            rest_density / (delta * delta),
+            bodies,
+        );
+
+        for ((body, divergence), density) in self
+            .bodies
+            .iter_mut()
+            .zip(divergence_impulses)
+            .zip(density_impulses)
+        {
+            body.apply_impulses(divergence.0 + density.0, divergence.1 + density.1);
+        }
+        self.integrate_bodies(&vec![(Vec2::default(), 0.); bodies], delta);
+
+        self.next_positions
+            .par_iter_mut()
+            .zip(self.current_positions.par_iter())
+            .zip(self.next_velocities.par_iter())
+            .for_each(|((next_pos, pos), vel)| *next_pos = *pos + *vel * delta);
+
+        self.diagnostics = StepDiagnostics {
+            solver: SolverMode::Dfsph,
+            iterations,
+            density_error,
+            divergence_iterations,
+            divergence_error,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn squashed_block(squash: Real) -> FluidSim {
+        let spacing = DfsphConfig::default().particle_spacing * squash;
+        let positions: Vec<Vec2> = (0..400)
+            .map(|i| Vec2 {
+                x: 100. + (i % 20) as Real * spacing,
+                y: 100. + (i / 20) as Real * spacing,
+            })
+            .collect();
+        let mut sim = FluidSim::from_particles(positions, vec![Vec2::default(); 400]);
+        sim.set_solver(SolverMode::Dfsph);
+        sim
+    }
+
+    #[test]
+    fn solve_gets_under_tolerance_and_says_so() {
+        let mut sim = squashed_block(0.8);
+        sim.update(1. / 60., winit::dpi::PhysicalSize::new(400, 400));
+
+        let diagnostics = sim.diagnostics();
+        assert_eq!(diagnostics.solver, SolverMode::Dfsph);
+        assert!(diagnostics.iterations >= 2);
+        assert!(diagnostics.iterations < sim.dfsph.max_iterations);
+        assert!(diagnostics.density_error <= sim.dfsph.density_tolerance);
+    }
+
+    #[test]
+    fn an_overshooting_pass_gets_taken_back_and_retried_smaller() {
+        let sim = squashed_block(0.5);
+        let size = winit::dpi::PhysicalSize::new(400, 400);
+        let delta = 1. / 60.;
+        let kernel = Kernel::new(sim.dfsph.smoothing_radius);
+        let neighbours = sph::neighbours(sim.dfsph.smoothing_radius, &sim.current_positions);
+        let neighbourhood = sim.neighbourhood(&kernel, &neighbours, size);
+        let rest_density = sim.dfsph.rest_density();
+        let error = |density: Real, change: Real| {
+            (density + change * delta - rest_density).max(0.) / rest_density
+        };
+        let solve = |stiffness: Real, passes: usize| {
+            let mut velocities = vec![Vec2::default(); sim.current_positions.len()];
+            let (_, average, _) = neighbourhood.solve(
+                &mut velocities,
+                delta,
+                0.,
+                passes,
+                1,
+                error,
+                stiffness * rest_density / (delta * delta),
+                0,
+            );
+            (average, velocities)
+        };
+        let before = solve(0., 0).0;
+        // stiff enough that a whole pass makes things worse, so it gets taken back
+        assert_eq!(solve(64., 1).0, before);
+
+        // and the retry is the same pass from the same start at half the strength
+        let (retried, velocities) = solve(64., 2);
+        assert_eq!(velocities, solve(32., 1).1);
+        assert!(retried < before, "{retried} against {before}");
+    }
+
+    #[test]
+    fn iteration_cap_is_respected() {
+        let mut sim = squashed_block(0.5);
+        sim.dfsph.max_iterations = 3;
+        sim.dfsph.density_tolerance = 0.;
+        sim.update(1. / 60., winit::dpi::PhysicalSize::new(400, 400));
+
+        assert_eq!(sim.diagnostics().iterations, 3);
+        assert!(sim.diagnostics().density_error > 0.);
+    }
+}
@@ -0,0 +1,36 @@
+use crate::fluid_sim::{SolverMode, real::Real};
+
+/// what the last step got up to, for keeping an eye on how well the solver is doing
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct StepDiagnostics {
+    pub solver: SolverMode,
+    /// passes the density solve took, zero for the explicit solver since it doesn't have one
+    pub iterations: usize,
+    /// average compression relative to rest density when the density solve stopped
+    pub density_error: Real,
+    /// passes the divergence solve took, only DFSPH has one
+    pub divergence_iterations: usize,
+    /// average rate of compression relative to rest density, per second
+    pub divergence_error: Real,
+}
+
+impl std::fmt::Display for StepDiagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?}: {} iterations, {:.3}% density error",
+            self.solver,
+            self.iterations,
+            self.density_error * 100.
+        )?;
+        if self.divergence_iterations > 0 {
+            write!(
+                f,
+                ", {} divergence iterations, {:.3}%/s divergence error",
+                self.divergence_iterations,
+                self.divergence_error * 100.
+            )?;
+        }
+        Ok(())
+    }
+}
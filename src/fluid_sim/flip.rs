@@ -0,0 +1,473 @@
+//! Particle-in-cell solvers: PIC, FLIP and APIC.
+//!
+//! The particles carry the fluid around but the pressure gets solved on a MAC grid laid over
+//! the window. Each step splats the particle velocities onto the grid faces, makes the grid
+//! divergence free, then reads the velocities back off again. How they get read back is the
+//! difference between the three: PIC takes the grid velocity as is and smears everything out,
+//! FLIP only takes the change and keeps the detail but gets noisy, and APIC carries a little
+//! affine velocity per particle so PIC stops losing the swirl.
+
+use crate::fluid_sim::{
+    FluidSim, SolverMode,
+    diagnostics::StepDiagnostics,
+    real::Real,
+    simulation::{Parameter, count},
+    vec2::Vec2,
+};
+use rayon::prelude::*;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FlipConfig {
+    /// grid cell width in pixels, around two particles across works well
+    pub cell_size: Real,
+    /// how much of the FLIP velocity change to take, the rest is plain PIC. 0 is pure PIC,
+    /// 1 is pure FLIP. APIC ignores it.
+    pub flip_ratio: Real,
+    /// gauss-seidel passes for the pressure solve
+    pub pressure_iterations: usize,
+}
+
+impl Default for FlipConfig {
+    fn default() -> Self {
+        Self {
+            cell_size: 12.,
+            flip_ratio: 0.95,
+            pressure_iterations: 60,
+        }
+    }
+}
+
+impl FlipConfig {
+    pub fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new("cell size", self.cell_size, 4.0..=40.),
+            Parameter::new("flip ratio", self.flip_ratio, 0.0..=1.),
+            Parameter::whole("pressure iterations", self.pressure_iterations, 1..=200),
+        ]
+    }
+
+    pub fn set_parameter(&mut self, name: &str, value: Real) {
+        match name {
+            "cell size" => self.cell_size = value,
+            "flip ratio" => self.flip_ratio = value,
+            "pressure iterations" => self.pressure_iterations = count(value),
+            _ => {}
+        }
+    }
+}
+
+/// one component of a MAC grid's velocity. The u samples sit on the middle of the vertical
+/// cell faces and the v samples on the horizontal ones, so each gets its own lattice.
+#[derive(Copy, Clone, Debug)]
+struct Lattice {
+    width: usize,
+    height: usize,
+    /// where sample (0, 0) sits, in cells
+    offset: Vec2,
+    cell_size: Real,
+}
+
+/// a sample near a particle: its index, weight, weight gradient and where it is
+type Stencil = [(usize, Real, Vec2, Vec2); 4];
+
+impl Lattice {
+    fn len(&self) -> usize {
+        self.width * self.height
+    }
+
+    /// the four samples around `pos` with their bilinear weights
+    fn stencil(&self, pos: Vec2) -> Stencil {
+        let h = self.cell_size;
+        let grid_x = pos.x / h - self.offset.x;
+        let grid_y = pos.y / h - self.offset.y;
+        let left = (grid_x.floor().max(0.) as usize).min(self.width - 2);
+        let top = (grid_y.floor().max(0.) as usize).min(self.height - 2);
+        let fx = (grid_x - left as Real).clamp(0., 1.);
+        let fy = (grid_y - top as Real).clamp(0., 1.);
+
+        let sample = |x: usize, y: usize, weight: Real, gradient: Vec2| {
+            let at = Vec2 {
+                x: (x as Real + self.offset.x) * h,
+                y: (y as Real + self.offset.y) * h,
+            };
+            (y * self.width + x, weight, gradient / h, at)
+        };
+        [
+            sample(
+                left,
+                top,
+                (1. - fx) * (1. - fy),
+                Vec2 {
+                    x: -(1. - fy),
+                    y: -(1. - fx),
+                },
+            ),
+            sample(left + 1, top, fx * (1. - fy), Vec2 { x: 1. - fy, y: -fx }),
+            sample(left, top + 1, (1. - fx) * fy, Vec2 { x: -fy, y: 1. - fx }),
+            sample(left + 1, top + 1, fx * fy, Vec2 { x: fy, y: fx }),
+        ]
+    }
+
+    /// where sample `i` sits in the window
+    fn position(&self, i: usize) -> Vec2 {
+        Vec2 {
+            x: ((i % self.width) as Real + self.offset.x) * self.cell_size,
+            y: ((i / self.width) as Real + self.offset.y) * self.cell_size,
+        }
+    }
+
+    fn interpolate(&self, values: &[Real], pos: Vec2) -> Real {
+        self.stencil(pos)
+            .iter()
+            .map(|(i, weight, _, _)| values[*i] * weight)
+            .sum()
+    }
+
+    /// samples nobody splatted onto take the average of their neighbours that did get
+    /// something, so particles at the surface don't read zeros off the empty side
+    fn extrapolate(&self, values: &mut [Real], weights: &[Real]) {
+        let filled: Vec<bool> = weights.iter().map(|weight| *weight > 0.).collect();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let i = y * self.width + x;
+                if filled[i] {
+                    continue;
+                }
+                let neighbours = [
+                    (x > 0).then(|| i - 1),
+                    (x + 1 < self.width).then(|| i + 1),
+                    (y > 0).then(|| i - self.width),
+                    (y + 1 < self.height).then(|| i + self.width),
+                ];
+                let (total, count) = neighbours
+                    .iter()
+                    .flatten()
+                    .filter(|j| filled[**j])
+                    .fold((0., 0), |(total, count), j| (total + values[*j], count + 1));
+                if count > 0 {
+                    values[i] = total / count as Real;
+                }
+            }
+        }
+    }
+}
+
+/// velocities and weights summed up from the particles, before they get divided out
+#[derive(Clone, Debug)]
+struct Splat {
+    u: Vec<Real>,
+    u_weight: Vec<Real>,
+    v: Vec<Real>,
+    v_weight: Vec<Real>,
+}
+
+impl Splat {
+    fn new(u_len: usize, v_len: usize) -> Self {
+        Self {
+            u: vec![0.; u_len],
+            u_weight: vec![0.; u_len],
+            v: vec![0.; v_len],
+            v_weight: vec![0.; v_len],
+        }
+    }
+
+    fn merge(mut self, other: Self) -> Self {
+        for (a, b) in self
+            .u
+            .iter_mut()
+            .chain(self.u_weight.iter_mut())
+            .chain(self.v.iter_mut())
+            .chain(self.v_weight.iter_mut())
+            .zip(
+                other
+                    .u
+                    .iter()
+                    .chain(&other.u_weight)
+                    .chain(&other.v)
+                    .chain(&other.v_weight),
+            )
+        {
+            *a += b;
+        }
+        self
+    }
+}
+
+impl FluidSim {
+    pub(super) fn step_flip(&mut self, delta: Real, size: winit::dpi::PhysicalSize<u32>) {
+        let config = self.flip;
+        let apic = self.solver == SolverMode::Apic;
+        let h = config.cell_size;
+        let columns = ((size.width as Real / h).ceil() as usize).max(2);
+        let rows = ((size.height as Real / h).ceil() as usize).max(2);
+        let u_lattice = Lattice {
+            width: columns + 1,
+            height: rows,
+            offset: Vec2 { x: 0., y: 0.5 },
+            cell_size: h,
+        };
+        let v_lattice = Lattice {
+            width: columns,
+            height: rows + 1,
+            offset: Vec2 { x: 0.5, y: 0. },
+            cell_size: h,
+        };
+
+        let particles = self.current_positions.len();
+        if self.affine.len() != particles {
+            self.affine = vec![[Vec2::default(); 2]; particles].into_boxed_slice();
+        }
+
+        // particles to grid
+        let splat = self
+            .current_positions
+            .par_iter()
+            .zip(self.current_velocities.par_iter())
+            .zip(self.affine.par_iter())
+            .fold(
+                || Splat::new(u_lattice.len(), v_lattice.len()),
+                |mut splat, ((pos, vel), affine)| {
+                    for (i, weight, _, at) in u_lattice.stencil(*pos) {
+                        let mut value = vel.x;
+                        if apic {
+                            value += affine[0].dot(at - *pos);
+                        }
+                        splat.u[i] += weight * value;
+                        splat.u_weight[i] += weight;
+                    }
+                    for (i, weight, _, at) in v_lattice.stencil(*pos) {
+                        let mut value = vel.y;
+                        if apic {
+                            value += affine[1].dot(at - *pos);
+                        }
+                        splat.v[i] += weight * value;
+                        splat.v_weight[i] += weight;
+                    }
+                    splat
+                },
+            )
+            .reduce(
+                || Splat::new(u_lattice.len(), v_lattice.len()),
+                Splat::merge,
+            );
+
+        let divide = |(value, weight): (&Real, &Real)| {
+            if *weight > 0. { value / weight } else { 0. }
+        };
+        let mut u: Vec<Real> = splat.u.iter().zip(&splat.u_weight).map(divide).collect();
+        let mut v: Vec<Real> = splat.v.iter().zip(&splat.v_weight).map(divide).collect();
+        u_lattice.extrapolate(&mut u, &splat.u_weight);
+        v_lattice.extrapolate(&mut v, &splat.v_weight);
+        let old_u = u.clone();
+        let old_v = v.clone();
+
+        let mut fluid = vec![false; columns * rows];
+        for pos in self.current_positions.iter() {
+            let x = ((pos.x / h).max(0.) as usize).min(columns - 1);
+            let y = ((pos.y / h).max(0.) as usize).min(rows - 1);
+            fluid[y * columns + x] = true;
+        }
+
+        // the outside forces get felt on each face, with the other component read off the grid
+        let (forces, time) = (&self.forces, self.time);
+        u.par_iter_mut().enumerate().for_each(|(i, u)| {
+            let at = u_lattice.position(i);
+            let velocity = Vec2 {
+                x: old_u[i],
+                y: v_lattice.interpolate(&old_v, at),
+            };
+            *u += forces.acceleration(at, velocity, time).x * delta;
+        });
+        v.par_iter_mut().enumerate().for_each(|(i, v)| {
+            let at = v_lattice.position(i);
+            let velocity = Vec2 {
+                x: u_lattice.interpolate(&old_u, at),
+                y: old_v[i],
+            };
+            *v += forces.acceleration(at, velocity, time).y * delta;
+        });
+        // nothing goes through the window edges
+        for y in 0..rows {
+            u[y * (columns + 1)] = 0.;
+            u[y * (columns + 1) + columns] = 0.;
+        }
+        for x in 0..columns {
+            v[x] = 0.;
+            v[rows * columns + x] = 0.;
+        }
+
+        project(
+            &mut u,
+            &mut v,
+            &fluid,
+            columns,
+            rows,
+            config.pressure_iterations,
+        );
+
+        // grid back to particles
+        let flip_ratio = config.flip_ratio;
+        self.next_velocities
+            .par_iter_mut()
+            .zip(self.next_positions.par_iter_mut())
+            .zip(self.affine.par_iter_mut())
+            .zip(self.current_positions.par_iter())
+            .zip(self.current_velocities.par_iter())
+            .for_each(|((((next_vel, next_pos), affine), pos), vel)| {
+                let pic = Vec2 {
+                    x: u_lattice.interpolate(&u, *pos),
+                    y: v_lattice.interpolate(&v, *pos),
+                };
+
+                *next_vel = if apic {
+                    let gradient = |lattice: &Lattice, values: &[Real]| {
+                        lattice
+                            .stencil(*pos)
+                            .iter()
+                            .fold(Vec2::default(), |total, (i, _, gradient, _)| {
+                                total + *gradient * values[*i]
+                            })
+                    };
+                    *affine = [gradient(&u_lattice, &u), gradient(&v_lattice, &v)];
+                    pic
+                } else {
+                    let change = Vec2 {
+                        x: pic.x - u_lattice.interpolate(&old_u, *pos),
+                        y: pic.y - v_lattice.interpolate(&old_v, *pos),
+                    };
+                    (*vel + change) * flip_ratio + pic * (1. - flip_ratio)
+                };
+                *next_pos = *pos + *next_vel * delta;
+            });
+
+        // the grid doesn't know about the bodies, they only get pushed about by collisions
+        self.integrate_bodies(&vec![(Vec2::default(), 0.); self.bodies.len()], delta);
+
+        self.diagnostics = StepDiagnostics {
+            solver: self.solver,
+            iterations: config.pressure_iterations,
+            ..Default::default()
+        };
+    }
+}
+
+/// makes the face velocities divergence free over the fluid cells. Empty cells are held at
+/// zero pressure, which is what gives the fluid a free surface, and the window edges are solid.
+fn project(
+    u: &mut [Real],
+    v: &mut [Real],
+    fluid: &[bool],
+    columns: usize,
+    rows: usize,
+    iterations: usize,
+) {
+    let u_index = |x: usize, y: usize| y * (columns + 1) + x;
+    let v_index = |x: usize, y: usize| y * columns + x;
+
+    let divergence: Vec<Real> = (0..columns * rows)
+        .map(|cell| {
+            let (x, y) = (cell % columns, cell / columns);
+            u[u_index(x + 1, y)] - u[u_index(x, y)] + v[v_index(x, y + 1)] - v[v_index(x, y)]
+        })
+        .collect();
+
+    // the pressure here has the time step and density folded in, so the face velocities just
+    // get its difference taken off them
+    let mut pressure = vec![0.; columns * rows];
+    for _ in 0..iterations {
+        for y in 0..rows {
+            for x in 0..columns {
+                let cell = y * columns + x;
+                if !fluid[cell] {
+                    continue;
+                }
+                let neighbours = [
+                    (x > 0).then(|| cell - 1),
+                    (x + 1 < columns).then(|| cell + 1),
+                    (y > 0).then(|| cell - columns),
+                    (y + 1 < rows).then(|| cell + columns),
+                ];
+                let (total, count) = neighbours
+                    .iter()
+                    .flatten()
+                    .fold((0., 0), |(total, count), n| {
+                        (total + pressure[*n], count + 1)
+                    });
+                pressure[cell] = (total - divergence[cell]) / count as Real;
+            }
+        }
+    }
+
+    for y in 0..rows {
+        for x in 1..columns {
+            let (left, right) = (y * columns + x - 1, y * columns + x);
+            if fluid[left] || fluid[right] {
+                u[u_index(x, y)] -= pressure[right] - pressure[left];
+            }
+        }
+    }
+    for y in 1..rows {
+        for x in 0..columns {
+            let (above, below) = ((y - 1) * columns + x, y * columns + x);
+            if fluid[above] || fluid[below] {
+                v[v_index(x, y)] -= pressure[below] - pressure[above];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(velocity: Vec2) -> FluidSim {
+        let positions: Vec<Vec2> = (0..400)
+            .map(|i| Vec2 {
+                x: 100. + (i % 20) as Real * 6.,
+                y: 100. + (i / 20) as Real * 6.,
+            })
+            .collect();
+        FluidSim::from_particles(positions, vec![velocity; 400])
+    }
+
+    #[test]
+    fn uniform_flow_survives_the_round_trip() {
+        let velocity = Vec2 { x: 50., y: 0. };
+        for solver in [SolverMode::Flip, SolverMode::Apic] {
+            let mut sim = block(velocity);
+            sim.set_solver(solver);
+            sim.flip.flip_ratio = 0.;
+            sim.update(1. / 60., winit::dpi::PhysicalSize::new(400, 400));
+
+            for vel in sim.current_velocities.iter() {
+                assert!((vel.x - velocity.x).abs() < 1., "{solver:?}: {vel:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn resting_pool_stays_put() {
+        let size = winit::dpi::PhysicalSize::new(240, 240);
+        let positions: Vec<Vec2> = (0..800)
+            .map(|i| Vec2 {
+                x: 3. + (i % 40) as Real * 6.,
+                y: 237. - (i / 40) as Real * 6.,
+            })
+            .collect();
+        let mut sim = FluidSim::from_particles(positions, vec![Vec2::default(); 800]);
+        sim.set_solver(SolverMode::Flip);
+
+        for _ in 0..60 {
+            sim.update(1. / 60., size);
+        }
+
+        let average_speed = sim
+            .current_velocities
+            .iter()
+            .map(|vel| vel.length())
+            .sum::<Real>()
+            / 800.;
+        // free fall for a second would be 400
+        assert!(average_speed < 40., "{average_speed}");
+    }
+}
@@ -0,0 +1,212 @@
+//! Velocities averaged onto a coarse grid, for drawing arrows and tracing streamlines through.
+
+use crate::fluid_sim::{grid::NeighbourGrid, real::Real, simulation::FieldView, vec2::Vec2};
+use rayon::prelude::*;
+
+/// one average velocity per cell over the window, or nothing for cells with no fluid in them
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VelocityGrid {
+    pub columns: usize,
+    pub rows: usize,
+    /// window pixels across and down each cell
+    pub cell: Vec2,
+    velocities: Vec<Option<Vec2>>,
+}
+
+impl VelocityGrid {
+    /// squares `cell` pixels wide, each one the average of the particles near its middle
+    /// weighted by how near
+    pub fn from_particles(
+        positions: &[Vec2],
+        velocities: &[Vec2],
+        cell: Real,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> Self {
+        let cell = cell.max(1.);
+        let columns = (size.width as Real / cell).ceil().max(1.) as usize;
+        let rows = (size.height as Real / cell).ceil().max(1.) as usize;
+        let grid = NeighbourGrid::new(cell, positions);
+        let mut sampled = Self {
+            columns,
+            rows,
+            cell: Vec2 { x: cell, y: cell },
+            velocities: Vec::new(),
+        };
+
+        sampled.velocities = (0..columns * rows)
+            .into_par_iter()
+            .map(|i| {
+                let centre = sampled.centre(i % columns, i / columns);
+                let (mut total, mut weights) = (Vec2::default(), 0.);
+                grid.for_each_near(centre, |j| {
+                    let offset = positions[j] - centre;
+                    // a tent a cell wide each way, like spreading particles onto a pic grid
+                    let weight =
+                        (1. - offset.x.abs() / cell).max(0.) * (1. - offset.y.abs() / cell).max(0.);
+                    total += velocities[j] * weight;
+                    weights += weight;
+                });
+                // less than about a particle's worth is just spray
+                (weights > 0.25).then(|| total / weights)
+            })
+            .collect();
+        sampled
+    }
+
+    /// a grid sim's velocities boxed down to cells about `cell` pixels across. The field's
+    /// stretched over the window, so the cells might not come out square.
+    pub fn from_field(field: &FieldView, cell: Real, size: winit::dpi::PhysicalSize<u32>) -> Self {
+        let width = size.width.max(1) as Real;
+        let height = size.height.max(1) as Real;
+        let columns = ((width / cell.max(1.)).round() as usize).clamp(1, field.columns);
+        let rows = ((height / cell.max(1.)).round() as usize).clamp(1, field.rows);
+
+        let velocities = (0..columns * rows)
+            .into_par_iter()
+            .map(|i| {
+                let (x, y) = (i % columns, i / columns);
+                let (mut total, mut count) = (Vec2::default(), 0);
+                for field_y in y * field.rows / rows..(y + 1) * field.rows / rows {
+                    for field_x in x * field.columns / columns..(x + 1) * field.columns / columns {
+                        if !field.is_solid(field_x, field_y) {
+                            total += field.velocity(field_x, field_y);
+                            count += 1;
+                        }
+                    }
+                }
+                (count > 0).then(|| total / count as Real)
+            })
+            .collect();
+
+        Self {
+            columns,
+            rows,
+            cell: Vec2 {
+                x: width / columns as Real,
+                y: height / rows as Real,
+            },
+            velocities,
+        }
+    }
+
+    /// the middle of a cell in window pixels
+    pub fn centre(&self, x: usize, y: usize) -> Vec2 {
+        Vec2 {
+            x: (x as Real + 0.5) * self.cell.x,
+            y: (y as Real + 0.5) * self.cell.y,
+        }
+    }
+
+    pub fn velocity(&self, x: usize, y: usize) -> Option<Vec2> {
+        self.velocities[y * self.columns + x]
+    }
+
+    /// the fastest any cell's going
+    pub fn max_speed(&self) -> Real {
+        self.velocities
+            .iter()
+            .flatten()
+            .fold(0., |max, velocity| velocity.length().max(max))
+    }
+
+    /// blended between the four nearest cell middles, empty cells count as still. Nothing
+    /// outside the window.
+    pub fn sample(&self, position: Vec2) -> Option<Vec2> {
+        let width = self.columns as Real * self.cell.x;
+        let height = self.rows as Real * self.cell.y;
+        if !(0. ..=width).contains(&position.x) || !(0. ..=height).contains(&position.y) {
+            return None;
+        }
+
+        let at = Vec2 {
+            x: (position.x / self.cell.x - 0.5).clamp(0., (self.columns - 1) as Real),
+            y: (position.y / self.cell.y - 0.5).clamp(0., (self.rows - 1) as Real),
+        };
+        let (x, y) = (at.x as usize, at.y as usize);
+        let (right, down) = ((x + 1).min(self.columns - 1), (y + 1).min(self.rows - 1));
+        let (tx, ty) = (at.x - x as Real, at.y - y as Real);
+        let get = |x, y| self.velocity(x, y).unwrap_or_default();
+        let top = get(x, y) * (1. - tx) + get(right, y) * tx;
+        let bottom = get(x, down) * (1. - tx) + get(right, down) * tx;
+        Some(top * (1. - ty) + bottom * ty)
+    }
+
+    /// follows the flow from `seed`, `step` pixels at a time with the midpoint rule. Stops
+    /// after `steps`, at the edge of the window or where the flow's nearly still.
+    pub fn streamline(&self, seed: Vec2, step: Real, steps: usize) -> Vec<Vec2> {
+        let slowest = self.max_speed() * 0.02;
+        let direction = |position: Vec2| {
+            let velocity = self.sample(position)?;
+            let speed = velocity.length();
+            (speed > slowest && speed > 0.).then(|| velocity / speed)
+        };
+
+        let mut line = vec![seed];
+        let mut position = seed;
+        for _ in 0..steps {
+            let Some(first) = direction(position) else {
+                break;
+            };
+            let Some(middle) = direction(position + first * (step / 2.)) else {
+                break;
+            };
+            position += middle * step;
+            if self.sample(position).is_none() {
+                break;
+            }
+            line.push(position);
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: winit::dpi::PhysicalSize<u32> = winit::dpi::PhysicalSize::new(200, 100);
+
+    #[test]
+    fn particles_average_onto_the_cells_near_them() {
+        let positions = [Vec2 { x: 25., y: 25. }, Vec2 { x: 25., y: 25. }];
+        let velocities = [Vec2 { x: 10., y: 0. }, Vec2 { x: 30., y: 20. }];
+        let grid = VelocityGrid::from_particles(&positions, &velocities, 50., SIZE);
+        assert_eq!((grid.columns, grid.rows), (4, 2));
+        assert_eq!(grid.velocity(0, 0), Some(Vec2 { x: 20., y: 10. }));
+        assert_eq!(grid.velocity(3, 1), None);
+        assert_eq!(grid.sample(Vec2 { x: -1., y: 5. }), None);
+    }
+
+    #[test]
+    fn streamlines_follow_the_flow_round() {
+        // a solid spin about the middle of the window
+        let centre = Vec2 { x: 100., y: 50. };
+        let positions: Vec<Vec2> = (0..40)
+            .flat_map(|x| {
+                (0..20).map(move |y| Vec2 {
+                    x: x as Real * 5. + 2.5,
+                    y: y as Real * 5. + 2.5,
+                })
+            })
+            .collect();
+        let velocities: Vec<Vec2> = positions
+            .iter()
+            .map(|p| {
+                let offset = *p - centre;
+                Vec2 {
+                    x: -offset.y,
+                    y: offset.x,
+                }
+            })
+            .collect();
+        let grid = VelocityGrid::from_particles(&positions, &velocities, 10., SIZE);
+
+        let seed = Vec2 { x: 130., y: 50. };
+        let line = grid.streamline(seed, 2., 200);
+        assert!(line.len() > 50);
+        for point in &line {
+            let drift = ((*point - centre).length() - 30.).abs();
+            assert!(drift < 2., "{drift}");
+        }
+    }
+}
@@ -0,0 +1,315 @@
+//! External forces on the particles: gravity, things that pull and push, swirls, noise and
+//! drag.
+//!
+//! Everything here hands back an acceleration rather than a force, since every particle
+//! weighs the same. The sim adds up whatever fields it's been given for each particle every
+//! step, so they can be stacked and swapped about while it runs.
+
+use crate::fluid_sim::{
+    real::{Real, consts::TAU},
+    vec2::Vec2,
+};
+use std::sync::Arc;
+
+/// the old fixed gravity, in pixels per second squared down the screen
+pub const GRAVITY_NUMBER: Real = 400.;
+
+/// anything that pushes on the particles from outside the fluid
+pub trait ForceField: std::fmt::Debug + Send + Sync {
+    /// acceleration on a particle at `position` going at `velocity`, `time` is simulated
+    /// seconds
+    fn acceleration(&self, position: Vec2, velocity: Vec2, time: Real) -> Vec2;
+}
+
+/// handed back when a field gets added, so that one can be taken out again later
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ForceId(u64);
+
+/// the fields a sim has got, in the order they were added
+#[derive(Clone, Debug, Default)]
+pub struct Forces {
+    fields: Vec<(ForceId, Arc<dyn ForceField>)>,
+    next_id: u64,
+}
+
+impl Forces {
+    pub fn add(&mut self, field: impl ForceField + 'static) -> ForceId {
+        let id = ForceId(self.next_id);
+        self.next_id += 1;
+        self.fields.push((id, Arc::new(field)));
+        id
+    }
+
+    /// gives back whether there was anything there to take out
+    pub fn remove(&mut self, id: ForceId) -> bool {
+        let before = self.fields.len();
+        self.fields.retain(|(field_id, _)| *field_id != id);
+        self.fields.len() != before
+    }
+
+    /// everything added together
+    pub fn acceleration(&self, position: Vec2, velocity: Vec2, time: Real) -> Vec2 {
+        self.fields
+            .iter()
+            .fold(Vec2::default(), |total, (_, field)| {
+                total + field.acceleration(position, velocity, time)
+            })
+    }
+}
+
+/// the same pull everywhere
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Gravity(pub Vec2);
+
+impl Default for Gravity {
+    fn default() -> Self {
+        Gravity(Vec2 {
+            x: 0.,
+            y: GRAVITY_NUMBER,
+        })
+    }
+}
+
+impl ForceField for Gravity {
+    fn acceleration(&self, _position: Vec2, _velocity: Vec2, _time: Real) -> Vec2 {
+        self.0
+    }
+}
+
+/// pulls everything within `radius` toward a point, or pushes it away if `strength` is
+/// negative. Strongest at the middle, fading to nothing at the edge.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Point {
+    pub position: Vec2,
+    pub radius: Real,
+    pub strength: Real,
+}
+
+impl Point {
+    pub fn attractor(position: Vec2, radius: Real, strength: Real) -> Self {
+        Self {
+            position,
+            radius,
+            strength: strength.abs(),
+        }
+    }
+
+    pub fn repulsor(position: Vec2, radius: Real, strength: Real) -> Self {
+        Self {
+            position,
+            radius,
+            strength: -strength.abs(),
+        }
+    }
+}
+
+impl ForceField for Point {
+    fn acceleration(&self, position: Vec2, _velocity: Vec2, _time: Real) -> Vec2 {
+        let offset = self.position - position;
+        let distance = offset.length();
+        if distance >= self.radius || distance < 1e-6 {
+            return Vec2::default();
+        }
+        offset / distance * (self.strength * falloff(distance, self.radius))
+    }
+}
+
+/// spins everything within `radius` around a point, anticlockwise on screen for a positive
+/// `strength`. Nothing gets pulled in, it only pushes along the circles.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Vortex {
+    pub position: Vec2,
+    pub radius: Real,
+    pub strength: Real,
+}
+
+impl ForceField for Vortex {
+    fn acceleration(&self, position: Vec2, _velocity: Vec2, _time: Real) -> Vec2 {
+        let offset = position - self.position;
+        let distance = offset.length();
+        if distance >= self.radius || distance < 1e-6 {
+            return Vec2::default();
+        }
+        // y points down the screen, so this way round is anticlockwise as you look at it
+        let around = Vec2 {
+            x: offset.y,
+            y: -offset.x,
+        } / distance;
+        around * (self.strength * falloff(distance, self.radius))
+    }
+}
+
+/// swirly noise that drifts over time. It's the curl of a handful of sine waves, so it stirs
+/// without bunching the particles up anywhere.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Turbulence {
+    pub strength: Real,
+    /// roughly how big the swirls are, in pixels
+    pub scale: Real,
+    /// how fast the pattern changes, in radians per second
+    pub speed: Real,
+    /// picks the pattern, different seeds give different swirls
+    pub seed: u32,
+}
+
+impl Default for Turbulence {
+    fn default() -> Self {
+        Self {
+            strength: 300.,
+            scale: 120.,
+            speed: 1.,
+            seed: 0,
+        }
+    }
+}
+
+/// how many waves get added up, more looks less regular but costs more
+const TURBULENCE_WAVES: u32 = 6;
+
+impl ForceField for Turbulence {
+    fn acceleration(&self, position: Vec2, _velocity: Vec2, time: Real) -> Vec2 {
+        // potential = sum of sin(k . p + phase), the curl of that is (d/dy, -d/dx)
+        (0..TURBULENCE_WAVES).fold(Vec2::default(), |total, wave| {
+            let hash = |salt: u32| unit_hash(self.seed, wave * 3 + salt);
+            let angle = hash(0) * TAU;
+            // each wave a bit smaller than the last
+            let octave = 1. + wave as Real * 0.5;
+            let k = Vec2 {
+                x: angle.cos(),
+                y: angle.sin(),
+            } * (octave / self.scale);
+            let phase = hash(1) * TAU + time * self.speed * (0.5 + hash(2));
+            let slope = (k.dot(position) + phase).cos() / octave;
+            total
+                + Vec2 {
+                    x: k.y * slope,
+                    y: -k.x * slope,
+                } * (self.strength * self.scale / TURBULENCE_WAVES as Real)
+        })
+    }
+}
+
+/// drags everything within `radius` toward going at `velocity`, like a spoon pulled through
+/// the fluid
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Stir {
+    pub position: Vec2,
+    pub radius: Real,
+    pub velocity: Vec2,
+    /// how many times a second things catch up with `velocity`, in the middle
+    pub rate: Real,
+}
+
+impl ForceField for Stir {
+    fn acceleration(&self, position: Vec2, velocity: Vec2, _time: Real) -> Vec2 {
+        let distance = (position - self.position).length();
+        if distance >= self.radius {
+            return Vec2::default();
+        }
+        (self.velocity - velocity) * (self.rate * falloff(distance, self.radius))
+    }
+}
+
+/// slows everything down in proportion to how fast it's going
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Drag {
+    /// per second, 1 takes off about a second's worth of speed every second
+    pub coefficient: Real,
+}
+
+impl ForceField for Drag {
+    fn acceleration(&self, _position: Vec2, velocity: Vec2, _time: Real) -> Vec2 {
+        -velocity * self.coefficient
+    }
+}
+
+/// 1 in the middle down to 0 at the edge, smooth at both ends
+pub(crate) fn falloff(distance: Real, radius: Real) -> Real {
+    let t = 1. - distance / radius;
+    t * t * (3. - 2. * t)
+}
+
+/// a number in 0..1 that only depends on the two inputs
+fn unit_hash(seed: u32, salt: u32) -> Real {
+    let mut x = seed.wrapping_mul(0x9e37_79b9) ^ salt.wrapping_mul(0x85eb_ca6b);
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x as Real / u32::MAX as Real
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_can_come_and_go() {
+        let mut forces = Forces::default();
+        let gravity = forces.add(Gravity::default());
+        let drag = forces.add(Drag { coefficient: 2. });
+
+        let velocity = Vec2 { x: 10., y: 0. };
+        let both = forces.acceleration(Vec2::default(), velocity, 0.);
+        assert_eq!(
+            both,
+            Vec2 {
+                x: -20.,
+                y: GRAVITY_NUMBER
+            }
+        );
+
+        assert!(forces.remove(gravity));
+        assert!(!forces.remove(gravity));
+        assert_eq!(
+            forces.acceleration(Vec2::default(), velocity, 0.),
+            Vec2 { x: -20., y: 0. }
+        );
+        assert!(forces.remove(drag));
+        assert_eq!(
+            forces.acceleration(Vec2::default(), velocity, 0.),
+            Vec2::default()
+        );
+    }
+
+    #[test]
+    fn points_pull_and_push_and_vortices_only_spin() {
+        let centre = Vec2 { x: 100., y: 100. };
+        let particle = Vec2 { x: 130., y: 100. };
+
+        let pull = Point::attractor(centre, 50., 100.).acceleration(particle, Vec2::default(), 0.);
+        assert!(pull.x < 0. && pull.y == 0.);
+        let push = Point::repulsor(centre, 50., 100.).acceleration(particle, Vec2::default(), 0.);
+        assert_eq!(push, -pull);
+        let outside =
+            Point::attractor(centre, 20., 100.).acceleration(particle, Vec2::default(), 0.);
+        assert_eq!(outside, Vec2::default());
+
+        let vortex = Vortex {
+            position: centre,
+            radius: 50.,
+            strength: 100.,
+        };
+        let spin = vortex.acceleration(particle, Vec2::default(), 0.);
+        assert!(spin.dot(particle - centre).abs() < 1e-4);
+        // right of the middle going anticlockwise on screen is up, which is -y
+        assert!(spin.y < 0.);
+    }
+
+    #[test]
+    fn turbulence_has_no_divergence() {
+        let noise = Turbulence::default();
+        let h = 0.01;
+        for i in 0..20 {
+            let p = Vec2 {
+                x: 37. * i as Real,
+                y: 23. * i as Real,
+            };
+            let at = |dx: Real, dy: Real| noise.acceleration(p + Vec2 { x: dx, y: dy }, p, 1.5);
+            let divergence = (at(h, 0.).x - at(-h, 0.).x + at(0., h).y - at(0., -h).y) / (2. * h);
+            assert!(divergence.abs() < 0.5, "{divergence}");
+            assert!(at(0., 0.).length() > 0.);
+        }
+    }
+}
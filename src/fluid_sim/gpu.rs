@@ -0,0 +1,849 @@
+//! Weakly compressible SPH run as wgpu compute shaders, with the particles living on the gpu.
+//!
+//! Each substep counting sorts the particles into cells a smoothing radius across, works out
+//! density and pressure, then the pressure and viscosity forces, then moves everything along.
+//! The positions stay in a storage buffer the renderer draws straight out of, and the colours
+//! get worked out next to them, so nothing comes back to the cpu unless something like the
+//! outline or the flow lines asks for it.
+
+use std::cell::Cell;
+
+use crate::fluid_sim::{
+    contour::{self, Contour, ContourConfig},
+    flow::VelocityGrid,
+    real::{Real, consts::PI, to_f32},
+    simulation::{Interaction, Parameter, Quantity, Simulation, Tool, View},
+    sph::WcsphConfig,
+    vec2::Vec2,
+};
+
+/// threads per workgroup for the per particle and per cell passes, has to match gpu.wgsl
+const WORKGROUP: u32 = 64;
+
+/// how many cells cover the window, none of them less than a smoothing radius across
+fn cells(config: &WcsphConfig, size: winit::dpi::PhysicalSize<u32>) -> (u32, u32) {
+    let cells = |pixels: u32| ((pixels as Real / config.smoothing_radius).ceil() as u32).max(1);
+    (cells(size.width), cells(size.height))
+}
+
+/// what gpu.wgsl gets every step, laid out the same
+#[repr(C)]
+#[derive(bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, Default)]
+struct Params {
+    size: [f32; 2],
+    gravity: [f32; 2],
+    tool_position: [f32; 2],
+    tool_velocity: [f32; 2],
+    count: u32,
+    columns: u32,
+    rows: u32,
+    tool: u32,
+    radius: f32,
+    rest_density: f32,
+    stiffness: f32,
+    viscosity: f32,
+    delta: f32,
+    poly6: f32,
+    spiky: f32,
+    laplacian: f32,
+    tool_radius: f32,
+    tool_strength: f32,
+    restitution: f32,
+    colour_by: u32,
+}
+
+#[derive(Debug)]
+struct Passes {
+    clear: wgpu::ComputePipeline,
+    count: wgpu::ComputePipeline,
+    scan: wgpu::ComputePipeline,
+    scatter: wgpu::ComputePipeline,
+    density: wgpu::ComputePipeline,
+    forces: wgpu::ComputePipeline,
+    integrate: wgpu::ComputePipeline,
+    colour: wgpu::ComputePipeline,
+}
+
+/// a gpu of its own, for when there's no window to share one with. Falls back to a software
+/// adapter, and gives up if there isn't even that.
+pub fn request_device(fallback: bool) -> Option<(wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(),
+        ..Default::default()
+    });
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::HighPerformance,
+        force_fallback_adapter: fallback,
+        compatible_surface: None,
+    }))
+    .ok()?;
+    pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+        label: Some("sph device"),
+        ..Default::default()
+    }))
+    .ok()
+}
+
+#[derive(Debug)]
+pub struct GpuSim {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pub config: WcsphConfig,
+    gravity: Vec2,
+    tool: Option<Interaction>,
+    /// what it started from, for resetting
+    initial_positions: Vec<Vec2>,
+    initial_velocities: Vec<Vec2>,
+    count: usize,
+    /// the cell grid the buffers are sized for
+    columns: u32,
+    rows: u32,
+    params: wgpu::Buffer,
+    positions: wgpu::Buffer,
+    velocities: wgpu::Buffer,
+    counts: wgpu::Buffer,
+    slots: wgpu::Buffer,
+    starts: wgpu::Buffer,
+    sorted: wgpu::Buffer,
+    fluid: wgpu::Buffer,
+    accelerations: wgpu::Buffer,
+    colours: wgpu::Buffer,
+    colour_range: wgpu::Buffer,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    /// just what the colour pass reads and writes, it doesn't share the steps' layout
+    colour_bind_group: wgpu::BindGroup,
+    passes: Passes,
+    steps: usize,
+    substeps: usize,
+    /// how many times something's had to wait for a buffer to come back off the gpu
+    readbacks: Cell<usize>,
+}
+
+impl GpuSim {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: WcsphConfig,
+        positions: Vec<Vec2>,
+        velocities: Vec<Vec2>,
+        gravity: Vec2,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> Self {
+        let count = positions.len();
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("sph compute"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("./gpu.wgsl").into()),
+        });
+        let entries: Vec<wgpu::BindGroupLayoutEntry> = (0..9)
+            .map(|binding| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: if binding == 0 {
+                        wgpu::BufferBindingType::Uniform
+                    } else {
+                        wgpu::BufferBindingType::Storage { read_only: false }
+                    },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            })
+            .collect();
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("sph bind group layout"),
+            entries: &entries,
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("sph pipeline layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: Some(entry_point),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            })
+        };
+        let passes = Passes {
+            clear: pipeline("clear_cells"),
+            count: pipeline("count_cells"),
+            scan: pipeline("scan_cells"),
+            scatter: pipeline("scatter_cells"),
+            density: pipeline("density"),
+            forces: pipeline("forces"),
+            integrate: pipeline("integrate"),
+            // left to work out its own layout, with every binding the steps use there'd be one
+            // storage buffer too many for what a gpu has to allow
+            colour: device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("colour"),
+                layout: None,
+                module: &shader,
+                entry_point: Some("colour"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            }),
+        };
+
+        let pairs = (count * std::mem::size_of::<[f32; 2]>()) as u64;
+        let vectors = |label, contents: &[Vec2]| {
+            let buffer = storage(device, label, pairs);
+            queue.write_buffer(&buffer, 0, bytemuck::cast_slice(&gpu_vectors(contents)));
+            buffer
+        };
+        let (columns, rows) = cells(&config, size);
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sph params"),
+            size: std::mem::size_of::<Params>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let positions_buffer = vectors("sph positions", &positions);
+        let velocities_buffer = vectors("sph velocities", &velocities);
+        let (counts, starts) = cell_buffers(device, columns, rows);
+        let slots = storage(device, "sph slots", pairs);
+        let sorted = storage(device, "sph sorted", (count * 4) as u64);
+        let fluid = storage(device, "sph density and pressure", pairs);
+        let accelerations = storage(device, "sph accelerations", pairs);
+        let colours = storage(device, "sph colours", (count * 4) as u64);
+        let colour_range = storage(device, "sph colour range", 16);
+        let colour_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("sph colour bind group"),
+            layout: &passes.colour.get_bind_group_layout(0),
+            entries: &[
+                (0, &params),
+                (2, &velocities_buffer),
+                (7, &fluid),
+                (9, &colours),
+                (10, &colour_range),
+            ]
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding,
+                resource: buffer.as_entire_binding(),
+            }),
+        });
+        let bind_group = bind_group(
+            device,
+            &layout,
+            [
+                &params,
+                &positions_buffer,
+                &velocities_buffer,
+                &counts,
+                &slots,
+                &starts,
+                &sorted,
+                &fluid,
+                &accelerations,
+            ],
+        );
+
+        let sim = Self {
+            device: device.clone(),
+            queue: queue.clone(),
+            config,
+            gravity,
+            tool: None,
+            initial_positions: positions,
+            initial_velocities: velocities,
+            count,
+            columns,
+            rows,
+            params,
+            positions: positions_buffer,
+            velocities: velocities_buffer,
+            counts,
+            slots,
+            starts,
+            sorted,
+            fluid,
+            accelerations,
+            colours,
+            colour_range,
+            layout,
+            bind_group,
+            colour_bind_group,
+            passes,
+            steps: 0,
+            substeps: 0,
+            readbacks: Cell::new(0),
+        };
+        // so colouring before the first step still has the particle count and rest density
+        queue.write_buffer(&sim.params, 0, bytemuck::bytes_of(&sim.params(size, 0.)));
+        sim
+    }
+
+    /// the storage buffer of `vec2<f32>` positions, in window pixels, that the renderer can
+    /// draw from directly
+    pub fn positions(&self) -> &wgpu::Buffer {
+        &self.positions
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// the storage buffer of one `f32` per particle that `colour` fills in
+    pub fn colours(&self) -> &wgpu::Buffer {
+        &self.colours
+    }
+
+    /// the min and max of `colours` then how many there are and a 1, as four `f32`s, ready to
+    /// be copied into the renderer's colouring uniform
+    pub fn colour_range(&self) -> &wgpu::Buffer {
+        &self.colour_range
+    }
+
+    pub fn readbacks(&self) -> usize {
+        self.readbacks.get()
+    }
+
+    /// works `quantity` out for every particle into `colours` and its range into
+    /// `colour_range`, without waiting on anything or bringing it back
+    pub fn colour(&self, quantity: Quantity) {
+        if self.count == 0 {
+            return;
+        }
+        let colour_by: u32 = match quantity {
+            Quantity::Speed => 0,
+            Quantity::Density => 1,
+            Quantity::Pressure => 2,
+            Quantity::Id => 3,
+        };
+        self.queue.write_buffer(
+            &self.params,
+            std::mem::offset_of!(Params, colour_by) as u64,
+            bytemuck::bytes_of(&colour_by),
+        );
+        self.queue.write_buffer(
+            &self.colour_range,
+            0,
+            bytemuck::cast_slice(&[f32::INFINITY, 0., self.count as f32, 1.]),
+        );
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("sph colour"),
+            });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("sph colour"),
+                timestamp_writes: None,
+            });
+            pass.set_bind_group(0, &self.colour_bind_group, &[]);
+            pass.set_pipeline(&self.passes.colour);
+            pass.dispatch_workgroups((self.count as u32).div_ceil(WORKGROUP), 1, 1);
+        }
+        self.queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    /// copies the positions back off the gpu, waiting until they get here
+    pub fn read_positions(&self) -> Vec<Vec2> {
+        self.read(&self.positions)
+    }
+
+    pub fn read_velocities(&self) -> Vec<Vec2> {
+        self.read(&self.velocities)
+    }
+
+    /// any of the buffers with a `vec2<f32>` per particle
+    fn read(&self, buffer: &wgpu::Buffer) -> Vec<Vec2> {
+        self.read_raw::<[f32; 2]>(buffer, self.count)
+            .into_iter()
+            .map(Vec2::from_f32)
+            .collect()
+    }
+
+    /// the first `len` things in `buffer`, waiting until they get here
+    fn read_raw<T: bytemuck::Pod + Default>(&self, buffer: &wgpu::Buffer, len: usize) -> Vec<T> {
+        let bytes = (len * std::mem::size_of::<T>()) as u64;
+        if bytes == 0 {
+            return Vec::new();
+        }
+        self.readbacks.set(self.readbacks.get() + 1);
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sph readback"),
+            size: bytes,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("sph readback"),
+            });
+        encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, bytes);
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = staging.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| {});
+        if let Err(error) = self.device.poll(wgpu::PollType::Wait) {
+            eprintln!("couldn't read the particles back: {error}");
+            return vec![T::default(); len];
+        }
+        let values = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        staging.unmap();
+        values
+    }
+
+    /// remakes the cell buffers if the window or the smoothing radius changed how many cells
+    /// there are
+    fn fit(&mut self, size: winit::dpi::PhysicalSize<u32>) {
+        let (columns, rows) = cells(&self.config, size);
+        if (columns, rows) == (self.columns, self.rows) {
+            return;
+        }
+        (self.counts, self.starts) = cell_buffers(&self.device, columns, rows);
+        (self.columns, self.rows) = (columns, rows);
+        self.bind_group = bind_group(
+            &self.device,
+            &self.layout,
+            [
+                &self.params,
+                &self.positions,
+                &self.velocities,
+                &self.counts,
+                &self.slots,
+                &self.starts,
+                &self.sorted,
+                &self.fluid,
+                &self.accelerations,
+            ],
+        );
+    }
+
+    fn params(&self, size: winit::dpi::PhysicalSize<u32>, delta: Real) -> Params {
+        let radius = self.config.smoothing_radius;
+        let (tool, tool_strength) = match self.tool {
+            None => (0, 0.),
+            Some(Interaction {
+                tool: Tool::Attract,
+                strength,
+                ..
+            }) => (1, strength.abs()),
+            Some(Interaction {
+                tool: Tool::Repel,
+                strength,
+                ..
+            }) => (1, -strength.abs()),
+            Some(Interaction {
+                tool: Tool::Stir,
+                strength,
+                ..
+            }) => (2, strength),
+        };
+        let held = self.tool.unwrap_or(Interaction {
+            tool: Tool::Attract,
+            position: Vec2::default(),
+            velocity: Vec2::default(),
+            radius: 0.,
+            strength: 0.,
+        });
+        Params {
+            size: [size.width as f32, size.height as f32],
+            gravity: self.gravity.to_f32(),
+            tool_position: held.position.to_f32(),
+            tool_velocity: held.velocity.to_f32(),
+            count: self.count as u32,
+            columns: self.columns,
+            rows: self.rows,
+            tool,
+            radius: to_f32(radius),
+            rest_density: to_f32(self.config.rest_density()),
+            stiffness: to_f32(self.config.stiffness),
+            viscosity: to_f32(self.config.viscosity),
+            delta: to_f32(delta),
+            poly6: to_f32(4. / (PI * radius.powi(8))),
+            spiky: to_f32(-30. / (PI * radius.powi(5))),
+            laplacian: to_f32(self.config.laplacian()),
+            tool_radius: to_f32(held.radius),
+            tool_strength: to_f32(tool_strength),
+            restitution: to_f32(self.config.decay_factor),
+            colour_by: 0,
+        }
+    }
+}
+
+impl Simulation for GpuSim {
+    fn step(&mut self, delta: Real, size: winit::dpi::PhysicalSize<u32>) {
+        if delta <= 0. || self.count == 0 {
+            return;
+        }
+        self.fit(size);
+        let substeps = self.config.substeps(delta);
+        let params = self.params(size, delta / substeps as Real);
+        self.queue
+            .write_buffer(&self.params, 0, bytemuck::bytes_of(&params));
+
+        let particles = (self.count as u32).div_ceil(WORKGROUP);
+        let cells = (self.columns * self.rows).div_ceil(WORKGROUP);
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("sph step"),
+            });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("sph step"),
+                timestamp_writes: None,
+            });
+            pass.set_bind_group(0, &self.bind_group, &[]);
+            for _ in 0..substeps {
+                for (pipeline, workgroups) in [
+                    (&self.passes.clear, cells),
+                    (&self.passes.count, particles),
+                    // the whole prefix sum is one workgroup of gpu.wgsl's SCAN threads
+                    (&self.passes.scan, 1),
+                    (&self.passes.scatter, particles),
+                    (&self.passes.density, particles),
+                    (&self.passes.forces, particles),
+                    (&self.passes.integrate, particles),
+                ] {
+                    pass.set_pipeline(pipeline);
+                    pass.dispatch_workgroups(workgroups, 1, 1);
+                }
+            }
+        }
+        self.queue.submit(std::iter::once(encoder.finish()));
+        self.steps += 1;
+        self.substeps = substeps;
+    }
+
+    fn view(&self) -> View<'_> {
+        View::Resident(self)
+    }
+
+    fn diagnostics(&self) -> String {
+        format!(
+            "gpu sph, {} particles, step {} in {} substeps, {} readbacks",
+            self.count,
+            self.steps,
+            self.substeps,
+            self.readbacks()
+        )
+    }
+
+    fn reset(&mut self) {
+        self.queue.write_buffer(
+            &self.positions,
+            0,
+            bytemuck::cast_slice(&gpu_vectors(&self.initial_positions)),
+        );
+        self.queue.write_buffer(
+            &self.velocities,
+            0,
+            bytemuck::cast_slice(&gpu_vectors(&self.initial_velocities)),
+        );
+        self.steps = 0;
+        self.substeps = 0;
+    }
+
+    fn snapshot(&self) -> Box<dyn Simulation> {
+        let mut copy = GpuSim::new(
+            &self.device,
+            &self.queue,
+            self.config,
+            self.initial_positions.clone(),
+            self.initial_velocities.clone(),
+            self.gravity,
+            winit::dpi::PhysicalSize::new(1, 1),
+        );
+        copy.tool = self.tool;
+        copy.steps = self.steps;
+        copy.substeps = self.substeps;
+
+        let bytes = (self.count * std::mem::size_of::<[f32; 2]>()) as u64;
+        if bytes > 0 {
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("sph snapshot"),
+                });
+            encoder.copy_buffer_to_buffer(&self.positions, 0, &copy.positions, 0, bytes);
+            encoder.copy_buffer_to_buffer(&self.velocities, 0, &copy.velocities, 0, bytes);
+            self.queue.submit(std::iter::once(encoder.finish()));
+        }
+        Box::new(copy)
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        let mut parameters = vec![
+            Parameter::new("gravity x", self.gravity.x, -1000.0..=1000.),
+            Parameter::new("gravity y", self.gravity.y, -1000.0..=1000.),
+        ];
+        parameters.extend(self.config.parameters());
+        parameters
+    }
+
+    fn set_parameter(&mut self, name: &str, value: Real) {
+        match name {
+            "gravity x" => self.gravity.x = value,
+            "gravity y" => self.gravity.y = value,
+            _ => self.config.set_parameter(name, value),
+        }
+    }
+
+    fn interact(&mut self, interaction: Option<Interaction>, _size: winit::dpi::PhysicalSize<u32>) {
+        self.tool = interaction;
+    }
+
+    /// these all have to come back off the gpu and cost a wait, the renderer uses `colour`
+    /// instead
+    fn scalars(&self, quantity: Quantity, _size: winit::dpi::PhysicalSize<u32>) -> Vec<Real> {
+        match quantity {
+            Quantity::Speed => self
+                .read_velocities()
+                .into_iter()
+                .map(|velocity| velocity.length())
+                .collect(),
+            Quantity::Density | Quantity::Pressure => {
+                let rest_density = self.config.rest_density();
+                self.read(&self.fluid)
+                    .into_iter()
+                    .map(|fluid| match quantity {
+                        Quantity::Density => fluid.x / rest_density,
+                        _ => fluid.y / (self.config.stiffness * rest_density),
+                    })
+                    .collect()
+            }
+            // the cell sort only sorts indices, every particle stays in its own slot
+            Quantity::Id => (0..self.count).map(|i| i as Real).collect(),
+        }
+    }
+
+    fn rest_spacing(&self, _size: winit::dpi::PhysicalSize<u32>) -> Real {
+        self.config.particle_spacing
+    }
+
+    fn contours(
+        &self,
+        config: &ContourConfig,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> Vec<Contour> {
+        contour::extract(&self.read_positions(), config, size)
+    }
+
+    fn velocity_grid(&self, cell: Real, size: winit::dpi::PhysicalSize<u32>) -> VelocityGrid {
+        VelocityGrid::from_particles(&self.read_positions(), &self.read_velocities(), cell, size)
+    }
+}
+
+const STORAGE: wgpu::BufferUsages = wgpu::BufferUsages::STORAGE
+    .union(wgpu::BufferUsages::COPY_SRC)
+    .union(wgpu::BufferUsages::COPY_DST);
+
+/// what goes in the `vec2<f32>` buffers, whatever the sims are running in
+fn gpu_vectors(vectors: &[Vec2]) -> Vec<[f32; 2]> {
+    vectors.iter().map(|vector| vector.to_f32()).collect()
+}
+
+/// a storage buffer that's never empty, since those can't be bound
+fn storage(device: &wgpu::Device, label: &str, bytes: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size: bytes.max(16),
+        usage: STORAGE,
+        mapped_at_creation: false,
+    })
+}
+
+/// the per cell counts and where each cell starts, with one more start on the end
+fn cell_buffers(device: &wgpu::Device, columns: u32, rows: u32) -> (wgpu::Buffer, wgpu::Buffer) {
+    let cells = (columns * rows) as u64;
+    (
+        storage(device, "sph cell counts", cells * 4),
+        storage(device, "sph cell starts", (cells + 1) * 4),
+    )
+}
+
+/// everything in gpu.wgsl's bindings order
+fn bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    buffers: [&wgpu::Buffer; 9],
+) -> wgpu::BindGroup {
+    let entries: Vec<wgpu::BindGroupEntry> = buffers
+        .into_iter()
+        .enumerate()
+        .map(|(binding, buffer)| wgpu::BindGroupEntry {
+            binding: binding as u32,
+            resource: buffer.as_entire_binding(),
+        })
+        .collect();
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("sph bind group"),
+        layout,
+        entries: &entries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fluid_sim::sph::Wcsph;
+
+    #[test]
+    fn matches_the_cpu_on_the_software_adapter() {
+        let Some((device, queue)) = request_device(true) else {
+            eprintln!("no fallback adapter, skipping");
+            return;
+        };
+        let size = winit::dpi::PhysicalSize::new(200, 150);
+        let gravity = Vec2 { x: 0., y: 400. };
+        // a block a bit squashed compared to rest spacing, dropped near the floor, with the
+        // odd particle nudged so it isn't a perfect lattice
+        let positions: Vec<Vec2> = (0..300)
+            .map(|i| Vec2 {
+                x: 30. + (i % 20) as Real * 7. + (i % 7) as Real * 0.3,
+                y: 60. + (i / 20) as Real * 6.,
+            })
+            .collect();
+        let velocities: Vec<Vec2> = (0..300)
+            .map(|i| Vec2 {
+                x: (i % 5) as Real * 10. - 20.,
+                y: 0.,
+            })
+            .collect();
+
+        let config = WcsphConfig::default();
+        let mut gpu = GpuSim::new(
+            &device,
+            &queue,
+            config,
+            positions.clone(),
+            velocities.clone(),
+            gravity,
+            size,
+        );
+        let mut cpu = Wcsph::new(config, positions.clone(), velocities, gravity);
+        // something pulling on one side, so the tool gets checked too
+        let pull = Some(Interaction {
+            tool: Tool::Attract,
+            position: Vec2 { x: 60., y: 80. },
+            velocity: Vec2::default(),
+            radius: 40.,
+            strength: 2000.,
+        });
+        gpu.interact(pull, size);
+        cpu.interact(pull, size);
+        for _ in 0..5 {
+            gpu.step(1. / 60., size);
+            cpu.step(1. / 60., size);
+        }
+
+        let View::Particles(cpu) = cpu.view() else {
+            panic!("wcsph isn't drawn as particles");
+        };
+        let gpu = gpu.read_positions();
+        assert_eq!(gpu.len(), cpu.fluid.len());
+        let worst = gpu
+            .iter()
+            .zip(cpu.fluid)
+            .map(|(gpu, cpu)| (*gpu - *cpu).length())
+            .fold(0., Real::max);
+        assert!(worst < 1e-2, "gpu and cpu ended up {worst} pixels apart");
+        // and something actually happened
+        let moved = positions
+            .iter()
+            .zip(cpu.fluid)
+            .map(|(start, end)| (*end - *start).length())
+            .fold(0., Real::max);
+        assert!(moved > 1., "only moved {moved} pixels");
+    }
+
+    #[test]
+    fn snapshots_and_resets_stay_on_the_gpu() {
+        let Some((device, queue)) = request_device(true) else {
+            eprintln!("no fallback adapter, skipping");
+            return;
+        };
+        let size = winit::dpi::PhysicalSize::new(200, 150);
+        let start: Vec<Vec2> = (0..50)
+            .map(|i| Vec2 {
+                x: 50. + (i % 10) as Real * 8.,
+                y: 50. + (i / 10) as Real * 8.,
+            })
+            .collect();
+        let mut sim = GpuSim::new(
+            &device,
+            &queue,
+            WcsphConfig::default(),
+            start.clone(),
+            vec![Vec2::default(); 50],
+            Vec2 { x: 0., y: 400. },
+            size,
+        );
+        sim.step(1. / 60., size);
+        let stepped = sim.read_positions();
+        let snapshot = sim.snapshot();
+        sim.step(1. / 60., size);
+        assert_ne!(sim.read_positions(), stepped);
+
+        let View::Resident(copy) = snapshot.view() else {
+            panic!("the snapshot should still be on the gpu");
+        };
+        assert_eq!(copy.read_positions(), stepped);
+        sim.reset();
+        assert_eq!(sim.read_positions(), start);
+    }
+
+    #[test]
+    fn colours_match_what_comes_back_off_the_gpu() {
+        let Some((device, queue)) = request_device(true) else {
+            eprintln!("no fallback adapter, skipping");
+            return;
+        };
+        let size = winit::dpi::PhysicalSize::new(200, 150);
+        let start: Vec<Vec2> = (0..50)
+            .map(|i| Vec2 {
+                x: 50. + (i % 10) as Real * 8.,
+                y: 50. + (i / 10) as Real * 8.,
+            })
+            .collect();
+        let velocities = (0..50)
+            .map(|i| Vec2 {
+                x: i as Real,
+                y: 0.,
+            })
+            .collect();
+        let mut sim = GpuSim::new(
+            &device,
+            &queue,
+            WcsphConfig::default(),
+            start,
+            velocities,
+            Vec2 { x: 0., y: 400. },
+            size,
+        );
+        sim.step(1. / 60., size);
+
+        for quantity in Quantity::ALL {
+            let before = sim.readbacks();
+            sim.colour(quantity);
+            assert_eq!(sim.readbacks(), before);
+            let colours: Vec<f32> = sim.read_raw(&sim.colours, sim.len());
+            let [min, max, count, on]: [f32; 4] = sim
+                .read_raw(&sim.colour_range, 4)
+                .try_into()
+                .expect("four numbers in the range");
+            let expected = sim.scalars(quantity, size);
+
+            for (colour, expected) in colours.iter().zip(&expected) {
+                let expected = to_f32(*expected);
+                assert!(
+                    (colour - expected).abs() <= expected.abs() * 1e-5 + 1e-5,
+                    "{quantity:?} came out {colour} instead of {expected}"
+                );
+            }
+            let smallest = colours.iter().copied().fold(f32::INFINITY, f32::min);
+            let biggest = colours.iter().copied().fold(0., f32::max);
+            assert_eq!((min, max, count, on), (smallest, biggest, 50., 1.));
+        }
+    }
+}
@@ -0,0 +1,289 @@
+// Weakly compressible sph, one entry point per pass. Every step the particles get counting
+// sorted into cells a smoothing radius across, so the neighbour loops only look at 3x3 cells.
+
+struct Params {
+    // the window in sim pixels
+    size: vec2<f32>,
+    gravity: vec2<f32>,
+    tool_position: vec2<f32>,
+    tool_velocity: vec2<f32>,
+    count: u32,
+    columns: u32,
+    rows: u32,
+    // 0 for nothing, 1 pulls or pushes depending on the strength's sign, 2 stirs
+    tool: u32,
+    radius: f32,
+    rest_density: f32,
+    stiffness: f32,
+    viscosity: f32,
+    delta: f32,
+    // the kernels' constants, worked out once on the cpu
+    poly6: f32,
+    spiky: f32,
+    laplacian: f32,
+    tool_radius: f32,
+    tool_strength: f32,
+    restitution: f32,
+    // what the colour pass works out, 0 speed, 1 density, 2 pressure, 3 id
+    colour_by: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+
+@group(0) @binding(1)
+var<storage, read_write> positions: array<vec2<f32>>;
+
+@group(0) @binding(2)
+var<storage, read_write> velocities: array<vec2<f32>>;
+
+// how many particles landed in each cell
+@group(0) @binding(3)
+var<storage, read_write> counts: array<atomic<u32>>;
+
+// each particle's cell, and how many got counted into that cell before it
+@group(0) @binding(4)
+var<storage, read_write> slots: array<vec2<u32>>;
+
+// where each cell's run starts in sorted, plus one on the end for the last cell
+@group(0) @binding(5)
+var<storage, read_write> starts: array<u32>;
+
+// particle indices grouped by cell
+@group(0) @binding(6)
+var<storage, read_write> sorted: array<u32>;
+
+// density and pressure
+@group(0) @binding(7)
+var<storage, read_write> fluid: array<vec2<f32>>;
+
+@group(0) @binding(8)
+var<storage, read_write> accelerations: array<vec2<f32>>;
+
+// one number per particle for the renderer to colour it by
+@group(0) @binding(9)
+var<storage, read_write> colours: array<f32>;
+
+// the smallest and biggest of them as float bits, then how many there are and a 1, laid out
+// like the renderer's colouring uniform so it can be copied straight in
+@group(0) @binding(10)
+var<storage, read_write> colour_range: array<atomic<u32>, 4>;
+
+// threads in the one workgroup scan_cells runs in, which it's dispatched as
+const SCAN: u32 = 256u;
+
+var<workgroup> totals: array<u32, SCAN>;
+
+fn cell_of(position: vec2<f32>) -> vec2<i32> {
+    let cell = vec2<i32>(floor(position / params.radius));
+    return clamp(cell, vec2<i32>(0), vec2<i32>(i32(params.columns) - 1, i32(params.rows) - 1));
+}
+
+fn cell_index(cell: vec2<i32>) -> u32 {
+    return u32(cell.y) * params.columns + u32(cell.x);
+}
+
+fn poly6(distance_squared: f32) -> f32 {
+    let falloff = max(params.radius * params.radius - distance_squared, 0.0);
+    return params.poly6 * falloff * falloff * falloff;
+}
+
+// 1 in the middle down to 0 at the edge, same as the cpu force fields
+fn falloff(distance: f32, radius: f32) -> f32 {
+    let t = 1.0 - distance / radius;
+    return t * t * (3.0 - 2.0 * t);
+}
+
+@compute @workgroup_size(64)
+fn clear_cells(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x < params.columns * params.rows {
+        atomicStore(&counts[id.x], 0u);
+    }
+}
+
+@compute @workgroup_size(64)
+fn count_cells(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if i >= params.count {
+        return;
+    }
+    let cell = cell_index(cell_of(positions[i]));
+    slots[i] = vec2<u32>(cell, atomicAdd(&counts[cell], 1u));
+}
+
+// the whole prefix sum in one workgroup, each thread adds up its own run of cells first
+@compute @workgroup_size(SCAN)
+fn scan_cells(@builtin(local_invocation_id) local: vec3<u32>) {
+    let cells = params.columns * params.rows;
+    let run = (cells + SCAN - 1u) / SCAN;
+    let first = min(local.x * run, cells);
+    let last = min(first + run, cells);
+
+    var sum = 0u;
+    for (var cell = first; cell < last; cell++) {
+        sum += atomicLoad(&counts[cell]);
+    }
+    totals[local.x] = sum;
+    workgroupBarrier();
+
+    // hillis steele over the runs' totals
+    for (var offset = 1u; offset < SCAN; offset *= 2u) {
+        var before = 0u;
+        if local.x >= offset {
+            before = totals[local.x - offset];
+        }
+        workgroupBarrier();
+        totals[local.x] += before;
+        workgroupBarrier();
+    }
+
+    var start = totals[local.x] - sum;
+    for (var cell = first; cell < last; cell++) {
+        starts[cell] = start;
+        start += atomicLoad(&counts[cell]);
+    }
+    if local.x == SCAN - 1u {
+        starts[cells] = totals[local.x];
+    }
+}
+
+@compute @workgroup_size(64)
+fn scatter_cells(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if i >= params.count {
+        return;
+    }
+    let slot = slots[i];
+    sorted[starts[slot.x] + slot.y] = i;
+}
+
+@compute @workgroup_size(64)
+fn density(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if i >= params.count {
+        return;
+    }
+    let position = positions[i];
+    let home = cell_of(position);
+
+    // counts the particle itself, same as the rest density does
+    var density = 0.0;
+    for (var y = max(home.y - 1, 0); y <= min(home.y + 1, i32(params.rows) - 1); y++) {
+        for (var x = max(home.x - 1, 0); x <= min(home.x + 1, i32(params.columns) - 1); x++) {
+            let cell = cell_index(vec2<i32>(x, y));
+            for (var k = starts[cell]; k < starts[cell + 1u]; k++) {
+                let offset = position - positions[sorted[k]];
+                density += poly6(dot(offset, offset));
+            }
+        }
+    }
+    fluid[i] = vec2<f32>(density, params.stiffness * max(density - params.rest_density, 0.0));
+}
+
+@compute @workgroup_size(64)
+fn forces(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if i >= params.count {
+        return;
+    }
+    let position = positions[i];
+    let velocity = velocities[i];
+    let own = fluid[i];
+    let own_term = own.y / (own.x * own.x);
+    let home = cell_of(position);
+
+    var acceleration = params.gravity;
+    for (var y = max(home.y - 1, 0); y <= min(home.y + 1, i32(params.rows) - 1); y++) {
+        for (var x = max(home.x - 1, 0); x <= min(home.x + 1, i32(params.columns) - 1); x++) {
+            let cell = cell_index(vec2<i32>(x, y));
+            for (var k = starts[cell]; k < starts[cell + 1u]; k++) {
+                let j = sorted[k];
+                let offset = position - positions[j];
+                let distance = length(offset);
+                // itself, or something sitting right on top of it
+                if distance >= params.radius || distance < 1e-6 {
+                    continue;
+                }
+                let other = fluid[j];
+                let falloff = params.radius - distance;
+                let gradient = offset * (params.spiky * falloff * falloff / distance);
+                acceleration -= gradient * (own_term + other.y / (other.x * other.x));
+                acceleration += (velocities[j] - velocity)
+                    * (params.viscosity * params.laplacian * falloff / other.x);
+            }
+        }
+    }
+
+    if params.tool != 0u {
+        let offset = params.tool_position - position;
+        let distance = length(offset);
+        if distance < params.tool_radius {
+            let weight = params.tool_strength * falloff(distance, params.tool_radius);
+            if params.tool == 2u {
+                acceleration += (params.tool_velocity - velocity) * weight;
+            } else if distance >= 1e-6 {
+                acceleration += offset / distance * weight;
+            }
+        }
+    }
+    accelerations[i] = acceleration;
+}
+
+@compute @workgroup_size(64)
+fn integrate(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if i >= params.count {
+        return;
+    }
+    var velocity = velocities[i] + accelerations[i] * params.delta;
+    var position = positions[i] + velocity * params.delta;
+
+    // bounce off the window edges, losing a bit each time
+    if position.x < 0.0 {
+        position.x = 0.0;
+        velocity.x = abs(velocity.x) * params.restitution;
+    } else if position.x > params.size.x {
+        position.x = params.size.x;
+        velocity.x = -abs(velocity.x) * params.restitution;
+    }
+    if position.y < 0.0 {
+        position.y = 0.0;
+        velocity.y = abs(velocity.y) * params.restitution;
+    } else if position.y > params.size.y {
+        position.y = params.size.y;
+        velocity.y = -abs(velocity.y) * params.restitution;
+    }
+
+    positions[i] = position;
+    velocities[i] = velocity;
+}
+
+// everything it can colour by is never below 0, and positive floats' bits sort the same way
+// the floats do, so plain atomic min and max find the range
+@compute @workgroup_size(64)
+fn colour(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if i >= params.count {
+        return;
+    }
+    var value = f32(i);
+    switch params.colour_by {
+        case 0u: {
+            value = length(velocities[i]);
+        }
+        case 1u: {
+            value = fluid[i].x / params.rest_density;
+        }
+        case 2u: {
+            value = fluid[i].y / (params.stiffness * params.rest_density);
+        }
+        default: {}
+    }
+    colours[i] = value;
+
+    // nan and infinity stay out of the range, same as on the cpu
+    if abs(value) <= 3.40282347e38 {
+        atomicMin(&colour_range[0], bitcast<u32>(value));
+        atomicMax(&colour_range[1], bitcast<u32>(value));
+    }
+}
@@ -0,0 +1,143 @@
+use crate::fluid_sim::{real::Real, vec2::Vec2};
+
+/// most cells we'll make along one side, if the particles spread out further the cells just
+/// get bigger
+const MAX_CELLS_PER_SIDE: usize = 1024;
+
+/// uniform grid built with a counting sort, for finding everything within some radius without
+/// going over every single particle.
+#[derive(Clone, Debug)]
+pub(crate) struct NeighbourGrid {
+    cell_size: Real,
+    origin: Vec2,
+    columns: usize,
+    rows: usize,
+    /// where each cell's run of indices starts in `sorted`, plus one on the end for the last cell
+    cell_starts: Vec<usize>,
+    sorted: Vec<usize>,
+}
+
+impl NeighbourGrid {
+    /// `radius` is the furthest apart two things can be and still count as neighbours
+    pub(crate) fn new(radius: Real, positions: &[Vec2]) -> Self {
+        let (min, max) = positions.iter().fold(
+            (
+                Vec2 {
+                    x: Real::MAX,
+                    y: Real::MAX,
+                },
+                Vec2 {
+                    x: Real::MIN,
+                    y: Real::MIN,
+                },
+            ),
+            |(min, max), p| {
+                (
+                    Vec2 {
+                        x: min.x.min(p.x),
+                        y: min.y.min(p.y),
+                    },
+                    Vec2 {
+                        x: max.x.max(p.x),
+                        y: max.y.max(p.y),
+                    },
+                )
+            },
+        );
+        let (origin, extent) = if positions.is_empty() {
+            (Vec2::default(), Vec2::default())
+        } else {
+            (min, max - min)
+        };
+
+        let cell_size = radius
+            .max(extent.x / MAX_CELLS_PER_SIDE as Real)
+            .max(extent.y / MAX_CELLS_PER_SIDE as Real)
+            .max(Real::EPSILON);
+        let columns = (extent.x / cell_size) as usize + 1;
+        let rows = (extent.y / cell_size) as usize + 1;
+
+        let mut grid = Self {
+            cell_size,
+            origin,
+            columns,
+            rows,
+            cell_starts: vec![0; columns * rows + 1],
+            sorted: vec![0; positions.len()],
+        };
+
+        // count, prefix sum, then scatter
+        let cells: Vec<usize> = positions.iter().map(|p| grid.cell_index(*p)).collect();
+        for cell in &cells {
+            grid.cell_starts[cell + 1] += 1;
+        }
+        for i in 1..grid.cell_starts.len() {
+            grid.cell_starts[i] += grid.cell_starts[i - 1];
+        }
+        let mut fill = grid.cell_starts.clone();
+        for (i, cell) in cells.iter().enumerate() {
+            grid.sorted[fill[*cell]] = i;
+            fill[*cell] += 1;
+        }
+
+        grid
+    }
+
+    fn cell_coords(&self, p: Vec2) -> (usize, usize) {
+        let x = ((p.x - self.origin.x) / self.cell_size).max(0.) as usize;
+        let y = ((p.y - self.origin.y) / self.cell_size).max(0.) as usize;
+        (x.min(self.columns - 1), y.min(self.rows - 1))
+    }
+
+    fn cell_index(&self, p: Vec2) -> usize {
+        let (x, y) = self.cell_coords(p);
+        y * self.columns + x
+    }
+
+    /// calls `f` with every index in the 3x3 block of cells around `pos`. Anything within the
+    /// radius is in there, but so are some things that aren't, so check the distance yourself.
+    pub(crate) fn for_each_near(&self, pos: Vec2, mut f: impl FnMut(usize)) {
+        let (x, y) = self.cell_coords(pos);
+        for row in y.saturating_sub(1)..(y + 2).min(self.rows) {
+            let first = row * self.columns + x.saturating_sub(1);
+            let last = row * self.columns + (x + 1).min(self.columns - 1);
+            for &i in &self.sorted[self.cell_starts[first]..self.cell_starts[last + 1]] {
+                f(i);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn finds_the_same_neighbours_as_brute_force() {
+        let mut rng = rand::rng();
+        let positions: Vec<Vec2> = (0..500)
+            .map(|_| Vec2 {
+                x: rng.random_range(0.0..300.),
+                y: rng.random_range(0.0..200.),
+            })
+            .collect();
+        let radius = 17.;
+        let grid = NeighbourGrid::new(radius, &positions);
+
+        for pos in &positions {
+            let mut from_grid = Vec::new();
+            grid.for_each_near(*pos, |j| {
+                if (positions[j] - *pos).length() < radius {
+                    from_grid.push(j);
+                }
+            });
+            from_grid.sort();
+
+            let brute: Vec<usize> = (0..positions.len())
+                .filter(|j| (positions[*j] - *pos).length() < radius)
+                .collect();
+            assert_eq!(from_grid, brute);
+        }
+    }
+}
@@ -0,0 +1,429 @@
+//! Lattice Boltzmann on a D2Q9 lattice with the BGK collision operator.
+//!
+//! Every cell holds nine populations, how much fluid is heading off in each of the eight
+//! directions plus the bit sitting still. A step relaxes them toward the equilibrium for the
+//! cell's density and velocity, then shuffles each one over to the neighbour it was heading
+//! for. Obstacles bounce their populations straight back, which makes a no-slip wall.
+//!
+//! Everything is in lattice units: one cell across, one lattice step long. Speeds want to stay
+//! well under 0.3 or the whole thing stops being incompressible.
+
+use crate::fluid_sim::{
+    real::Real,
+    simulation::{FieldView, Parameter, count},
+    vec2::Vec2,
+};
+use rayon::prelude::*;
+
+/// the nine lattice directions, resting first
+const DIRECTIONS: [(isize, isize); 9] = [
+    (0, 0),
+    (1, 0),
+    (0, 1),
+    (-1, 0),
+    (0, -1),
+    (1, 1),
+    (-1, 1),
+    (-1, -1),
+    (1, -1),
+];
+const WEIGHTS: [Real; 9] = [
+    4. / 9.,
+    1. / 9.,
+    1. / 9.,
+    1. / 9.,
+    1. / 9.,
+    1. / 36.,
+    1. / 36.,
+    1. / 36.,
+    1. / 36.,
+];
+/// which direction points back the way each one came
+const OPPOSITE: [usize; 9] = [0, 3, 4, 1, 2, 7, 8, 5, 6];
+
+/// what's on the other side of one edge of the lattice
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Edge {
+    /// no-slip, bounces everything back
+    Wall,
+    /// fluid at rest density coming in at this velocity
+    Inflow(Vec2),
+    /// lets whatever reaches it leave without reflecting much back
+    Outflow,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LbmConfig {
+    /// cells across the window, the rows follow from its aspect ratio
+    pub columns: usize,
+    /// the BGK relaxation time. Has to stay above 0.5, the closer it gets the thinner the
+    /// fluid and the closer to blowing up
+    pub relaxation_time: Real,
+    pub left: Edge,
+    pub right: Edge,
+    pub top: Edge,
+    pub bottom: Edge,
+    /// lattice steps per second of real time
+    pub steps_per_second: Real,
+    /// most lattice steps one call to update will take, so a slow frame can't snowball
+    pub max_steps_per_update: usize,
+}
+
+impl Default for LbmConfig {
+    fn default() -> Self {
+        Self {
+            columns: 240,
+            relaxation_time: 0.56,
+            left: Edge::Inflow(Vec2 { x: 0.08, y: 0. }),
+            right: Edge::Outflow,
+            top: Edge::Wall,
+            bottom: Edge::Wall,
+            steps_per_second: 600.,
+            max_steps_per_update: 20,
+        }
+    }
+}
+
+impl LbmConfig {
+    /// the column count needs the lattice building again, so it isn't in here
+    pub fn parameters(&self) -> Vec<Parameter> {
+        let mut parameters = vec![
+            Parameter::new("relaxation time", self.relaxation_time, 0.505..=2.),
+            Parameter::new("steps per second", self.steps_per_second, 30.0..=2000.),
+            Parameter::whole("max steps per update", self.max_steps_per_update, 1..=100),
+        ];
+        if let Edge::Inflow(velocity) = self.left {
+            parameters.push(Parameter::new("inflow speed", velocity.x, 0.0..=0.2));
+        }
+        parameters
+    }
+
+    pub fn set_parameter(&mut self, name: &str, value: Real) {
+        match name {
+            "relaxation time" => self.relaxation_time = value.max(0.505),
+            "steps per second" => self.steps_per_second = value,
+            "max steps per update" => self.max_steps_per_update = count(value),
+            "inflow speed" => {
+                if let Edge::Inflow(velocity) = &mut self.left {
+                    velocity.x = value;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// the populations a cell at `density` moving at `velocity` settles toward
+fn equilibrium(density: Real, velocity: Vec2) -> [Real; 9] {
+    let speed_squared = velocity.dot(velocity);
+    std::array::from_fn(|i| {
+        let (ex, ey) = DIRECTIONS[i];
+        let along = ex as Real * velocity.x + ey as Real * velocity.y;
+        WEIGHTS[i] * density * (1. + 3. * along + 4.5 * along * along - 1.5 * speed_squared)
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct Lbm {
+    pub config: LbmConfig,
+    columns: usize,
+    rows: usize,
+    /// nine populations per cell, row by row
+    populations: Vec<Real>,
+    /// same again after collision, what the streaming pulls from
+    collided: Vec<Real>,
+    obstacles: Vec<bool>,
+
+    density: Vec<Real>,
+    velocity_x: Vec<Real>,
+    velocity_y: Vec<Real>,
+    /// real time that hasn't been turned into lattice steps yet
+    pending: Real,
+    /// how many the last update took
+    last_steps: usize,
+}
+
+impl Lbm {
+    /// a lattice shaped like the window with no obstacles, already flowing at the inflow speed
+    pub fn new(config: LbmConfig, size: winit::dpi::PhysicalSize<u32>) -> Self {
+        let columns = config.columns.max(2);
+        let rows = (columns as Real * size.height as Real / size.width.max(1) as Real).round();
+        let rows = (rows as usize).max(2);
+        let cells = columns * rows;
+
+        let mut lbm = Self {
+            config,
+            columns,
+            rows,
+            populations: vec![0.; cells * 9],
+            collided: vec![0.; cells * 9],
+            obstacles: vec![false; cells],
+            density: vec![1.; cells],
+            velocity_x: vec![0.; cells],
+            velocity_y: vec![0.; cells],
+            pending: 0.,
+            last_steps: 0,
+        };
+        lbm.reset_populations();
+        lbm
+    }
+
+    /// obstacles from a mask, one entry per cell row by row, true is solid. Masks the wrong
+    /// size get ignored.
+    pub fn with_obstacles(mut self, mask: Vec<bool>) -> Self {
+        if mask.len() == self.obstacles.len() {
+            self.obstacles = mask;
+            self.reset_populations();
+        }
+        self
+    }
+
+    /// a cylinder a quarter of the way along, for watching the vortex street come off it
+    pub fn with_cylinder(self) -> Self {
+        let (columns, rows) = (self.columns, self.rows);
+        // just off the middle, a perfectly symmetric flow takes ages to start shedding
+        let centre = Vec2 {
+            x: columns as Real / 4.,
+            y: rows as Real / 2. + 1.5,
+        };
+        let radius = rows as Real / 9.;
+        let mask = (0..columns * rows)
+            .map(|cell| {
+                let at = Vec2 {
+                    x: (cell % columns) as Real,
+                    y: (cell / columns) as Real,
+                };
+                (at - centre).length() < radius
+            })
+            .collect();
+        self.with_obstacles(mask)
+    }
+
+    /// the inflow velocity everywhere, or still if nothing's flowing in
+    fn reset_populations(&mut self) {
+        let flow = [
+            self.config.left,
+            self.config.right,
+            self.config.top,
+            self.config.bottom,
+        ]
+        .into_iter()
+        .find_map(|edge| match edge {
+            Edge::Inflow(velocity) => Some(velocity),
+            _ => None,
+        })
+        .unwrap_or_default();
+
+        for (cell, populations) in self.populations.chunks_mut(9).enumerate() {
+            let velocity = if self.obstacles[cell] {
+                Vec2::default()
+            } else {
+                flow
+            };
+            populations.copy_from_slice(&equilibrium(1., velocity));
+        }
+        self.update_macroscopic();
+    }
+
+    pub(crate) fn field(&self) -> FieldView<'_> {
+        FieldView {
+            columns: self.columns,
+            rows: self.rows,
+            border: 0,
+            density: &self.density,
+            velocity_x: &self.velocity_x,
+            velocity_y: &self.velocity_y,
+            obstacles: Some(&self.obstacles),
+        }
+    }
+
+    /// back to a steady flow, the obstacles stay
+    pub(crate) fn reset(&mut self) {
+        self.pending = 0.;
+        self.last_steps = 0;
+        self.reset_populations();
+    }
+
+    pub(crate) fn summary(&self) -> String {
+        let fastest = self
+            .velocity_x
+            .iter()
+            .zip(&self.velocity_y)
+            .map(|(x, y)| x.hypot(*y))
+            .fold(0., Real::max);
+        format!(
+            "lbm {}x{}: {} lattice steps, tau {}, fastest cell {fastest:.3}",
+            self.columns, self.rows, self.last_steps, self.config.relaxation_time
+        )
+    }
+
+    /// runs however many lattice steps `delta` seconds is worth
+    pub(crate) fn update(&mut self, delta: Real) {
+        self.pending += delta * self.config.steps_per_second;
+        let steps = (self.pending as usize).min(self.config.max_steps_per_update);
+        self.pending = (self.pending - steps as Real).min(1.);
+        for _ in 0..steps {
+            self.lattice_step();
+        }
+        self.last_steps = steps;
+        self.update_macroscopic();
+    }
+
+    fn lattice_step(&mut self) {
+        let columns = self.columns;
+        let omega = 1. / self.config.relaxation_time.max(0.501);
+        let obstacles = &self.obstacles;
+
+        // collide
+        self.collided
+            .par_chunks_mut(9)
+            .zip(self.populations.par_chunks(9))
+            .enumerate()
+            .for_each(|(cell, (collided, populations))| {
+                if obstacles[cell] {
+                    collided.copy_from_slice(populations);
+                    return;
+                }
+                let (density, velocity) = moments(populations);
+                let settled = equilibrium(density, velocity);
+                for i in 0..9 {
+                    collided[i] = populations[i] + omega * (settled[i] - populations[i]);
+                }
+            });
+
+        // stream, every cell pulls from the neighbour each population is coming from
+        let collided = &self.collided;
+        let config = self.config;
+        let rows = self.rows;
+        self.populations
+            .par_chunks_mut(9 * columns)
+            .enumerate()
+            .for_each(|(y, row)| {
+                for (x, populations) in row.chunks_mut(9).enumerate() {
+                    let cell = y * columns + x;
+                    for (i, population) in populations.iter_mut().enumerate() {
+                        let (ex, ey) = DIRECTIONS[i];
+                        let from_x = x as isize - ex;
+                        let from_y = y as isize - ey;
+
+                        let edge = if from_x < 0 {
+                            Some(config.left)
+                        } else if from_x >= columns as isize {
+                            Some(config.right)
+                        } else if from_y < 0 {
+                            Some(config.top)
+                        } else if from_y >= rows as isize {
+                            Some(config.bottom)
+                        } else {
+                            None
+                        };
+
+                        *population = match edge {
+                            Some(Edge::Wall) => collided[cell * 9 + OPPOSITE[i]],
+                            Some(Edge::Inflow(velocity)) => equilibrium(1., velocity)[i],
+                            Some(Edge::Outflow) => collided[cell * 9 + i],
+                            None => {
+                                let from = from_y as usize * columns + from_x as usize;
+                                if obstacles[from] {
+                                    collided[cell * 9 + OPPOSITE[i]]
+                                } else {
+                                    collided[from * 9 + i]
+                                }
+                            }
+                        };
+                    }
+                }
+            });
+    }
+
+    /// density and velocity out of the populations, for drawing
+    fn update_macroscopic(&mut self) {
+        let obstacles = &self.obstacles;
+        self.density
+            .par_iter_mut()
+            .zip(self.velocity_x.par_iter_mut())
+            .zip(self.velocity_y.par_iter_mut())
+            .zip(self.populations.par_chunks(9))
+            .enumerate()
+            .for_each(|(cell, (((density, vx), vy), populations))| {
+                let (rho, velocity) = if obstacles[cell] {
+                    (1., Vec2::default())
+                } else {
+                    moments(populations)
+                };
+                *density = rho;
+                *vx = velocity.x;
+                *vy = velocity.y;
+            });
+    }
+}
+
+/// a cell's density and velocity
+fn moments(populations: &[Real]) -> (Real, Vec2) {
+    let mut density = 0.;
+    let mut momentum = Vec2::default();
+    for (population, (ex, ey)) in populations.iter().zip(DIRECTIONS) {
+        density += population;
+        momentum.x += population * ex as Real;
+        momentum.y += population * ey as Real;
+    }
+    if density > 1e-6 {
+        (density, momentum / density)
+    } else {
+        (density, Vec2::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(config: LbmConfig) -> Lbm {
+        Lbm::new(config, winit::dpi::PhysicalSize::new(400, 100))
+    }
+
+    #[test]
+    fn still_fluid_stays_still_and_keeps_its_mass() {
+        let mut lbm = channel(LbmConfig {
+            columns: 40,
+            left: Edge::Wall,
+            right: Edge::Wall,
+            ..Default::default()
+        });
+        let mass: Real = lbm.populations.iter().sum();
+        for _ in 0..50 {
+            lbm.lattice_step();
+        }
+        lbm.update_macroscopic();
+
+        let after: Real = lbm.populations.iter().sum();
+        assert!((after - mass).abs() < mass * 1e-4);
+        assert!(lbm.velocity_x.iter().all(|v| v.abs() < 1e-5));
+    }
+
+    #[test]
+    fn obstacle_slows_the_flow_behind_it() {
+        let mut lbm = channel(LbmConfig {
+            columns: 80,
+            ..Default::default()
+        });
+        let wall = (0..lbm.columns * lbm.rows)
+            .map(|cell| {
+                let (x, y) = (cell % lbm.columns, cell / lbm.columns);
+                x == 20 && y > 2 && y < lbm.rows - 3
+            })
+            .collect();
+        lbm = lbm.with_obstacles(wall);
+        for _ in 0..300 {
+            lbm.lattice_step();
+        }
+        lbm.update_macroscopic();
+
+        let field = lbm.field();
+        let middle = field.rows / 2;
+        let behind = field.velocity(22, middle).x;
+        let far_ahead = field.velocity(5, middle).x;
+        assert!(field.is_solid(20, middle));
+        assert!(behind < far_ahead * 0.5, "{behind} vs {far_ahead}");
+    }
+}
@@ -1,20 +1,139 @@
-use crate::{fluid_sim::vec2::Vec2, render::vertex::Vertex};
+use crate::fluid_sim::{
+    boundary::{Boundary, Pose},
+    dfsph::DfsphConfig,
+    diagnostics::StepDiagnostics,
+    flip::FlipConfig,
+    forces::{ForceField, ForceId, Forces, Gravity, Point, Stir},
+    pairs::Columns,
+    pbf::PbfConfig,
+    real::{Real, consts::PI},
+    rigid_body::RigidBody,
+    simulation::{Interaction, Parameter, Points, Quantity, Tool},
+    vec2::Vec2,
+};
 use rand::Rng;
 use rayon::prelude::*;
-use std::f32::consts::PI;
 
-mod vec2;
+pub mod boundary;
+pub mod contour;
+pub mod dfsph;
+pub mod diagnostics;
+pub mod flip;
+pub mod flow;
+pub mod forces;
+pub mod gpu;
+mod grid;
+pub mod lbm;
+mod pairs;
+pub mod pbf;
+pub mod real;
+pub mod rigid_body;
+pub mod scene;
+pub mod simulation;
+mod sort;
+pub mod sph;
+pub mod stable_fluids;
+pub mod vec2;
 
-const MIN: f32 = -PI / 16.;
-const MAX: f32 = PI / 16.;
-const GRAVITY_NUMBER: f32 = 400.;
+const MIN: Real = -PI / 16.;
+const MAX: Real = PI / 16.;
 const PARTICLE_NUMBER: usize = 5000;
-const MAX_START_SPEED: f32 = 140.;
-const MAX_AWAY_SPEED: f32 = 400.;
-const DECAY_FACTOR: f32 = 0.9;
-const FALLOFF_CONSTANT: f32 = 2000.;
-const INTERACTION_RADIUS: f32 = 200.;
-const INTERACTION_RADIUS_SQUARED: f32 = INTERACTION_RADIUS * INTERACTION_RADIUS;
+const MAX_START_SPEED: Real = 140.;
+/// how many steps go by between putting the particles back in order along the z curve
+pub const SORT_EVERY: usize = 30;
+
+/// which set of physics moves the particles along each step
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SolverMode {
+    /// the original pairwise shove, needs small steps
+    #[default]
+    Explicit,
+    /// position based fluids, stays stable with big steps
+    Pbf,
+    /// divergence-free sph, iterates until the density error is under a tolerance
+    Dfsph,
+    /// pressure solved on a grid, velocities carried back with a pic/flip blend
+    Flip,
+    /// same grid, but the particles carry an affine velocity instead of the flip blend
+    Apic,
+}
+
+impl SolverMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            SolverMode::Explicit => "explicit",
+            SolverMode::Pbf => "pbf",
+            SolverMode::Dfsph => "dfsph",
+            SolverMode::Flip => "flip",
+            SolverMode::Apic => "apic",
+        }
+    }
+}
+
+/// how hard the original solver's particles shove each other about
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ExplicitConfig {
+    /// the shove at one pixel apart, it falls off with the distance squared from there
+    pub falloff: Real,
+    /// in pixels, past this particles leave each other alone
+    pub interaction_radius: Real,
+    /// cap on what any one pair can do, so particles right on top of each other don't fly off
+    pub max_away_speed: Real,
+}
+
+impl Default for ExplicitConfig {
+    fn default() -> Self {
+        Self {
+            falloff: 2000.,
+            interaction_radius: 200.,
+            max_away_speed: 400.,
+        }
+    }
+}
+
+impl ExplicitConfig {
+    pub fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new("falloff", self.falloff, 100.0..=10000.),
+            Parameter::new("interaction radius", self.interaction_radius, 10.0..=400.),
+            Parameter::new("max away speed", self.max_away_speed, 10.0..=2000.),
+        ]
+    }
+
+    pub fn set_parameter(&mut self, name: &str, value: Real) {
+        match name {
+            "falloff" => self.falloff = value,
+            "interaction radius" => self.interaction_radius = value,
+            "max away speed" => self.max_away_speed = value,
+            _ => {}
+        }
+    }
+
+    /// how hard `other` shoves a particle sitting at `pos`, nothing if it's out of range
+    fn push_from(&self, other: Vec2, pos: Vec2) -> Vec2 {
+        let dist_vec = particle_distance(other, pos);
+        let dist_squared = dist_vec.x.powi(2) + dist_vec.y.powi(2);
+
+        if dist_squared < self.interaction_radius.powi(2) && dist_squared > 1e-6 {
+            let magnatude = (self.falloff / dist_squared).min(self.max_away_speed);
+            let force_direction = dist_vec / dist_squared.sqrt();
+            force_direction * magnatude
+        } else {
+            Vec2::default()
+        }
+    }
+
+    /// treats the Vec2 as a distance rather than a point. Might be a little confusing
+    #[allow(dead_code)]
+    fn falloff_function(&self, mut input: Vec2) -> Vec2 {
+        let input_squared = input * input;
+        let xinput = self.falloff / input_squared.x;
+        let yinput = self.falloff / input_squared.y;
+        input.x = xinput;
+        input.y = yinput;
+        input
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct FluidSim {
@@ -23,14 +142,57 @@ pub struct FluidSim {
 
     next_positions: Box<[Vec2]>,
     next_velocities: Box<[Vec2]>,
+    /// which particle is in each slot. The spatial sort moves them between slots, this is
+    /// how anything following one particle finds it again
+    ids: Box<[u32]>,
+
+    boundaries: Vec<Boundary>,
+    bodies: Vec<RigidBody>,
+    /// everything pushing on the fluid from outside, gravity included
+    forces: Forces,
+    /// which of the forces is the gravity and what it is, so it can be swapped for another
+    gravity: (ForceId, Vec2),
+    /// the force whatever's poking the fluid is putting on it, if anything is
+    poke: Option<ForceId>,
+    /// simulated seconds, what the moving boundaries get evaluated at
+    time: Real,
+    /// steps taken since the start, for knowing when to sort
+    steps: usize,
+    /// how many steps between spatial sorts, 0 for never
+    pub sort_every: usize,
+
+    /// how much speed a particle or body keeps bouncing off a wall, whatever the solver
+    pub decay_factor: Real,
+
+    solver: SolverMode,
+    pub explicit: ExplicitConfig,
+    pub pbf: PbfConfig,
+    pub dfsph: DfsphConfig,
+    pub flip: FlipConfig,
+    /// each particle's velocity gradient, rows for x and y. Only APIC uses it
+    affine: Box<[[Vec2; 2]]>,
+    /// the positions again as x and y columns, for the explicit solver's shove
+    columns: Columns,
+    diagnostics: StepDiagnostics,
+    /// a copy of the sim from just before its first step, for resetting back to
+    initial: Option<Box<FluidSim>>,
 }
 
 impl FluidSim {
     pub fn new_rand(size: winit::dpi::PhysicalSize<u32>) -> Self {
+        Self::new_rand_in(
+            Vec2::default(),
+            Vec2 {
+                x: size.width as Real,
+                y: size.height as Real,
+            },
+        )
+    }
+
+    /// same as new_rand but the particles only spawn inside the rectangle from min to max
+    pub fn new_rand_in(min: Vec2, max: Vec2) -> Self {
         #[allow(deprecated)]
         let mut rng = rand::thread_rng();
-        let width = size.width;
-        let height = size.height;
 
         let mut particles_positions = Vec::with_capacity(PARTICLE_NUMBER);
         let mut particles_velocities = Vec::with_capacity(PARTICLE_NUMBER);
@@ -38,9 +200,9 @@ impl FluidSim {
         for _ in 0..PARTICLE_NUMBER {
             particles_positions.push(Vec2 {
                 #[allow(deprecated)]
-                x: rng.gen_range(0.0..width as f32),
+                x: rng.gen_range(min.x..max.x),
                 #[allow(deprecated)]
-                y: rng.gen_range(0.0..height as f32),
+                y: rng.gen_range(min.y..max.y),
             });
 
             particles_velocities.push(Vec2 {
@@ -51,44 +213,289 @@ impl FluidSim {
             });
         }
 
+        Self::from_particles(particles_positions, particles_velocities)
+    }
+
+    pub fn from_particles(positions: Vec<Vec2>, velocities: Vec<Vec2>) -> Self {
+        let positions_len = positions.len() as u32;
+        let mut forces = Forces::default();
+        let gravity = Gravity::default();
+        let gravity = (forces.add(gravity), gravity.0);
         Self {
-            current_positions: particles_positions.clone().into_boxed_slice(),
-            current_velocities: particles_velocities.clone().into_boxed_slice(),
-            next_positions: particles_positions.into_boxed_slice(),
-            next_velocities: particles_velocities.into_boxed_slice(),
+            affine: vec![[Vec2::default(); 2]; positions.len()].into_boxed_slice(),
+            columns: Columns::default(),
+            current_positions: positions.clone().into_boxed_slice(),
+            current_velocities: velocities.clone().into_boxed_slice(),
+            next_positions: positions.into_boxed_slice(),
+            next_velocities: velocities.into_boxed_slice(),
+            ids: (0..positions_len).collect(),
+            boundaries: Vec::new(),
+            bodies: Vec::new(),
+            forces,
+            gravity,
+            poke: None,
+            time: 0.,
+            steps: 0,
+            sort_every: SORT_EVERY,
+            decay_factor: 0.9,
+            solver: SolverMode::default(),
+            explicit: ExplicitConfig::default(),
+            pbf: PbfConfig::default(),
+            dfsph: DfsphConfig::default(),
+            flip: FlipConfig::default(),
+            diagnostics: StepDiagnostics::default(),
+            initial: None,
+        }
+    }
+
+    pub fn add_boundary(&mut self, boundary: Boundary) {
+        self.boundaries.push(boundary);
+    }
+
+    pub fn add_body(&mut self, body: RigidBody) {
+        self.bodies.push(body);
+    }
+
+    /// starts pushing the particles about with another field, on top of whatever's already
+    /// there. A new sim only has gravity.
+    pub fn add_force(&mut self, force: impl ForceField + 'static) -> ForceId {
+        self.forces.add(force)
+    }
+
+    /// gives back whether it was there to take out
+    pub fn remove_force(&mut self, id: ForceId) -> bool {
+        self.forces.remove(id)
+    }
+
+    /// swaps the gravity for a different one, any other fields stay
+    pub fn set_gravity(&mut self, gravity: Vec2) {
+        self.remove_force(self.gravity.0);
+        self.gravity = (self.add_force(Gravity(gravity)), gravity);
+    }
+
+    pub fn gravity(&self) -> Vec2 {
+        self.gravity.1
+    }
+
+    pub fn solver(&self) -> SolverMode {
+        self.solver
+    }
+
+    /// swaps whatever was poking the fluid for this, the position's in window pixels same as
+    /// the particles
+    pub fn poke(&mut self, interaction: Option<Interaction>) {
+        if let Some(id) = self.poke.take() {
+            self.remove_force(id);
+        }
+        let Some(Interaction {
+            tool,
+            position,
+            velocity,
+            radius,
+            strength,
+        }) = interaction
+        else {
+            return;
+        };
+        self.poke = Some(match tool {
+            Tool::Attract => self.add_force(Point::attractor(position, radius, strength)),
+            Tool::Repel => self.add_force(Point::repulsor(position, radius, strength)),
+            Tool::Stir => self.add_force(Stir {
+                position,
+                radius,
+                velocity,
+                rate: strength,
+            }),
+        });
+    }
+
+    /// what the force fields add up to for something at `position` going at `velocity`, right
+    /// now
+    fn external_acceleration(&self, position: Vec2, velocity: Vec2) -> Vec2 {
+        self.forces.acceleration(position, velocity, self.time)
+    }
+
+    /// moves the bodies on under the force fields plus whatever the fluid did to them
+    fn integrate_bodies(&mut self, forces: &[(Vec2, Real)], delta: Real) {
+        for (i, (force, torque)) in forces.iter().enumerate() {
+            let body = &self.bodies[i];
+            let outside = self.external_acceleration(body.position, body.velocity);
+            self.bodies[i].integrate(*force, *torque, outside, delta);
+        }
+    }
+
+    /// rough distance between particles if they were spread evenly over the window, which is
+    /// what the rigid bodies want for their ghost spacing
+    pub fn particle_spacing(&self, size: winit::dpi::PhysicalSize<u32>) -> Real {
+        (size.width as Real * size.height as Real / self.current_positions.len() as Real).sqrt()
+    }
+
+    /// how far apart the particles sit when the fluid's at rest. The sph solvers say so
+    /// outright, the rest get the even spread over the window.
+    pub fn rest_spacing(&self, size: winit::dpi::PhysicalSize<u32>) -> Real {
+        match self.solver {
+            SolverMode::Pbf => self.pbf.particle_spacing,
+            SolverMode::Dfsph => self.dfsph.particle_spacing,
+            SolverMode::Explicit | SolverMode::Flip | SolverMode::Apic => {
+                self.particle_spacing(size)
+            }
+        }
+    }
+
+    /// half the spacing for every particle in `points`, the ghosts going by
+    /// their own body's spacing. Empty when there aren't any bodies and they're all the same.
+    pub(crate) fn particle_radii(&self, size: winit::dpi::PhysicalSize<u32>) -> Vec<Real> {
+        if self.bodies.is_empty() {
+            return Vec::new();
+        }
+        let fluid = self.rest_spacing(size) / 2.;
+        std::iter::repeat_n(fluid, self.current_positions.len())
+            .chain(
+                self.bodies
+                    .iter()
+                    .flat_map(|body| std::iter::repeat_n(body.spacing() / 2., body.ghost_count())),
+            )
+            .collect()
+    }
+
+    pub fn set_solver(&mut self, solver: SolverMode) {
+        self.solver = solver;
+        // whatever apic had before is stale now
+        self.affine.fill([Vec2::default(); 2]);
+    }
+
+    /// how the last step went
+    pub fn diagnostics(&self) -> StepDiagnostics {
+        self.diagnostics
+    }
+
+    /// puts the particles, bodies and clock back to where they were before the first step.
+    /// The solver, its settings and the force fields stay as they are now.
+    pub fn reset(&mut self) {
+        let Some(initial) = self.initial.take() else {
+            return;
+        };
+        let (solver, explicit, pbf, dfsph, flip) =
+            (self.solver, self.explicit, self.pbf, self.dfsph, self.flip);
+        let (sort_every, decay_factor) = (self.sort_every, self.decay_factor);
+        let forces = std::mem::take(&mut self.forces);
+        let (gravity, poke) = (self.gravity, self.poke);
+        *self = (*initial).clone();
+        self.forces = forces;
+        self.gravity = gravity;
+        self.poke = poke;
+        self.explicit = explicit;
+        self.pbf = pbf;
+        self.dfsph = dfsph;
+        self.flip = flip;
+        self.sort_every = sort_every;
+        self.decay_factor = decay_factor;
+        self.set_solver(solver);
+        self.initial = Some(initial);
+    }
+
+    pub(crate) fn update(&mut self, delta: Real, size: winit::dpi::PhysicalSize<u32>) {
+        if self.initial.is_none() {
+            self.initial = Some(Box::new(self.clone()));
+        }
+
+        // where the bodies were before the solver moved them, the collisions need it
+        let body_poses: Vec<_> = self.bodies.iter().map(RigidBody::pose).collect();
+
+        match self.solver {
+            SolverMode::Explicit => self.step_explicit(delta),
+            SolverMode::Pbf => self.step_pbf(delta, size),
+            SolverMode::Dfsph => self.step_dfsph(delta, size),
+            SolverMode::Flip | SolverMode::Apic => self.step_flip(delta, size),
         }
+
+        self.collide(delta, size, &body_poses);
+
+        self.time += delta;
+        self.steps += 1;
+
+        // SWAP THEM!!!
+        std::mem::swap(&mut self.current_positions, &mut self.next_positions);
+        std::mem::swap(&mut self.current_velocities, &mut self.next_velocities);
+
+        if self.sort_every > 0 && self.steps.is_multiple_of(self.sort_every) {
+            self.sort_spatially();
+        }
+    }
+
+    /// puts the particles in z curve order so neighbours sit close together in memory, as
+    /// they drift apart the neighbour loops stop hitting the cache. Everything kept per
+    /// particle moves with it, the ids included.
+    fn sort_spatially(&mut self) {
+        let order = sort::z_order(&self.current_positions);
+        sort::permute(&mut self.current_positions, &order);
+        sort::permute(&mut self.current_velocities, &order);
+        sort::permute(&mut self.next_positions, &order);
+        sort::permute(&mut self.next_velocities, &order);
+        sort::permute(&mut self.affine, &order);
+        sort::permute(&mut self.ids, &order);
+    }
+
+    /// every ghost of every body in world space, tagged with which body it belongs to
+    fn ghosts(&self) -> Vec<(usize, Vec2)> {
+        self.bodies
+            .iter()
+            .enumerate()
+            .flat_map(|(i, body)| body.ghosts_world().map(move |ghost| (i, ghost)))
+            .collect()
     }
 
-    pub(crate) fn update(&mut self, delta: f32, size: winit::dpi::PhysicalSize<u32>) {
+    /// the original force model: everything in range shoves everything else away
+    fn step_explicit(&mut self, delta: Real) {
         let delta_vec = Vec2 { x: delta, y: delta };
 
+        let ghosts = self.ghosts();
+        self.columns.fill(&self.current_positions);
+        let (forces, time, config) = (&self.forces, self.time, &self.explicit);
+        let fluid = &self.columns;
+
         self.next_velocities
             .par_iter_mut()
             .enumerate()
             .for_each(|(i, new_velocity)| {
+                let pos = self.current_positions[i];
                 *new_velocity = self.current_velocities[i];
 
-                // gravity
-                new_velocity.y += GRAVITY_NUMBER * delta;
+                // gravity and anything else pushing from outside
+                *new_velocity += forces.acceleration(pos, *new_velocity, time) * delta;
 
-                let pos = self.current_positions[i];
                 // pressure from the other particles around it
-                for j in 0..PARTICLE_NUMBER {
-                    if i == j {
-                        continue;
-                    }
+                *new_velocity += fluid.push_on(pos, config) * delta;
 
-                    let dist_vec = particle_distance(self.current_positions[j], pos);
-                    let dist_squared = dist_vec.x.powi(2) + dist_vec.y.powi(2);
-
-                    if dist_squared < INTERACTION_RADIUS_SQUARED && dist_squared > 1e-6 {
-                        let magnatude = (FALLOFF_CONSTANT / dist_squared).min(MAX_AWAY_SPEED);
-                        let force_direction = dist_vec / dist_squared.sqrt();
-                        *new_velocity += force_direction * magnatude * delta;
-                    }
+                // the rigid bodies' ghosts push exactly like particles do
+                for (_, ghost) in &ghosts {
+                    *new_velocity += config.push_from(*ghost, pos) * delta;
                 }
             });
 
+        // and the fluid pushes back on the ghosts, plus the ghosts of any other body
+        let ghost_forces: Vec<Vec2> = ghosts
+            .par_iter()
+            .map(|(body, ghost)| {
+                let from_fluid = fluid.push_on(*ghost, config);
+                ghosts
+                    .iter()
+                    .filter(|(other_body, _)| other_body != body)
+                    .fold(from_fluid, |force, (_, other)| {
+                        force + config.push_from(*other, *ghost)
+                    })
+            })
+            .collect();
+
+        let mut body_forces = vec![(Vec2::default(), 0.); self.bodies.len()];
+        for ((body, ghost), force) in ghosts.iter().zip(&ghost_forces) {
+            let (total, torque) = &mut body_forces[*body];
+            *total += *force;
+            *torque += (*ghost - self.bodies[*body].position).cross(*force);
+        }
+
+        self.integrate_bodies(&body_forces, delta);
+
         // update the positions with some fancy zipping
         self.next_positions
             .par_iter_mut()
@@ -98,6 +505,27 @@ impl FluidSim {
                 *next_pos = *current_pos + *next_vel * delta_vec
             });
 
+        self.diagnostics = StepDiagnostics {
+            solver: SolverMode::Explicit,
+            ..Default::default()
+        };
+    }
+
+    /// walls, boundaries and bodies, run on the next positions whatever the solver was
+    fn collide(&mut self, delta: Real, size: winit::dpi::PhysicalSize<u32>, body_poses: &[Pose]) {
+        let decay_factor = self.decay_factor;
+        // where every boundary is at the start and end of this step
+        let boundary_poses: Vec<_> = self
+            .boundaries
+            .iter()
+            .map(|boundary| {
+                (
+                    boundary.pose_at(self.time),
+                    boundary.pose_at(self.time + delta),
+                )
+            })
+            .collect();
+
         // bounce with some randomness
         self.next_positions
             .par_iter_mut()
@@ -109,37 +537,128 @@ impl FluidSim {
                     pos.x = 0.0;
                     #[allow(deprecated)]
                     vel.rotate_degrees(cgmath::Rad(rng.gen_range(MIN..MAX)));
-                    vel.x *= -DECAY_FACTOR;
-                } else if pos.x > size.width as f32 {
-                    pos.x = size.width as f32;
+                    vel.x *= -decay_factor;
+                } else if pos.x > size.width as Real {
+                    pos.x = size.width as Real;
                     #[allow(deprecated)]
                     vel.rotate_degrees(cgmath::Rad(rng.gen_range(MIN..MAX)));
-                    vel.x *= -DECAY_FACTOR;
+                    vel.x *= -decay_factor;
                 }
                 if pos.y < 0.0 {
                     pos.y = 0.0;
                     #[allow(deprecated)]
                     vel.rotate_degrees(cgmath::Rad(rng.gen_range(MIN..MAX)));
-                    vel.y *= -DECAY_FACTOR;
-                } else if pos.y > size.height as f32 {
-                    pos.y = size.height as f32;
+                    vel.y *= -decay_factor;
+                } else if pos.y > size.height as Real {
+                    pos.y = size.height as Real;
                     #[allow(deprecated)]
                     vel.rotate_degrees(cgmath::Rad(rng.gen_range(MIN..MAX)));
-                    vel.y *= -DECAY_FACTOR;
+                    vel.y *= -decay_factor;
+                }
+
+                for (boundary, (before, after)) in self.boundaries.iter().zip(&boundary_poses) {
+                    boundary.collide(before, after, delta, decay_factor, pos, vel);
                 }
             });
 
-        // SWAP THEM!!!
-        std::mem::swap(&mut self.current_positions, &mut self.next_positions);
-        std::mem::swap(&mut self.current_velocities, &mut self.next_velocities);
+        // particles hitting the bodies, the bodies get the opposite of every kick
+        let body_impulses = self
+            .next_positions
+            .par_iter_mut()
+            .zip(self.next_velocities.par_iter_mut())
+            .fold(
+                || vec![(Vec2::default(), 0.); self.bodies.len()],
+                |mut impulses, (pos, vel)| {
+                    for (i, (body, before)) in self.bodies.iter().zip(body_poses).enumerate() {
+                        if let Some((kick, at)) =
+                            body.collide_particle(before, delta, decay_factor, pos, vel)
+                        {
+                            let (impulse, torque) = &mut impulses[i];
+                            *impulse -= kick;
+                            *torque -= (at - body.position).cross(kick);
+                        }
+                    }
+                    impulses
+                },
+            )
+            .reduce(
+                || vec![(Vec2::default(), 0.); self.bodies.len()],
+                |mut total, part| {
+                    for (total, part) in total.iter_mut().zip(part) {
+                        total.0 += part.0;
+                        total.1 += part.1;
+                    }
+                    total
+                },
+            );
+
+        for (body, (impulse, angular_impulse)) in self.bodies.iter_mut().zip(body_impulses) {
+            body.apply_impulses(impulse, angular_impulse);
+            body.collide_walls(size, decay_factor);
+        }
     }
 
-    // for the render if we want the particles to be outputed as vertexs for the pipeline
-    pub(crate) fn get_particles_vertexes(&self) -> Vec<Vertex> {
-        self.current_positions
-            .iter()
-            .map(|particle| Vertex {
-                position: [particle.x, particle.y],
+    /// what to draw, the fluid borrowed as it is and the rigid bodies drawn as their ghosts
+    /// on the end. Only the ghosts need working out, so without bodies nothing gets copied.
+    pub(crate) fn points(&self) -> Points<'_> {
+        Points {
+            fluid: &self.current_positions,
+            ids: &self.ids,
+            ghosts: self
+                .bodies
+                .iter()
+                .flat_map(RigidBody::ghosts_world)
+                .collect(),
+        }
+    }
+
+    /// `quantity` for every particle, in the same order as `points` but
+    /// without the ghosts on the end
+    pub(crate) fn scalars(
+        &self,
+        quantity: Quantity,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> Vec<Real> {
+        match quantity {
+            Quantity::Speed => self
+                .current_velocities
+                .par_iter()
+                .map(|velocity| velocity.length())
+                .collect(),
+            Quantity::Density => self.densities(size),
+            // tait's equation with the usual exponent of 7, in units of the stiffness
+            Quantity::Pressure => self
+                .densities(size)
+                .into_par_iter()
+                .map(|density| (density.powi(7) - 1.).max(0.) / 7.)
+                .collect(),
+            Quantity::Id => self.ids.par_iter().map(|&id| id as Real).collect(),
+        }
+    }
+
+    /// every particle's sph density over the rest density, measured with the dfsph kernel
+    /// when that's the solver and the pbf one otherwise. The window edges count, so the
+    /// fluid sitting on the floor doesn't look thin.
+    fn densities(&self, size: winit::dpi::PhysicalSize<u32>) -> Vec<Real> {
+        let (radius, spacing) = match self.solver {
+            SolverMode::Dfsph => (self.dfsph.smoothing_radius, self.dfsph.particle_spacing),
+            _ => (self.pbf.smoothing_radius, self.pbf.particle_spacing),
+        };
+        let kernel = sph::Kernel::new(radius);
+        let rest_density = sph::rest_density(&kernel, spacing);
+        let walls = sph::Walls::new(&kernel, spacing, size);
+        let positions = &self.current_positions;
+
+        sph::neighbours(radius, positions)
+            .into_par_iter()
+            .zip(positions.par_iter())
+            .map(|(neighbours, &pos)| {
+                let others = neighbours.iter().map(|&j| {
+                    let offset = pos - positions[j];
+                    kernel.poly6(offset.dot(offset))
+                });
+                let walls = walls.near(pos).map(|(_, density, _)| density);
+                (kernel.poly6(0.) + others.chain(walls).sum::<Real>()) / rest_density
             })
             .collect()
     }
@@ -158,17 +677,6 @@ fn particle_distance(first: Vec2, second: Vec2) -> Vec2 {
     }
 }
 
-/// treats the Vec2 as a distance rather than a point. Might be a little confusing
-#[allow(dead_code)]
-fn falloff_function(mut input: Vec2) -> Vec2 {
-    let input_squared = input * input;
-    let xinput = FALLOFF_CONSTANT / input_squared.x;
-    let yinput = FALLOFF_CONSTANT / input_squared.y;
-    input.x = xinput;
-    input.y = yinput;
-    input
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -178,12 +686,7 @@ mod tests {
     }
 
     fn dummy_sim(positions: Vec<Vec2>, velocities: Vec<Vec2>) -> FluidSim {
-        FluidSim {
-            current_positions: positions.clone().into_boxed_slice(),
-            current_velocities: velocities.clone().into_boxed_slice(),
-            next_positions: positions.into_boxed_slice(),
-            next_velocities: velocities.into_boxed_slice(),
-        }
+        FluidSim::from_particles(positions, velocities)
     }
 
     #[test]
@@ -195,8 +698,214 @@ mod tests {
         // TODO there's probably more to test here that I'm not thinking about.
     }
 
+    #[test]
+    fn piston_pushes_particles_along() {
+        use boundary::{Motion, Pose, Shape};
+
+        let mut sim = dummy_sim(vec![Vec2 { x: 200., y: 200. }], vec![Vec2 { x: 0., y: 0. }]);
+        sim.add_boundary(Boundary::solid(
+            Shape::Rect {
+                half_extents: Vec2 { x: 20., y: 100. },
+            },
+            Motion::scripted(|t| {
+                Pose::new(
+                    Vec2 {
+                        x: 170. + 300. * t,
+                        y: 200.,
+                    },
+                    0.,
+                )
+            }),
+        ));
+
+        for _ in 0..10 {
+            sim.update(1. / 60., test_size());
+        }
+
+        // the piston face ends up at 240, the particle has to be at least that far along
+        assert!(sim.current_positions[0].x >= 240. - 1e-3);
+        assert!(sim.current_velocities[0].x > 0.);
+    }
+
+    #[test]
+    fn bodies_and_particles_push_each_other() {
+        let mut sim = dummy_sim(vec![Vec2 { x: 200., y: 240. }], vec![Vec2::default()]);
+        sim.add_body(RigidBody::new(
+            boundary::Shape::Circle { radius: 20. },
+            1.,
+            Vec2 { x: 200., y: 200. },
+            5.,
+        ));
+
+        let delta = 1. / 60.;
+        sim.update(delta, test_size());
+
+        // gravity alone would give both of them the same downwards speed
+        let falling = forces::GRAVITY_NUMBER * delta;
+        assert!(sim.current_velocities[0].y > falling);
+        assert!(sim.bodies[0].velocity.y < falling);
+    }
+
+    #[test]
+    fn wood_floats_and_steel_sinks() {
+        let size = test_size();
+        // a 40x15 block packed into the bottom of the window
+        let positions = (0..600)
+            .map(|i| Vec2 {
+                x: 5. + (i % 40) as Real * 10.,
+                y: 255. + (i / 40) as Real * 10.,
+            })
+            .collect::<Vec<_>>();
+        let mut sim = dummy_sim(positions, vec![Vec2::default(); 600]);
+        for _ in 0..120 {
+            sim.update(1. / 60., size);
+        }
+
+        // same densities as the floaters scene, dropped in from above the settled column
+        let spacing = sim.particle_spacing(size);
+        let body = |density, x| {
+            RigidBody::new(
+                boundary::Shape::Circle { radius: 30. },
+                density,
+                Vec2 { x, y: 60. },
+                spacing,
+            )
+        };
+        sim.add_body(body(0.5, 100.));
+        sim.add_body(body(7.8, 300.));
+        for _ in 0..300 {
+            sim.update(1. / 60., size);
+        }
+
+        let mean = sim.current_positions.iter().map(|p| p.y).sum::<Real>()
+            / sim.current_positions.len() as Real;
+        let (wood, steel) = (sim.bodies[0].position.y, sim.bodies[1].position.y);
+        // lower y is higher up. Sitting on the floor the steel would be at 370
+        assert!(wood < mean);
+        assert!(steel > 400. - 60.);
+    }
+
+    #[test]
+    fn gravity_can_point_anywhere() {
+        let mut sim = dummy_sim(vec![Vec2 { x: 200., y: 200. }], vec![Vec2::default()]);
+        sim.set_gravity(Vec2 { x: -300., y: 0. });
+        sim.add_force(forces::Drag { coefficient: 1. });
+
+        sim.update(1. / 60., test_size());
+
+        // drag sees the velocity from the start of the step, which was nothing
+        let velocity = sim.current_velocities[0];
+        assert!((velocity.x + 5.).abs() < 1e-4 && velocity.y == 0.);
+    }
+
+    #[test]
+    fn poking_pulls_pushes_and_lets_go() {
+        let start = Vec2 { x: 200., y: 200. };
+        let poke = |tool| {
+            let mut sim = dummy_sim(vec![start], vec![Vec2::default()]);
+            sim.set_gravity(Vec2::default());
+            sim.poke(Some(Interaction {
+                tool,
+                position: Vec2 { x: 250., y: 200. },
+                velocity: Vec2 { x: 0., y: -300. },
+                radius: 100.,
+                strength: if tool == Tool::Stir { 10. } else { 1000. },
+            }));
+            sim.update(1. / 60., test_size());
+            sim
+        };
+
+        assert!(poke(Tool::Attract).current_velocities[0].x > 0.);
+        assert!(poke(Tool::Repel).current_velocities[0].x < 0.);
+        let stirred = poke(Tool::Stir).current_velocities[0];
+        assert!(stirred.y < 0. && stirred.y >= -300.);
+
+        // letting go takes the force off again
+        let mut sim = poke(Tool::Attract);
+        sim.poke(None);
+        let before = sim.current_velocities[0];
+        sim.update(1. / 60., test_size());
+        assert_eq!(sim.current_velocities[0], before);
+    }
+
+    #[test]
+    fn points_are_the_positions_themselves() {
+        let sim = FluidSim::from_particles(
+            vec![Vec2 { x: 1., y: 2. }, Vec2 { x: 3., y: 4. }],
+            vec![Vec2::default(); 2],
+        );
+        let points = sim.points();
+        // borrowed rather than copied, and nothing to work out without any bodies
+        assert_eq!(points.fluid.as_ptr(), sim.current_positions.as_ptr());
+        assert!(points.ghosts.is_empty());
+        let floats: &[Real] = bytemuck::cast_slice(points.fluid);
+        assert_eq!(floats, [1., 2., 3., 4.]);
+    }
+
+    #[test]
+    fn sorting_keeps_every_particle_with_its_id() {
+        let mut sim = FluidSim::new_rand(test_size());
+        sim.set_solver(SolverMode::Pbf);
+        sim.sort_every = 2;
+        sim.update(1. / 120., test_size());
+        let before: Vec<_> = sim
+            .points()
+            .by_id()
+            .into_iter()
+            .zip(sim.scalars(Quantity::Speed, test_size()))
+            .collect();
+        let ids_before = sim.scalars(Quantity::Id, test_size());
+        assert!(
+            ids_before
+                .iter()
+                .enumerate()
+                .all(|(i, &id)| id == i as Real)
+        );
+
+        sim.sort_spatially();
+        assert_ne!(sim.scalars(Quantity::Id, test_size()), ids_before);
+        let speeds = sim.scalars(Quantity::Speed, test_size());
+        let mut after = vec![(Vec2::default(), 0.); speeds.len()];
+        for ((&id, &position), speed) in sim.ids.iter().zip(&*sim.current_positions).zip(speeds) {
+            after[id as usize] = (position, speed);
+        }
+        assert_eq!(after, before);
+
+        // and the next step puts them in order on its own
+        sim.sort_every = 1;
+        sim.update(1. / 120., test_size());
+        let order = sort::z_order(&sim.current_positions);
+        assert!(
+            order
+                .iter()
+                .enumerate()
+                .all(|(i, &slot)| slot as usize == i)
+        );
+    }
+
+    #[test]
+    fn the_shove_follows_the_config() {
+        let config = ExplicitConfig::default();
+        let (here, there) = (Vec2 { x: 0., y: 0. }, Vec2 { x: 100., y: 0. });
+        let push = config.push_from(there, here);
+        assert!(push.x < 0.);
+
+        let short = ExplicitConfig {
+            interaction_radius: 50.,
+            ..config
+        };
+        assert_eq!(short.push_from(there, here), Vec2::default());
+        let hard = ExplicitConfig {
+            falloff: config.falloff * 2.,
+            ..config
+        };
+        assert_eq!(hard.push_from(there, here), push * 2.);
+    }
+
     #[test]
     fn falloff_actually_works() {
+        let config = ExplicitConfig::default();
+        let falloff_function = |input| config.falloff_function(input);
         assert!(
             falloff_function(Vec2 { x: 10., y: 10. }) < falloff_function(Vec2 { x: 1., y: 1. })
         );
@@ -0,0 +1,182 @@
+//! The explicit solver's pair shove over the positions laid out as separate x and y columns,
+//! a few neighbours at a time where there's simd for it.
+
+use crate::fluid_sim::{ExplicitConfig, real::Real, vec2::Vec2};
+
+/// how many neighbours get done at once, four fills an sse register
+pub(crate) const LANES: usize = 4;
+
+/// the positions split into columns, padded out to a whole number of lanes with points that
+/// are never in range of anything. The sim keeps its positions as pairs, so this is a gather
+/// every step, but that's one pass over the particles against the shove's every pair:
+/// `the_gather_costs_less_than_the_lanes_save` times both. Kept between steps so refilling it
+/// doesn't allocate.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Columns {
+    xs: Vec<Real>,
+    ys: Vec<Real>,
+}
+
+impl Columns {
+    /// swaps whatever was in the columns for `points`, reusing the room already there
+    pub(crate) fn fill(&mut self, points: &[Vec2]) {
+        self.xs.clear();
+        self.ys.clear();
+        self.xs.extend(points.iter().map(|point| point.x));
+        self.ys.extend(points.iter().map(|point| point.y));
+        // nan fails every comparison, so the padding falls out of range on its own
+        let padded = points.len().next_multiple_of(LANES);
+        self.xs.resize(padded, Real::NAN);
+        self.ys.resize(padded, Real::NAN);
+    }
+
+    /// the shove from every point on one at `pos`, itself included since something sitting
+    /// right on top doesn't push
+    #[cfg(all(target_arch = "x86_64", not(any(feature = "scalar", feature = "f64"))))]
+    pub(crate) fn push_on(&self, pos: Vec2, config: &ExplicitConfig) -> Vec2 {
+        self.push_on_lanes(pos, config)
+    }
+
+    #[cfg(not(all(target_arch = "x86_64", not(any(feature = "scalar", feature = "f64")))))]
+    pub(crate) fn push_on(&self, pos: Vec2, config: &ExplicitConfig) -> Vec2 {
+        self.push_on_scalar(pos, config)
+    }
+
+    /// four pairs at a time in sse registers, which every x86_64 has, so only in `f32`. Each
+    /// pair works out the same as `ExplicitConfig::push_from` up to rounding, and the adding
+    /// up is in a different order.
+    #[cfg(all(target_arch = "x86_64", not(feature = "f64")))]
+    #[cfg_attr(feature = "scalar", allow(dead_code))]
+    fn push_on_lanes(&self, pos: Vec2, config: &ExplicitConfig) -> Vec2 {
+        use std::arch::x86_64::*;
+
+        let (xs, _) = self.xs.as_chunks::<LANES>();
+        let (ys, _) = self.ys.as_chunks::<LANES>();
+        // SAFETY: sse is part of x86_64 so the intrinsics are always there, and the loads
+        // are unaligned ones of whole chunks
+        unsafe {
+            let pos_x = _mm_set1_ps(pos.x);
+            let pos_y = _mm_set1_ps(pos.y);
+            let radius_squared = _mm_set1_ps(config.interaction_radius.powi(2));
+            let too_close = _mm_set1_ps(1e-6);
+            let falloff = _mm_set1_ps(config.falloff);
+            let max_away = _mm_set1_ps(config.max_away_speed);
+
+            let mut sum_x = _mm_setzero_ps();
+            let mut sum_y = _mm_setzero_ps();
+            for (xs, ys) in xs.iter().zip(ys) {
+                let x = _mm_sub_ps(pos_x, _mm_loadu_ps(xs.as_ptr()));
+                let y = _mm_sub_ps(pos_y, _mm_loadu_ps(ys.as_ptr()));
+                let distance_squared = _mm_add_ps(_mm_mul_ps(x, x), _mm_mul_ps(y, y));
+                let in_range = _mm_and_ps(
+                    _mm_cmplt_ps(distance_squared, radius_squared),
+                    _mm_cmpgt_ps(distance_squared, too_close),
+                );
+                let magnitude = _mm_min_ps(_mm_div_ps(falloff, distance_squared), max_away);
+                let scale = _mm_and_ps(
+                    in_range,
+                    _mm_div_ps(magnitude, _mm_sqrt_ps(distance_squared)),
+                );
+                sum_x = _mm_add_ps(sum_x, _mm_mul_ps(_mm_and_ps(in_range, x), scale));
+                sum_y = _mm_add_ps(sum_y, _mm_mul_ps(_mm_and_ps(in_range, y), scale));
+            }
+
+            let mut sums = [[0.; LANES]; 2];
+            _mm_storeu_ps(sums[0].as_mut_ptr(), sum_x);
+            _mm_storeu_ps(sums[1].as_mut_ptr(), sum_y);
+            Vec2 {
+                x: sums[0].iter().sum(),
+                y: sums[1].iter().sum(),
+            }
+        }
+    }
+
+    /// one pair at a time through `ExplicitConfig::push_from`, for everything that isn't
+    /// x86_64 in `f32` and to check the lanes against
+    #[cfg_attr(
+        all(target_arch = "x86_64", not(any(feature = "scalar", feature = "f64"))),
+        allow(dead_code)
+    )]
+    fn push_on_scalar(&self, pos: Vec2, config: &ExplicitConfig) -> Vec2 {
+        self.xs
+            .iter()
+            .zip(&self.ys)
+            .fold(Vec2::default(), |force, (&x, &y)| {
+                force + config.push_from(Vec2 { x, y }, pos)
+            })
+    }
+}
+
+#[cfg(all(test, target_arch = "x86_64", not(feature = "f64")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lanes_and_scalar_agree() {
+        use rand::Rng;
+
+        let mut rng = rand::rng();
+        let points: Vec<Vec2> = (0..203)
+            .map(|_| Vec2 {
+                x: rng.random_range(0.0..300.),
+                y: rng.random_range(0.0..300.),
+            })
+            .collect();
+        let mut columns = Columns::default();
+        columns.fill(&points);
+        let config = ExplicitConfig::default();
+        assert_eq!(columns.xs.len() % LANES, 0);
+
+        for &pos in points.iter().chain(&[Vec2 { x: 1000., y: 1000. }]) {
+            let lanes = columns.push_on_lanes(pos, &config);
+            let scalar = columns.push_on_scalar(pos, &config);
+            let error = (lanes - scalar).length();
+            assert!(
+                error <= 1e-4 * scalar.length().max(1.),
+                "{lanes:?} against {scalar:?}"
+            );
+        }
+        assert_eq!(
+            columns.push_on_lanes(Vec2 { x: 1000., y: 1000. }, &config),
+            Vec2::default()
+        );
+    }
+
+    /// a whole step's worth of the explicit solver, gather and all. Timing so it's left out
+    /// of the normal run, `cargo test --release -- --ignored --nocapture` to see it
+    #[test]
+    #[ignore]
+    fn the_gather_costs_less_than_the_lanes_save() {
+        use rand::Rng;
+        use std::{hint::black_box, time::Instant};
+
+        let mut rng = rand::rng();
+        let points: Vec<Vec2> = (0..5000)
+            .map(|_| Vec2 {
+                x: rng.random_range(0.0..800.),
+                y: rng.random_range(0.0..600.),
+            })
+            .collect();
+        let config = ExplicitConfig::default();
+        let mut columns = Columns::default();
+        columns.fill(&points);
+
+        let start = Instant::now();
+        for _ in 0..100 {
+            columns.fill(black_box(&points));
+        }
+        let gather = start.elapsed() / 100;
+        let time = |push: fn(&Columns, Vec2, &ExplicitConfig) -> Vec2| {
+            let start = Instant::now();
+            for &pos in &points {
+                black_box(push(&columns, black_box(pos), &config));
+            }
+            start.elapsed()
+        };
+        let lanes = time(Columns::push_on_lanes);
+        let scalar = time(Columns::push_on_scalar);
+
+        println!("gather {gather:.2?}, lanes {lanes:.2?}, scalar {scalar:.2?}");
+        assert!(gather < scalar.saturating_sub(lanes));
+    }
+}
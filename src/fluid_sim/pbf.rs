@@ -0,0 +1,320 @@
+//! Position Based Fluids, after Macklin & Müller 2013.
+//!
+//! Instead of pushing particles around with forces this predicts where they want to go, then
+//! nudges those predictions until the density everywhere is back at rest density. Because it
+//! works on positions directly it doesn't blow up with big time steps the way the explicit
+//! solver does.
+
+use crate::fluid_sim::{
+    FluidSim, SolverMode,
+    diagnostics::StepDiagnostics,
+    real::Real,
+    simulation::{Parameter, count},
+    sph::{self, Kernel, Walls},
+    vec2::Vec2,
+};
+use rayon::prelude::*;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PbfConfig {
+    /// kernel radius, in pixels
+    pub smoothing_radius: Real,
+    /// how far apart particles sit when the fluid is at rest, sets the rest density
+    pub particle_spacing: Real,
+    /// constraint solver passes per step
+    pub iterations: usize,
+    /// the epsilon in the lambda denominator, bigger is softer but more stable
+    pub relaxation: Real,
+    /// strength of the artificial pressure that stops particles clumping at the surface
+    pub tensile_k: Real,
+    pub tensile_n: i32,
+    /// where the artificial pressure is measured from, as a fraction of the smoothing radius
+    pub tensile_dq: Real,
+    /// how much each particle gets dragged toward its neighbours' velocity
+    pub xsph_viscosity: Real,
+}
+
+impl Default for PbfConfig {
+    fn default() -> Self {
+        Self {
+            smoothing_radius: 20.,
+            particle_spacing: 8.,
+            iterations: 4,
+            relaxation: 1e-2,
+            tensile_k: 0.1,
+            tensile_n: 4,
+            tensile_dq: 0.2,
+            xsph_viscosity: 0.1,
+        }
+    }
+}
+
+impl PbfConfig {
+    /// density a square lattice at `particle_spacing` works out to with this kernel
+    pub fn rest_density(&self) -> Real {
+        sph::rest_density(&Kernel::new(self.smoothing_radius), self.particle_spacing)
+    }
+
+    pub fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new("smoothing radius", self.smoothing_radius, 5.0..=60.),
+            Parameter::new("particle spacing", self.particle_spacing, 2.0..=30.),
+            Parameter::whole("iterations", self.iterations, 1..=20),
+            Parameter::new("relaxation", self.relaxation, 1e-4..=1.),
+            Parameter::new("tensile k", self.tensile_k, 0.0..=1.),
+            Parameter::whole("tensile n", self.tensile_n as usize, 1..=8),
+            Parameter::new("tensile dq", self.tensile_dq, 0.0..=1.),
+            Parameter::new("xsph viscosity", self.xsph_viscosity, 0.0..=1.),
+        ]
+    }
+
+    pub fn set_parameter(&mut self, name: &str, value: Real) {
+        match name {
+            "smoothing radius" => self.smoothing_radius = value,
+            "particle spacing" => self.particle_spacing = value,
+            "iterations" => self.iterations = count(value),
+            "relaxation" => self.relaxation = value,
+            "tensile k" => self.tensile_k = value,
+            "tensile n" => self.tensile_n = count(value) as i32,
+            "tensile dq" => self.tensile_dq = value,
+            "xsph viscosity" => self.xsph_viscosity = value,
+            _ => {}
+        }
+    }
+}
+
+impl FluidSim {
+    pub(super) fn step_pbf(&mut self, delta: Real, size: winit::dpi::PhysicalSize<u32>) {
+        let config = self.pbf;
+        let kernel = Kernel::new(config.smoothing_radius);
+        let rest_density = config.rest_density();
+        let tensile_reference = kernel.poly6((config.tensile_dq * config.smoothing_radius).powi(2));
+        let width = size.width as Real;
+        let height = size.height as Real;
+        let walls = Walls::new(&kernel, config.particle_spacing, size);
+        let keep_in_window = |p: &mut Vec2| {
+            p.x = p.x.clamp(0., width);
+            p.y = p.y.clamp(0., height);
+        };
+
+        // predict where everything wants to go with just the outside forces acting on it
+        let (forces, time) = (&self.forces, self.time);
+        self.next_positions
+            .par_iter_mut()
+            .zip(self.next_velocities.par_iter_mut())
+            .zip(self.current_positions.par_iter())
+            .zip(self.current_velocities.par_iter())
+            .for_each(|(((next_pos, next_vel), pos), vel)| {
+                *next_vel = *vel + forces.acceleration(*pos, *vel, time) * delta;
+                *next_pos = *pos + *next_vel * delta;
+                keep_in_window(next_pos);
+            });
+
+        // neighbours get found once per step, the iterations don't move things far enough to
+        // need another search
+        let neighbours = sph::neighbours(config.smoothing_radius, &self.next_positions);
+
+        // the bodies' ghosts sit still during the solve and act like fluid that can't move
+        let ghosts = self.ghosts();
+        let ghost_positions: Vec<Vec2> = ghosts.iter().map(|(_, ghost)| *ghost).collect();
+        let ghost_neighbours = sph::neighbours_among(
+            config.smoothing_radius,
+            &self.next_positions,
+            &ghost_positions,
+        );
+
+        let body_centres: Vec<Vec2> = self.bodies.iter().map(|body| body.position).collect();
+        let mut body_reactions = vec![(Vec2::default(), 0.); self.bodies.len()];
+        let mut lambdas = vec![0.; self.next_positions.len()];
+        let mut corrections = vec![Vec2::default(); self.next_positions.len()];
+        // measured at the start of each pass, so this ends up being what the last pass fixed
+        let mut density_error = 0.;
+
+        for _ in 0..config.iterations {
+            let positions = &self.next_positions;
+
+            let total_error: Real = lambdas
+                .par_iter_mut()
+                .enumerate()
+                .map(|(i, lambda)| {
+                    let pos = positions[i];
+                    let mut density = kernel.poly6(0.);
+                    let mut own_gradient = Vec2::default();
+                    let mut gradient_sum = 0.;
+
+                    for &j in &neighbours[i] {
+                        let offset = pos - positions[j];
+                        density += kernel.poly6(offset.dot(offset));
+                        let gradient = kernel.spiky_gradient(offset) / rest_density;
+                        own_gradient += gradient;
+                        gradient_sum += gradient.dot(gradient);
+                    }
+                    for &g in &ghost_neighbours[i] {
+                        let offset = pos - ghost_positions[g];
+                        density += kernel.poly6(offset.dot(offset));
+                        own_gradient += kernel.spiky_gradient(offset) / rest_density;
+                    }
+                    for (normal, wall_density, wall_gradient) in walls.near(pos) {
+                        density += wall_density;
+                        own_gradient += normal * (wall_gradient / rest_density);
+                    }
+
+                    // only ever push apart. Letting it pull too makes the sparse bits at the
+                    // surface clump together, the artificial pressure handles the rest
+                    let constraint = (density / rest_density - 1.).max(0.);
+                    *lambda = -constraint
+                        / (gradient_sum + own_gradient.dot(own_gradient) + config.relaxation);
+                    constraint
+                })
+                .sum();
+            density_error = total_error / lambdas.len().max(1) as Real;
+
+            let artificial_pressure = |offset: Vec2| {
+                -config.tensile_k
+                    * (kernel.poly6(offset.dot(offset)) / tensile_reference).powi(config.tensile_n)
+            };
+
+            let reactions = corrections
+                .par_iter_mut()
+                .enumerate()
+                .fold(
+                    || vec![(Vec2::default(), 0.); body_centres.len()],
+                    |mut reactions, (i, correction)| {
+                        let pos = positions[i];
+                        *correction = Vec2::default();
+
+                        for &j in &neighbours[i] {
+                            let offset = pos - positions[j];
+                            let scale = lambdas[i] + lambdas[j] + artificial_pressure(offset);
+                            *correction += kernel.spiky_gradient(offset) * scale;
+                        }
+                        for &g in &ghost_neighbours[i] {
+                            let (body, ghost) = ghosts[g];
+                            let offset = pos - ghost;
+                            let scale = lambdas[i] + artificial_pressure(offset);
+                            let share = kernel.spiky_gradient(offset) * scale;
+                            *correction += share;
+                            let push = share / rest_density;
+
+                            // whatever the ghost pushes the particle by, the body gets pushed
+                            // back by
+                            let (total, torque) = &mut reactions[body];
+                            *total -= push;
+                            *torque -= (ghost - body_centres[body]).cross(push);
+                        }
+                        for (normal, _, wall_gradient) in walls.near(pos) {
+                            *correction += normal * (wall_gradient * lambdas[i]);
+                        }
+
+                        *correction = *correction / rest_density;
+                        reactions
+                    },
+                )
+                .reduce(
+                    || vec![(Vec2::default(), 0.); body_centres.len()],
+                    |mut total, part| {
+                        for (total, part) in total.iter_mut().zip(part) {
+                            total.0 += part.0;
+                            total.1 += part.1;
+                        }
+                        total
+                    },
+                );
+            for (total, part) in body_reactions.iter_mut().zip(reactions) {
+                total.0 += part.0;
+                total.1 += part.1;
+            }
+
+            self.next_positions
+                .par_iter_mut()
+                .zip(&corrections)
+                .for_each(|(pos, correction)| {
+                    // particles piled on the same spot have no gradient to speak of, so their
+                    // lambdas go through the roof. Never let one pass fling anything further
+                    // than a particle's width.
+                    let length = correction.length();
+                    if length > config.particle_spacing {
+                        *pos += *correction * (config.particle_spacing / length);
+                    } else {
+                        *pos += *correction;
+                    }
+                    keep_in_window(pos);
+                });
+        }
+
+        // velocity is just how far things ended up moving
+        self.next_velocities
+            .par_iter_mut()
+            .zip(self.next_positions.par_iter())
+            .zip(self.current_positions.par_iter())
+            .for_each(|((vel, next_pos), pos)| *vel = (*next_pos - *pos) / delta);
+
+        // xsph, smooths the velocities out so it looks like a liquid rather than sand
+        let velocities = &self.next_velocities;
+        let positions = &self.next_positions;
+        let smoothed: Vec<Vec2> = (0..velocities.len())
+            .into_par_iter()
+            .map(|i| {
+                let mut blend = Vec2::default();
+                for &j in &neighbours[i] {
+                    let offset = positions[i] - positions[j];
+                    blend += (velocities[j] - velocities[i]) * kernel.poly6(offset.dot(offset));
+                }
+                velocities[i] + blend * (config.xsph_viscosity / rest_density)
+            })
+            .collect();
+        self.next_velocities.copy_from_slice(&smoothed);
+
+        // total displacement over the step is an impulse of displacement / delta, so the force
+        // is that over delta again
+        let per_step = delta * delta;
+        let body_forces: Vec<_> = body_reactions
+            .into_iter()
+            .map(|(push, twist)| (push / per_step, twist / per_step))
+            .collect();
+        self.integrate_bodies(&body_forces, delta);
+
+        self.diagnostics = StepDiagnostics {
+            solver: SolverMode::Pbf,
+            iterations: config.iterations,
+            density_error,
+            ..Default::default()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernel_integrates_to_one() {
+        // a lattice at rest density covers spacing squared per particle
+        let config = PbfConfig::default();
+        let covered = config.rest_density() * config.particle_spacing.powi(2);
+        assert!((covered - 1.).abs() < 0.05, "{covered}");
+    }
+
+    #[test]
+    fn squashed_block_spreads_out() {
+        let config = PbfConfig::default();
+        let squashed = config.particle_spacing * 0.5;
+        let positions: Vec<Vec2> = (0..100)
+            .map(|i| Vec2 {
+                x: 200. + (i % 10) as Real * squashed,
+                y: 200. + (i / 10) as Real * squashed,
+            })
+            .collect();
+        let width = |sim: &FluidSim| {
+            let xs = sim.current_positions.iter().map(|p| p.x);
+            xs.clone().fold(Real::MIN, Real::max) - xs.fold(Real::MAX, Real::min)
+        };
+
+        let mut sim = FluidSim::from_particles(positions, vec![Vec2::default(); 100]);
+        sim.set_solver(SolverMode::Pbf);
+        let before = width(&sim);
+        sim.update(1. / 30., winit::dpi::PhysicalSize::new(400, 400));
+
+        assert!(width(&sim) > before * 1.2);
+    }
+}
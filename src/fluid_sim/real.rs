@@ -0,0 +1,19 @@
+//! The float the particle sims run in. `f32` normally, `f64` with the `f64` feature for long
+//! runs where rounding would get mixed up with the integration error. Whatever goes to the
+//! screen or the gpu gets turned into `f32` on the way.
+
+#[cfg(not(feature = "f64"))]
+pub type Real = f32;
+#[cfg(feature = "f64")]
+pub type Real = f64;
+
+#[cfg(not(feature = "f64"))]
+pub use std::f32::consts;
+#[cfg(feature = "f64")]
+pub use std::f64::consts;
+
+/// a sim number for the renderer, a no-op unless the sims are in `f64`
+#[allow(clippy::unnecessary_cast)]
+pub fn to_f32(value: Real) -> f32 {
+    value as f32
+}
@@ -0,0 +1,292 @@
+use crate::fluid_sim::{
+    boundary::{Pose, Shape, collide_with_shape},
+    real::Real,
+    vec2::Vec2,
+};
+
+/// a floating object that gets shoved around by the fluid and shoves back.
+///
+/// The body is filled with ghost particles spaced like the fluid is. Each ghost pushes on the
+/// fluid and gets pushed by it exactly like a real particle would, so a body with the same
+/// density as the fluid is just a rigid lump of fluid. That's where the buoyancy comes from.
+#[derive(Clone, Debug)]
+pub struct RigidBody {
+    pub shape: Shape,
+    pub position: Vec2,
+    pub angle: Real,
+    pub velocity: Vec2,
+    pub angular_velocity: Real,
+
+    mass: Real,
+    inertia: Real,
+    /// local space samples covering the body, one per fluid particle's worth of area
+    ghosts: Box<[Vec2]>,
+    spacing: Real,
+}
+
+impl RigidBody {
+    /// `relative_density` is compared against the fluid, so under 1 floats and over 1 sinks.
+    /// `spacing` should be roughly the distance between fluid particles.
+    pub fn new(mut shape: Shape, relative_density: Real, position: Vec2, spacing: Real) -> Self {
+        let (min, max) = local_bounds(&shape);
+
+        let mut ghosts = Vec::new();
+        let mut y = min.y + spacing / 2.;
+        while y < max.y {
+            let mut x = min.x + spacing / 2.;
+            while x < max.x {
+                let sample = Vec2 { x, y };
+                if shape.distance(sample) <= 0. {
+                    ghosts.push(sample);
+                }
+                x += spacing;
+            }
+            y += spacing;
+        }
+        // tiny bodies still need something to push with
+        if ghosts.is_empty() {
+            ghosts.push(Vec2::default());
+        }
+
+        // recentre so the position really is the centre of mass
+        let centre = ghosts
+            .iter()
+            .fold(Vec2::default(), |sum, ghost| sum + *ghost)
+            / ghosts.len() as Real;
+        for ghost in &mut ghosts {
+            *ghost -= centre;
+        }
+        // circles and rects are symmetric so the centre's already at zero for them
+        if let Shape::Polygon(points) = &mut shape {
+            for point in points {
+                *point -= centre;
+            }
+        }
+
+        let ghost_mass = relative_density;
+        let mass = ghost_mass * ghosts.len() as Real;
+        // every ghost stands for a little square of the body, so give it the square's own
+        // inertia on top of the parallel axis bit
+        let inertia = ghosts
+            .iter()
+            .map(|ghost| ghost_mass * (ghost.dot(*ghost) + spacing * spacing / 6.))
+            .sum();
+
+        Self {
+            shape,
+            position,
+            angle: 0.,
+            velocity: Vec2::default(),
+            angular_velocity: 0.,
+            mass,
+            inertia,
+            ghosts: ghosts.into_boxed_slice(),
+            spacing,
+        }
+    }
+
+    pub fn with_angle(mut self, angle: Real) -> Self {
+        self.angle = angle;
+        self
+    }
+
+    pub fn pose(&self) -> Pose {
+        Pose::new(self.position, self.angle)
+    }
+
+    /// how far apart the ghosts are
+    pub fn spacing(&self) -> Real {
+        self.spacing
+    }
+
+    pub(crate) fn ghost_count(&self) -> usize {
+        self.ghosts.len()
+    }
+
+    pub(crate) fn ghosts_world(&self) -> impl Iterator<Item = Vec2> + '_ {
+        let pose = self.pose();
+        self.ghosts.iter().map(move |ghost| pose.to_world(*ghost))
+    }
+
+    /// velocity of the body's material at a world point
+    pub fn velocity_at(&self, world: Vec2) -> Vec2 {
+        let r = world - self.position;
+        self.velocity
+            + Vec2 {
+                x: -self.angular_velocity * r.y,
+                y: self.angular_velocity * r.x,
+            }
+    }
+
+    pub(crate) fn apply_impulse(&mut self, impulse: Vec2, at: Vec2) {
+        self.velocity += impulse / self.mass;
+        self.angular_velocity += (at - self.position).cross(impulse) / self.inertia;
+    }
+
+    /// for when a pile of impulses has already been summed up about the centre of mass
+    pub(crate) fn apply_impulses(&mut self, linear: Vec2, angular: Real) {
+        self.velocity += linear / self.mass;
+        self.angular_velocity += angular / self.inertia;
+    }
+
+    /// semi implicit euler, same as the particles get
+    pub(crate) fn integrate(&mut self, force: Vec2, torque: Real, gravity: Vec2, delta: Real) {
+        self.velocity += (force / self.mass + gravity) * delta;
+        self.angular_velocity += torque / self.inertia * delta;
+        self.position += self.velocity * delta;
+        self.angle += self.angular_velocity * delta;
+    }
+
+    /// kicks a particle out of the body. Gives back the impulse the particle got so the caller
+    /// can hand the opposite of it to the body, along with where it happened.
+    pub(crate) fn collide_particle(
+        &self,
+        before: &Pose,
+        delta: Real,
+        restitution: Real,
+        pos: &mut Vec2,
+        vel: &mut Vec2,
+    ) -> Option<(Vec2, Vec2)> {
+        let old_velocity = *vel;
+        let after = self.pose();
+        collide_with_shape(
+            &self.shape,
+            false,
+            before,
+            &after,
+            delta,
+            restitution,
+            pos,
+            vel,
+        )
+        .then(|| (*vel - old_velocity, *pos))
+    }
+
+    /// keeps the body inside the window, bouncing off the edges with a proper impulse so it
+    /// picks up some spin
+    pub(crate) fn collide_walls(&mut self, size: winit::dpi::PhysicalSize<u32>, restitution: Real) {
+        let margin = self.spacing / 2.;
+        let walls = [
+            (Vec2 { x: 1., y: 0. }, margin),
+            (Vec2 { x: -1., y: 0. }, margin - size.width as Real),
+            (Vec2 { x: 0., y: 1. }, margin),
+            (Vec2 { x: 0., y: -1. }, margin - size.height as Real),
+        ];
+
+        for (normal, offset) in walls {
+            // deepest ghost through this wall, distance is measured along the inward normal
+            let Some((depth, point)) = self
+                .ghosts_world()
+                .map(|ghost| (ghost.dot(normal) - offset, ghost))
+                .min_by(|a, b| a.0.total_cmp(&b.0))
+            else {
+                continue;
+            };
+            if depth >= 0. {
+                continue;
+            }
+
+            self.position -= normal * depth;
+            let point = point - normal * depth;
+
+            let into_wall = self.velocity_at(point).dot(normal);
+            if into_wall < 0. {
+                let r = point - self.position;
+                let r_cross_n = r.cross(normal);
+                let effective_mass = 1. / self.mass + r_cross_n * r_cross_n / self.inertia;
+                let impulse = -(1. + restitution) * into_wall / effective_mass;
+                self.apply_impulse(normal * impulse, point);
+            }
+        }
+    }
+}
+
+fn local_bounds(shape: &Shape) -> (Vec2, Vec2) {
+    match shape {
+        Shape::Circle { radius } => (
+            Vec2 {
+                x: -radius,
+                y: -radius,
+            },
+            Vec2 {
+                x: *radius,
+                y: *radius,
+            },
+        ),
+        Shape::Rect { half_extents } => (-*half_extents, *half_extents),
+        Shape::Polygon(points) => points.iter().fold(
+            (
+                Vec2 {
+                    x: Real::MAX,
+                    y: Real::MAX,
+                },
+                Vec2 {
+                    x: Real::MIN,
+                    y: Real::MIN,
+                },
+            ),
+            |(min, max), point| {
+                (
+                    Vec2 {
+                        x: min.x.min(point.x),
+                        y: min.y.min(point.y),
+                    },
+                    Vec2 {
+                        x: max.x.max(point.x),
+                        y: max.y.max(point.y),
+                    },
+                )
+            },
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mass_follows_density_and_area() {
+        let shape = Shape::Rect {
+            half_extents: Vec2 { x: 20., y: 10. },
+        };
+        let wood = RigidBody::new(shape.clone(), 0.5, Vec2::default(), 5.);
+        let steel = RigidBody::new(shape, 8., Vec2::default(), 5.);
+
+        // 40x20 box with 5 pixel spacing is 8x4 ghosts
+        assert!((wood.mass - 16.).abs() < 1e-4);
+        assert!((steel.mass - 256.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn off_centre_impulse_spins_the_body() {
+        let mut body = RigidBody::new(
+            Shape::Rect {
+                half_extents: Vec2 { x: 20., y: 10. },
+            },
+            1.,
+            Vec2::default(),
+            5.,
+        );
+        body.apply_impulse(Vec2 { x: 0., y: 10. }, Vec2 { x: 20., y: 0. });
+
+        assert!(body.velocity.y > 0.);
+        assert!(body.angular_velocity > 0.);
+    }
+
+    #[test]
+    fn walls_keep_the_body_in() {
+        let size = winit::dpi::PhysicalSize::new(400, 400);
+        let mut body = RigidBody::new(
+            Shape::Circle { radius: 20. },
+            1.,
+            Vec2 { x: 200., y: 390. },
+            5.,
+        );
+        body.velocity = Vec2 { x: 0., y: 100. };
+        body.collide_walls(size, 0.5);
+
+        assert!(body.ghosts_world().all(|ghost| ghost.y <= 400.));
+        assert!(body.velocity.y < 0.);
+    }
+}
@@ -0,0 +1,232 @@
+use crate::fluid_sim::{
+    FluidSim,
+    boundary::{Boundary, Keyframe, Motion, Pose, Shape},
+    forces::{Drag, Point, Turbulence, Vortex},
+    real::{Real, consts::PI},
+    rigid_body::RigidBody,
+    vec2::Vec2,
+};
+
+/// starting setups for the sim, picked with `--scene <name>`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Scene {
+    /// particles thrown everywhere, the original
+    #[default]
+    Random,
+    /// a paddle sliding back and forth through the bottom of the window, past a ramp
+    Paddle,
+    /// a blade spinning around a hub in the middle of the window
+    Mixer,
+    /// a piston shoving the fluid against the right wall
+    Piston,
+    /// a tank being shaken side to side
+    ShakingTank,
+    /// a wooden block, a wooden disc and a steel disc dropped into the fluid
+    Floaters,
+    /// everything getting sucked round and round into the middle
+    Whirlpool,
+    /// a gale blowing across the window with gusts all over the place
+    Storm,
+}
+
+impl Scene {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "random" => Some(Scene::Random),
+            "paddle" => Some(Scene::Paddle),
+            "mixer" => Some(Scene::Mixer),
+            "piston" => Some(Scene::Piston),
+            "shaking-tank" => Some(Scene::ShakingTank),
+            "floaters" => Some(Scene::Floaters),
+            "whirlpool" => Some(Scene::Whirlpool),
+            "storm" => Some(Scene::Storm),
+            _ => None,
+        }
+    }
+
+    pub fn build(self, size: winit::dpi::PhysicalSize<u32>) -> FluidSim {
+        let width = size.width as Real;
+        let height = size.height as Real;
+        let center = Vec2 {
+            x: width / 2.,
+            y: height / 2.,
+        };
+
+        match self {
+            Scene::Random => FluidSim::new_rand(size),
+            Scene::Paddle => {
+                let mut sim = FluidSim::new_rand(size);
+                let y = height * 0.8;
+                sim.add_boundary(Boundary::solid(
+                    Shape::Rect {
+                        half_extents: Vec2 {
+                            x: 15.,
+                            y: height * 0.2,
+                        },
+                    },
+                    Motion::scripted(move |t| {
+                        let x = center.x + width * 0.35 * (t * 1.5).sin();
+                        Pose::new(Vec2 { x, y }, 0.)
+                    }),
+                ));
+                sim.add_boundary(Boundary::solid(
+                    Shape::Polygon(vec![
+                        Vec2 { x: 0., y: height },
+                        Vec2 {
+                            x: width * 0.25,
+                            y: height,
+                        },
+                        Vec2 {
+                            x: 0.,
+                            y: height * 0.75,
+                        },
+                    ]),
+                    Motion::Static(Pose::default()),
+                ));
+                sim
+            }
+            Scene::Mixer => {
+                let mut sim = FluidSim::new_rand(size);
+                sim.add_boundary(Boundary::solid(
+                    Shape::Rect {
+                        half_extents: Vec2 {
+                            x: width.min(height) * 0.3,
+                            y: 10.,
+                        },
+                    },
+                    Motion::scripted(move |t| Pose::new(center, t * PI)),
+                ));
+                sim.add_boundary(Boundary::solid(
+                    Shape::Circle { radius: 25. },
+                    Motion::Static(Pose::new(center, 0.)),
+                ));
+                sim
+            }
+            Scene::Piston => {
+                let mut sim = FluidSim::new_rand(size);
+                let half_width = width * 0.1;
+                let rest = Pose::new(
+                    Vec2 {
+                        x: -half_width,
+                        y: center.y,
+                    },
+                    0.,
+                );
+                let pushed = Pose::new(
+                    Vec2 {
+                        x: width * 0.5,
+                        y: center.y,
+                    },
+                    0.,
+                );
+                sim.add_boundary(Boundary::solid(
+                    Shape::Rect {
+                        half_extents: Vec2 {
+                            x: half_width,
+                            y: height,
+                        },
+                    },
+                    Motion::Keyframes {
+                        frames: vec![
+                            Keyframe {
+                                time: 0.,
+                                pose: rest,
+                            },
+                            Keyframe {
+                                time: 2.,
+                                pose: rest,
+                            },
+                            Keyframe {
+                                time: 3.,
+                                pose: pushed,
+                            },
+                            Keyframe {
+                                time: 5.,
+                                pose: rest,
+                            },
+                        ],
+                        looping: true,
+                    },
+                ));
+                sim
+            }
+            Scene::ShakingTank => {
+                let half_extents = Vec2 {
+                    x: width * 0.3,
+                    y: height * 0.4,
+                };
+                let mut sim = FluidSim::new_rand_in(center - half_extents, center + half_extents);
+                sim.add_boundary(Boundary::container(
+                    Shape::Rect { half_extents },
+                    Motion::scripted(move |t| {
+                        let shake = Vec2 {
+                            x: width * 0.08 * (t * 4.).sin(),
+                            y: 0.,
+                        };
+                        Pose::new(center + shake, 0.)
+                    }),
+                ));
+                sim
+            }
+            Scene::Floaters => {
+                let mut sim = FluidSim::new_rand(size);
+                let spacing = sim.particle_spacing(size);
+                let drop_height = height * 0.15;
+
+                sim.add_body(
+                    RigidBody::new(
+                        Shape::Rect {
+                            half_extents: Vec2 { x: 50., y: 25. },
+                        },
+                        0.5,
+                        Vec2 {
+                            x: width * 0.25,
+                            y: drop_height,
+                        },
+                        spacing,
+                    )
+                    .with_angle(0.3),
+                );
+                sim.add_body(RigidBody::new(
+                    Shape::Circle { radius: 30. },
+                    0.6,
+                    Vec2 {
+                        x: width * 0.5,
+                        y: drop_height,
+                    },
+                    spacing,
+                ));
+                sim.add_body(RigidBody::new(
+                    Shape::Circle { radius: 30. },
+                    7.8,
+                    Vec2 {
+                        x: width * 0.75,
+                        y: drop_height,
+                    },
+                    spacing,
+                ));
+                sim
+            }
+            Scene::Whirlpool => {
+                let mut sim = FluidSim::new_rand(size);
+                let radius = width.min(height) * 0.45;
+                sim.add_force(Vortex {
+                    position: center,
+                    radius,
+                    strength: 900.,
+                });
+                sim.add_force(Point::attractor(center, radius, 300.));
+                // keeps an eye open in the middle instead of everything piling up on one spot
+                sim.add_force(Point::repulsor(center, radius * 0.15, 600.));
+                sim.add_force(Drag { coefficient: 0.3 });
+                sim
+            }
+            Scene::Storm => {
+                let mut sim = FluidSim::new_rand(size);
+                sim.set_gravity(Vec2 { x: 150., y: 300. });
+                sim.add_force(Turbulence::default());
+                sim
+            }
+        }
+    }
+}
@@ -0,0 +1,753 @@
+use crate::fluid_sim::{
+    FluidSim, SolverMode,
+    contour::{self, Contour, ContourConfig},
+    flow::VelocityGrid,
+    gpu::{self, GpuSim},
+    lbm::{Lbm, LbmConfig},
+    real::Real,
+    scene::Scene,
+    sph::{Wcsph, WcsphConfig},
+    stable_fluids::{StableFluids, StableFluidsConfig},
+    vec2::Vec2,
+};
+use std::ops::RangeInclusive;
+
+/// anything that can be stepped forward and drawn, particles or grid. The viewer and the
+/// headless runner only ever see this, so they don't care which solver is underneath.
+pub trait Simulation {
+    /// move on by `delta` seconds, `size` is the window the sim lives in
+    fn step(&mut self, delta: Real, size: winit::dpi::PhysicalSize<u32>);
+
+    /// what there is to draw right now
+    fn view(&self) -> View<'_>;
+
+    /// one line on how the last step went
+    fn diagnostics(&self) -> String;
+
+    /// back to how it was before it was first stepped
+    fn reset(&mut self);
+
+    /// a copy of the sim as it is right now, that can be swapped back in later
+    fn snapshot(&self) -> Box<dyn Simulation>;
+
+    /// the knobs that can be turned while it runs
+    fn parameters(&self) -> Vec<Parameter>;
+
+    /// turns one of the knobs from `parameters`, names it doesn't know get ignored
+    fn set_parameter(&mut self, name: &str, value: Real);
+
+    /// something poking at the fluid until it gets replaced or taken away with `None`.
+    /// `size` is the window the position was measured in. Sims that can't be poked ignore it.
+    fn interact(
+        &mut self,
+        _interaction: Option<Interaction>,
+        _size: winit::dpi::PhysicalSize<u32>,
+    ) {
+    }
+
+    /// one number per particle, in the same order `view` draws them. Body ghosts are left off
+    /// the end, and grid sims don't have anything to give.
+    fn scalars(&self, _quantity: Quantity, _size: winit::dpi::PhysicalSize<u32>) -> Vec<Real> {
+        Vec::new()
+    }
+
+    /// how far apart the particles sit in window pixels, the dots get drawn in proportion.
+    /// Grid sims don't have particles, so it doesn't matter what they say.
+    fn rest_spacing(&self, _size: winit::dpi::PhysicalSize<u32>) -> Real {
+        0.
+    }
+
+    /// half the spacing for every particle `view` draws, ghosts included, for when they
+    /// aren't all the same size. Empty when they are.
+    fn particle_radii(&self, _size: winit::dpi::PhysicalSize<u32>) -> Vec<Real> {
+        Vec::new()
+    }
+
+    /// outlines round the fluid, in window pixels. Only particle sims have a surface to find.
+    fn contours(
+        &self,
+        _config: &ContourConfig,
+        _size: winit::dpi::PhysicalSize<u32>,
+    ) -> Vec<Contour> {
+        Vec::new()
+    }
+
+    /// the flow averaged onto cells about `cell` pixels across. Grid sims just box their own
+    /// grid down.
+    fn velocity_grid(&self, cell: Real, size: winit::dpi::PhysicalSize<u32>) -> VelocityGrid {
+        match self.view() {
+            View::Field(field) => VelocityGrid::from_field(&field, cell, size),
+            View::Particles(_) | View::Resident(_) => VelocityGrid::default(),
+        }
+    }
+}
+
+/// something about each particle that it can be coloured by
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Quantity {
+    /// pixels per second
+    #[default]
+    Speed,
+    /// as a fraction of rest density, so 1 is where the fluid wants to be
+    Density,
+    /// what a weakly compressible fluid would make of the density. Never below 0, and
+    /// climbing much faster than the density does once things get squashed
+    Pressure,
+    /// which particle it is, the same however the sim shuffles them about, so it shows how
+    /// the fluid's got mixed up. Sims that never shuffle just use the slot.
+    Id,
+}
+
+impl Quantity {
+    pub const ALL: [Quantity; 4] = [
+        Quantity::Speed,
+        Quantity::Density,
+        Quantity::Pressure,
+        Quantity::Id,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|quantity| quantity.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Quantity::Speed => "speed",
+            Quantity::Density => "density",
+            Quantity::Pressure => "pressure",
+            Quantity::Id => "id",
+        }
+    }
+}
+
+/// a setting on a running sim, with the range it makes sense in
+#[derive(Clone, Debug, PartialEq)]
+pub struct Parameter {
+    pub name: &'static str,
+    pub value: Real,
+    pub range: RangeInclusive<Real>,
+    /// iteration counts and the like, where only whole numbers make sense
+    pub whole: bool,
+}
+
+impl Parameter {
+    pub fn new(name: &'static str, value: Real, range: RangeInclusive<Real>) -> Self {
+        Self {
+            name,
+            value,
+            range,
+            whole: false,
+        }
+    }
+
+    pub fn whole(name: &'static str, value: usize, range: RangeInclusive<usize>) -> Self {
+        Self {
+            name,
+            value: value as Real,
+            range: *range.start() as Real..=*range.end() as Real,
+            whole: true,
+        }
+    }
+}
+
+/// a parameter's value back as a count
+pub(crate) fn count(value: Real) -> usize {
+    value.round().max(0.) as usize
+}
+
+/// what something poking the fluid does to it
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Tool {
+    /// pulls everything nearby in
+    #[default]
+    Attract,
+    /// shoves everything nearby away
+    Repel,
+    /// drags everything nearby along at the tool's own velocity
+    Stir,
+}
+
+/// a tool held somewhere in the window, the mouse for one
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Interaction {
+    pub tool: Tool,
+    /// window pixels
+    pub position: Vec2,
+    /// how fast the tool itself is going, pixels per second. Only stirring uses it
+    pub velocity: Vec2,
+    /// pixels
+    pub radius: Real,
+    /// pixels per second squared in the middle for attract and repel, for stir it's how many
+    /// times a second things catch up with the tool's velocity
+    pub strength: Real,
+}
+
+/// how a simulation wants to be drawn
+pub enum View<'a> {
+    /// points in window pixels
+    Particles(Points<'a>),
+    /// values on a grid stretched over the whole window
+    Field(FieldView<'a>),
+    /// points in window pixels that never leave the gpu, the renderer binds the sim's own
+    /// buffer instead of having them copied over
+    Resident(&'a GpuSim),
+}
+
+/// particle positions in window pixels, borrowed out of the sim so they can go to the gpu
+/// without being copied first. Anything the sim has to work out, like where the bodies'
+/// ghosts are right now, goes on the end.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Points<'a> {
+    pub fluid: &'a [Vec2],
+    /// which particle each of `fluid` is, for sims that move them between slots. Empty when
+    /// every particle is just its slot.
+    pub ids: &'a [u32],
+    pub ghosts: Vec<Vec2>,
+}
+
+impl Points<'_> {
+    pub fn len(&self) -> usize {
+        self.fluid.len() + self.ghosts.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Vec2> + '_ {
+        self.fluid.iter().chain(&self.ghosts).copied()
+    }
+
+    /// every point put at its id rather than its slot, so the same particle is at the same
+    /// place every frame whatever order the sim keeps them in. The ghosts stay on the end.
+    pub fn by_id(&self) -> Vec<Vec2> {
+        if self.ids.is_empty() {
+            return self.iter().collect();
+        }
+        let mut placed = vec![Vec2::default(); self.len()];
+        for (&id, &position) in self.ids.iter().zip(self.fluid) {
+            placed[id as usize] = position;
+        }
+        placed[self.fluid.len()..].copy_from_slice(&self.ghosts);
+        placed
+    }
+}
+
+/// a grid's worth of density and velocity, borrowed straight out of the sim. The arrays can
+/// have a ring of `border` cells around the outside that aren't meant to be drawn.
+#[derive(Copy, Clone, Debug)]
+pub struct FieldView<'a> {
+    pub columns: usize,
+    pub rows: usize,
+    pub border: usize,
+    pub density: &'a [Real],
+    pub velocity_x: &'a [Real],
+    pub velocity_y: &'a [Real],
+    /// cells that are solid rather than fluid, if the sim has any
+    pub obstacles: Option<&'a [bool]>,
+}
+
+impl FieldView<'_> {
+    fn index(&self, x: usize, y: usize) -> usize {
+        (y + self.border) * (self.columns + 2 * self.border) + x + self.border
+    }
+
+    /// `x` and `y` count from the top left drawable cell
+    pub fn density(&self, x: usize, y: usize) -> Real {
+        self.density[self.index(x, y)]
+    }
+
+    pub fn velocity(&self, x: usize, y: usize) -> Vec2 {
+        let i = self.index(x, y);
+        Vec2 {
+            x: self.velocity_x[i],
+            y: self.velocity_y[i],
+        }
+    }
+
+    pub fn is_solid(&self, x: usize, y: usize) -> bool {
+        self.obstacles
+            .is_some_and(|obstacles| obstacles[self.index(x, y)])
+    }
+
+    /// how fast the fluid is spinning around a cell, anticlockwise on screen is positive
+    pub fn vorticity(&self, x: usize, y: usize) -> Real {
+        let left = self.velocity(x.saturating_sub(1), y);
+        let right = self.velocity((x + 1).min(self.columns - 1), y);
+        let up = self.velocity(x, y.saturating_sub(1));
+        let down = self.velocity(x, (y + 1).min(self.rows - 1));
+        // y points down the screen, so this comes out flipped from the usual curl
+        (up.x - down.x) - (right.y - left.y)
+    }
+}
+
+/// every solver there is, particle or grid, in the order the viewer cycles through them
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Solver {
+    /// the particle sim, with whichever solver it's been given
+    Particles(SolverMode),
+    /// weakly compressible sph, a step at a time with no pressure solve
+    Wcsph,
+    /// the same sph in compute shaders, the particles stay on the gpu
+    Gpu,
+    /// jos stam's stable fluids on a grid
+    StableFluids,
+    /// d2q9 lattice boltzmann, flowing past a cylinder
+    Lbm,
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Solver::Particles(SolverMode::default())
+    }
+}
+
+impl Solver {
+    pub const ALL: [Solver; 9] = [
+        Solver::Particles(SolverMode::Explicit),
+        Solver::Particles(SolverMode::Pbf),
+        Solver::Particles(SolverMode::Dfsph),
+        Solver::Particles(SolverMode::Flip),
+        Solver::Particles(SolverMode::Apic),
+        Solver::Wcsph,
+        Solver::Gpu,
+        Solver::StableFluids,
+        Solver::Lbm,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|solver| solver.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Solver::Particles(mode) => mode.name(),
+            Solver::Wcsph => "wcsph",
+            Solver::Gpu => "gpu",
+            Solver::StableFluids => "stable-fluids",
+            Solver::Lbm => "lbm",
+        }
+    }
+
+    /// the one after this in `ALL`, wrapping round
+    pub fn next(self) -> Self {
+        let i = Self::ALL
+            .iter()
+            .position(|solver| *solver == self)
+            .unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+}
+
+/// everything that goes into building a sim from scratch, so one can be swapped for another
+/// while the window's open
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Setup {
+    /// only the particle solvers use it
+    pub scene: Scene,
+    pub solver: Solver,
+    pub grid: StableFluidsConfig,
+    pub lbm: LbmConfig,
+    /// for both the cpu and the gpu sph
+    pub wcsph: WcsphConfig,
+    /// swaps out the particles' usual straight down gravity, in pixels per second squared
+    pub gravity: Option<Vec2>,
+    /// steps between the particle sim's spatial sorts, 0 for never, when the command line said
+    pub sort_every: Option<usize>,
+}
+
+impl Setup {
+    pub fn build(&self, size: winit::dpi::PhysicalSize<u32>) -> Box<dyn Simulation> {
+        match self.solver {
+            Solver::Particles(mode) => {
+                let mut sim = self.scene.build(size);
+                sim.set_solver(mode);
+                if let Some(gravity) = self.gravity {
+                    sim.set_gravity(gravity);
+                }
+                if let Some(sort_every) = self.sort_every {
+                    sim.sort_every = sort_every;
+                }
+                Box::new(sim)
+            }
+            Solver::StableFluids => {
+                Box::new(StableFluids::new(self.grid, size).with_default_emitters())
+            }
+            Solver::Wcsph => {
+                let (positions, velocities, gravity) = self.fluid(size);
+                Box::new(Wcsph::new(self.wcsph, positions, velocities, gravity))
+            }
+            Solver::Gpu => match gpu::request_device(false).or_else(|| gpu::request_device(true)) {
+                Some((device, queue)) => self.build_on(size, &device, &queue),
+                None => {
+                    eprintln!("no gpu to run the gpu solver on, running it on the cpu instead");
+                    Setup {
+                        solver: Solver::Wcsph,
+                        ..*self
+                    }
+                    .build(size)
+                }
+            },
+            Solver::Lbm => Box::new(Lbm::new(self.lbm, size).with_cylinder()),
+        }
+    }
+
+    /// same as `build`, but the gpu solver runs on the device it's given so whatever draws
+    /// it can use its buffers
+    pub fn build_on(
+        &self,
+        size: winit::dpi::PhysicalSize<u32>,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Box<dyn Simulation> {
+        if self.solver != Solver::Gpu {
+            return self.build(size);
+        }
+        let (positions, velocities, gravity) = self.fluid(size);
+        Box::new(GpuSim::new(
+            device, queue, self.wcsph, positions, velocities, gravity, size,
+        ))
+    }
+
+    /// the scene's particles and gravity for the sph solvers. The scene's bodies and walls
+    /// don't come along, only its fluid.
+    fn fluid(&self, size: winit::dpi::PhysicalSize<u32>) -> (Vec<Vec2>, Vec<Vec2>, Vec2) {
+        let scene = self.scene.build(size);
+        (
+            scene.current_positions.to_vec(),
+            scene.current_velocities.to_vec(),
+            self.gravity.unwrap_or(scene.gravity()),
+        )
+    }
+}
+
+impl Simulation for FluidSim {
+    fn step(&mut self, delta: Real, size: winit::dpi::PhysicalSize<u32>) {
+        self.update(delta, size);
+    }
+
+    fn view(&self) -> View<'_> {
+        View::Particles(self.points())
+    }
+
+    fn diagnostics(&self) -> String {
+        self.diagnostics().to_string()
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        let gravity = self.gravity();
+        let mut parameters = vec![
+            Parameter::new("gravity x", gravity.x, -1000.0..=1000.),
+            Parameter::new("gravity y", gravity.y, -1000.0..=1000.),
+            Parameter::new("decay factor", self.decay_factor, 0.0..=1.),
+        ];
+        parameters.extend(match self.solver() {
+            SolverMode::Explicit => self.explicit.parameters(),
+            SolverMode::Pbf => self.pbf.parameters(),
+            SolverMode::Dfsph => self.dfsph.parameters(),
+            SolverMode::Flip | SolverMode::Apic => self.flip.parameters(),
+        });
+        parameters
+    }
+
+    fn set_parameter(&mut self, name: &str, value: Real) {
+        let gravity = self.gravity();
+        match name {
+            "gravity x" => self.set_gravity(Vec2 {
+                x: value,
+                ..gravity
+            }),
+            "gravity y" => self.set_gravity(Vec2 {
+                y: value,
+                ..gravity
+            }),
+            "decay factor" => self.decay_factor = value,
+            _ => match self.solver() {
+                SolverMode::Explicit => self.explicit.set_parameter(name, value),
+                SolverMode::Pbf => self.pbf.set_parameter(name, value),
+                SolverMode::Dfsph => self.dfsph.set_parameter(name, value),
+                SolverMode::Flip | SolverMode::Apic => self.flip.set_parameter(name, value),
+            },
+        }
+    }
+
+    fn reset(&mut self) {
+        self.reset();
+    }
+
+    fn snapshot(&self) -> Box<dyn Simulation> {
+        Box::new(self.clone())
+    }
+
+    fn interact(&mut self, interaction: Option<Interaction>, _size: winit::dpi::PhysicalSize<u32>) {
+        self.poke(interaction);
+    }
+
+    fn scalars(&self, quantity: Quantity, size: winit::dpi::PhysicalSize<u32>) -> Vec<Real> {
+        self.scalars(quantity, size)
+    }
+
+    fn rest_spacing(&self, size: winit::dpi::PhysicalSize<u32>) -> Real {
+        self.rest_spacing(size)
+    }
+
+    fn particle_radii(&self, size: winit::dpi::PhysicalSize<u32>) -> Vec<Real> {
+        self.particle_radii(size)
+    }
+
+    fn contours(
+        &self,
+        config: &ContourConfig,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> Vec<Contour> {
+        contour::extract(&self.current_positions, config, size)
+    }
+
+    fn velocity_grid(&self, cell: Real, size: winit::dpi::PhysicalSize<u32>) -> VelocityGrid {
+        VelocityGrid::from_particles(
+            &self.current_positions,
+            &self.current_velocities,
+            cell,
+            size,
+        )
+    }
+}
+
+impl Simulation for StableFluids {
+    fn step(&mut self, delta: Real, _size: winit::dpi::PhysicalSize<u32>) {
+        self.update(delta);
+    }
+
+    fn view(&self) -> View<'_> {
+        View::Field(self.field())
+    }
+
+    fn diagnostics(&self) -> String {
+        self.summary()
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        self.config.parameters()
+    }
+
+    fn set_parameter(&mut self, name: &str, value: Real) {
+        self.config.set_parameter(name, value);
+    }
+
+    fn reset(&mut self) {
+        self.reset();
+    }
+
+    fn snapshot(&self) -> Box<dyn Simulation> {
+        Box::new(self.clone())
+    }
+
+    fn interact(&mut self, interaction: Option<Interaction>, size: winit::dpi::PhysicalSize<u32>) {
+        self.poke(interaction, size);
+    }
+}
+
+impl Simulation for Lbm {
+    fn step(&mut self, delta: Real, _size: winit::dpi::PhysicalSize<u32>) {
+        self.update(delta);
+    }
+
+    fn view(&self) -> View<'_> {
+        View::Field(self.field())
+    }
+
+    fn diagnostics(&self) -> String {
+        self.summary()
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        self.config.parameters()
+    }
+
+    fn set_parameter(&mut self, name: &str, value: Real) {
+        self.config.set_parameter(name, value);
+    }
+
+    fn reset(&mut self) {
+        self.reset();
+    }
+
+    fn snapshot(&self) -> Box<dyn Simulation> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_solver_has_a_name_that_finds_it_again() {
+        for solver in Solver::ALL {
+            assert_eq!(Solver::from_name(solver.name()), Some(solver));
+        }
+        assert_eq!(Solver::Lbm.next(), Solver::ALL[0]);
+    }
+
+    #[test]
+    fn reset_and_snapshots_put_things_back() {
+        let size = winit::dpi::PhysicalSize::new(200, 150);
+        let mut sims: Vec<(&str, Box<dyn Simulation>)> = Vec::new();
+        for mode in [
+            SolverMode::Explicit,
+            SolverMode::Pbf,
+            SolverMode::Dfsph,
+            SolverMode::Flip,
+            SolverMode::Apic,
+        ] {
+            let positions: Vec<Vec2> = (0..100)
+                .map(|i| Vec2 {
+                    x: 50. + (i % 10) as Real * 8.,
+                    y: 50. + (i / 10) as Real * 8.,
+                })
+                .collect();
+            let mut sim = FluidSim::from_particles(positions, vec![Vec2::default(); 100]);
+            sim.set_solver(mode);
+            sims.push((mode.name(), Box::new(sim)));
+        }
+        let grid = StableFluidsConfig {
+            columns: 20,
+            ..Default::default()
+        };
+        sims.push((
+            "stable-fluids",
+            Box::new(StableFluids::new(grid, size).with_default_emitters()),
+        ));
+        let lbm = LbmConfig {
+            columns: 20,
+            ..Default::default()
+        };
+        sims.push(("lbm", Box::new(Lbm::new(lbm, size).with_cylinder())));
+        let setup = Setup {
+            solver: Solver::Wcsph,
+            ..Default::default()
+        };
+        sims.push(("wcsph", setup.build(size)));
+        if let Some((device, queue)) = gpu::request_device(true) {
+            let setup = Setup {
+                solver: Solver::Gpu,
+                ..Default::default()
+            };
+            sims.push(("gpu", setup.build_on(size, &device, &queue)));
+        }
+
+        let drawn = |sim: &dyn Simulation| match sim.view() {
+            View::Particles(particles) => particles
+                .iter()
+                .map(|position| position.x + position.y)
+                .sum::<Real>(),
+            View::Field(field) => {
+                field.velocity_x.iter().sum::<Real>() + field.density.iter().sum::<Real>()
+            }
+            View::Resident(gpu) => gpu
+                .read_positions()
+                .iter()
+                .map(|position| position.x + position.y)
+                .sum::<Real>(),
+        };
+
+        for (name, mut sim) in sims {
+            let start = drawn(sim.as_ref());
+
+            sim.step(1. / 60., size);
+            let snapshot = sim.snapshot();
+            let stepped = drawn(sim.as_ref());
+            assert_ne!(start, stepped, "{name}");
+
+            sim.step(1. / 60., size);
+            assert_eq!(drawn(snapshot.as_ref()), stepped, "{name}");
+
+            sim.reset();
+            assert_eq!(drawn(sim.as_ref()), start, "{name}");
+        }
+    }
+
+    #[test]
+    fn every_particle_gets_a_scalar_and_grids_get_none() {
+        let size = winit::dpi::PhysicalSize::new(200, 150);
+        for solver in Solver::ALL {
+            let sim = Setup {
+                solver,
+                scene: Scene::Floaters,
+                ..Default::default()
+            }
+            .build(size);
+            for quantity in Quantity::ALL {
+                assert_eq!(Quantity::from_name(quantity.name()), Some(quantity));
+                let scalars = sim.scalars(quantity, size);
+                match sim.view() {
+                    // the floaters' ghosts don't get one
+                    View::Particles(particles) => {
+                        assert!(!scalars.is_empty());
+                        assert_eq!(scalars.len(), particles.fluid.len());
+                    }
+                    // no bodies come along to the sph solvers
+                    View::Resident(gpu) => assert_eq!(scalars.len(), gpu.len()),
+                    View::Field(_) => assert!(scalars.is_empty()),
+                }
+                assert!(scalars.iter().all(|scalar| scalar.is_finite()));
+            }
+        }
+    }
+
+    #[test]
+    fn ghosts_get_a_radius_of_their_own() {
+        let size = winit::dpi::PhysicalSize::new(200, 150);
+        for solver in [
+            Solver::Particles(SolverMode::Pbf),
+            Solver::Particles(SolverMode::Flip),
+        ] {
+            let floaters = Setup {
+                solver,
+                scene: Scene::Floaters,
+                ..Default::default()
+            }
+            .build(size);
+            let View::Particles(particles) = floaters.view() else {
+                panic!("{} should have particles", solver.name());
+            };
+            let radii = floaters.particle_radii(size);
+            assert_eq!(radii.len(), particles.len());
+            let fluid = floaters.scalars(Quantity::Id, size).len();
+            assert_eq!(radii[0], floaters.rest_spacing(size) / 2.);
+            assert!(radii[fluid..].iter().all(|radius| *radius > 0.));
+
+            let plain = Setup {
+                solver,
+                ..Default::default()
+            }
+            .build(size);
+            assert!(plain.particle_radii(size).is_empty());
+            assert!(plain.rest_spacing(size) > 0.);
+        }
+    }
+
+    #[test]
+    fn every_parameter_can_be_turned() {
+        let size = winit::dpi::PhysicalSize::new(200, 150);
+        for solver in Solver::ALL {
+            let setup = Setup {
+                solver,
+                scene: Scene::Paddle,
+                ..Default::default()
+            };
+            let mut sim = setup.build(size);
+            let parameters = sim.parameters();
+            assert!(!parameters.is_empty(), "{}", solver.name());
+
+            for parameter in parameters {
+                let value = *parameter.range.end();
+                sim.set_parameter(parameter.name, value);
+                let turned = sim
+                    .parameters()
+                    .into_iter()
+                    .find(|turned| turned.name == parameter.name)
+                    .unwrap();
+                assert_eq!(turned.value, value, "{} {}", solver.name(), parameter.name);
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! Reordering the particles along a z-order curve, so ones near each other in space end up
+//! near each other in memory too.
+
+use crate::fluid_sim::{real::Real, vec2::Vec2};
+use rayon::prelude::*;
+
+/// how finely positions get chopped up for their keys, in window pixels. Anything in the same
+/// square keeps the order it had.
+const KEY_CELL: Real = 4.;
+
+/// the old slot of every particle in its new order, sorted by where it sits on the curve
+pub(crate) fn z_order(positions: &[Vec2]) -> Vec<u32> {
+    let mut keyed: Vec<(u32, u32)> = positions
+        .par_iter()
+        .enumerate()
+        .map(|(i, position)| (key(*position), i as u32))
+        .collect();
+    keyed.par_sort_unstable();
+    keyed.into_par_iter().map(|(_, i)| i).collect()
+}
+
+/// puts `items` in the order `z_order` gave back
+pub(crate) fn permute<T: Copy + Send + Sync>(items: &mut [T], order: &[u32]) {
+    let shuffled: Vec<T> = order.par_iter().map(|&i| items[i as usize]).collect();
+    items.copy_from_slice(&shuffled);
+}
+
+/// the square `position` is in with the bits of its column and row interleaved
+fn key(position: Vec2) -> u32 {
+    // `as` saturates, so anything off the top left lands in the first square
+    let column = (position.x / KEY_CELL) as u16;
+    let row = (position.y / KEY_CELL) as u16;
+    spread(column) | spread(row) << 1
+}
+
+/// the bits of `value` with a zero between each of them
+fn spread(value: u16) -> u32 {
+    let mut value = value as u32;
+    value = (value | value << 8) & 0x00ff_00ff;
+    value = (value | value << 4) & 0x0f0f_0f0f;
+    value = (value | value << 2) & 0x3333_3333;
+    (value | value << 1) & 0x5555_5555
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_follow_the_curve() {
+        assert_eq!(spread(0b1011), 0b100_0101);
+        // the four squares of the first block go across then down
+        let corner = |x: Real, y: Real| key(Vec2 { x, y });
+        assert_eq!(corner(0., 0.), 0);
+        assert_eq!(corner(KEY_CELL, 0.), 1);
+        assert_eq!(corner(0., KEY_CELL), 2);
+        assert_eq!(corner(KEY_CELL, KEY_CELL), 3);
+        assert_eq!(corner(-50., -50.), 0);
+
+        let positions = [
+            Vec2 { x: 100., y: 100. },
+            Vec2 { x: 1., y: 1. },
+            Vec2 { x: 5., y: 1. },
+        ];
+        let order = z_order(&positions);
+        assert_eq!(order, [1, 2, 0]);
+        let mut names = ['a', 'b', 'c'];
+        permute(&mut names, &order);
+        assert_eq!(names, ['b', 'c', 'a']);
+    }
+}
@@ -0,0 +1,517 @@
+//! Bits the SPH style solvers share: kernels, the window walls and neighbour lists. Plus the
+//! weakly compressible SPH the gpu backend runs, done on the cpu.
+
+use crate::fluid_sim::{
+    contour::{self, Contour, ContourConfig},
+    flow::VelocityGrid,
+    forces::{ForceField, Point, Stir},
+    grid::NeighbourGrid,
+    real::{Real, consts::PI},
+    simulation::{Interaction, Parameter, Points, Quantity, Simulation, Tool, View},
+    vec2::Vec2,
+};
+use rayon::prelude::*;
+
+/// most substeps one step gets split into, past this the sim just runs slow
+pub(crate) const MAX_SUBSTEPS: usize = 16;
+
+/// the 2d poly6 and spiky kernels with their constants worked out once
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Kernel {
+    radius: Real,
+    radius_squared: Real,
+    poly6_scale: Real,
+    spiky_scale: Real,
+}
+
+impl Kernel {
+    pub(crate) fn new(radius: Real) -> Self {
+        Self {
+            radius,
+            radius_squared: radius * radius,
+            poly6_scale: 4. / (PI * radius.powi(8)),
+            spiky_scale: -30. / (PI * radius.powi(5)),
+        }
+    }
+
+    pub(crate) fn poly6(&self, distance_squared: Real) -> Real {
+        if distance_squared >= self.radius_squared {
+            return 0.;
+        }
+        let falloff = self.radius_squared - distance_squared;
+        self.poly6_scale * falloff * falloff * falloff
+    }
+
+    /// gradient of the spiky kernel with respect to the first particle, `offset` points from
+    /// the second particle to the first
+    pub(crate) fn spiky_gradient(&self, offset: Vec2) -> Vec2 {
+        let distance = offset.length();
+        if distance >= self.radius || distance < 1e-6 {
+            return Vec2::default();
+        }
+        let falloff = self.radius - distance;
+        offset * (self.spiky_scale * falloff * falloff / distance)
+    }
+}
+
+/// how much a window edge adds to a particle's density, and to its gradient along the edge's
+/// normal, if the edge were packed with particles at rest spacing. Tabled by distance from the
+/// edge since it only depends on that. Without this the fluid can't feel the floor and piles
+/// up on it.
+pub(crate) struct WallTable {
+    step: Real,
+    density: Vec<Real>,
+    gradient: Vec<Real>,
+}
+
+impl WallTable {
+    const SAMPLES: usize = 64;
+
+    fn new(kernel: &Kernel, spacing: Real) -> Self {
+        let step = kernel.radius / Self::SAMPLES as Real;
+        let across = (kernel.radius / spacing).ceil() as i32;
+        let deep = (kernel.radius / spacing).ceil() as i32;
+
+        let mut density = Vec::with_capacity(Self::SAMPLES + 1);
+        let mut gradient = Vec::with_capacity(Self::SAMPLES + 1);
+        for sample in 0..=Self::SAMPLES {
+            let distance = sample as Real * step;
+            let mut sample_density = 0.;
+            let mut sample_gradient = 0.;
+            for row in 0..deep {
+                for column in -across..=across {
+                    // x along the wall, y from the wall particle to the fluid particle
+                    let offset = Vec2 {
+                        x: column as Real * spacing,
+                        y: distance + (row as Real + 0.5) * spacing,
+                    };
+                    sample_density += kernel.poly6(offset.dot(offset));
+                    sample_gradient += kernel.spiky_gradient(offset).y;
+                }
+            }
+            density.push(sample_density);
+            gradient.push(sample_gradient);
+        }
+
+        Self {
+            step,
+            density,
+            gradient,
+        }
+    }
+
+    fn sample(&self, distance: Real) -> (Real, Real) {
+        let at = (distance.max(0.) / self.step).min(Self::SAMPLES as Real);
+        let below = (at as usize).min(Self::SAMPLES - 1);
+        let t = at - below as Real;
+        let lerp = |table: &[Real]| table[below] + (table[below + 1] - table[below]) * t;
+        (lerp(&self.density), lerp(&self.gradient))
+    }
+}
+
+pub(crate) struct Walls {
+    width: Real,
+    height: Real,
+    table: WallTable,
+}
+
+impl Walls {
+    pub(crate) fn new(kernel: &Kernel, spacing: Real, size: winit::dpi::PhysicalSize<u32>) -> Self {
+        Self {
+            width: size.width as Real,
+            height: size.height as Real,
+            table: WallTable::new(kernel, spacing),
+        }
+    }
+
+    /// every window edge within reach of `pos`, as its inward normal and what it adds to the
+    /// density and gradient
+    pub(crate) fn near(&self, pos: Vec2) -> impl Iterator<Item = (Vec2, Real, Real)> + '_ {
+        [
+            (Vec2 { x: 1., y: 0. }, pos.x),
+            (Vec2 { x: -1., y: 0. }, self.width - pos.x),
+            (Vec2 { x: 0., y: 1. }, pos.y),
+            (Vec2 { x: 0., y: -1. }, self.height - pos.y),
+        ]
+        .into_iter()
+        .filter(|(_, distance)| *distance < self.table.step * WallTable::SAMPLES as Real)
+        .map(|(normal, distance)| {
+            let (density, gradient) = self.table.sample(distance);
+            (normal, density, gradient)
+        })
+    }
+}
+
+/// density a square lattice at `spacing` works out to, counting the particle itself
+pub(crate) fn rest_density(kernel: &Kernel, spacing: Real) -> Real {
+    let reach = (kernel.radius / spacing).ceil() as i32;
+    let mut density = 0.;
+    for y in -reach..=reach {
+        for x in -reach..=reach {
+            let offset = Vec2 {
+                x: x as Real * spacing,
+                y: y as Real * spacing,
+            };
+            density += kernel.poly6(offset.dot(offset));
+        }
+    }
+    density
+}
+
+/// everything within `radius` of each position, not counting itself
+pub(crate) fn neighbours(radius: Real, positions: &[Vec2]) -> Vec<Vec<usize>> {
+    let grid = NeighbourGrid::new(radius, positions);
+    let radius_squared = radius * radius;
+    positions
+        .par_iter()
+        .enumerate()
+        .map(|(i, pos)| {
+            let mut found = Vec::new();
+            grid.for_each_near(*pos, |j| {
+                let offset = *pos - positions[j];
+                if j != i && offset.dot(offset) < radius_squared {
+                    found.push(j);
+                }
+            });
+            found
+        })
+        .collect()
+}
+
+/// for each position, everything in `others` within `radius` of it
+pub(crate) fn neighbours_among(
+    radius: Real,
+    positions: &[Vec2],
+    others: &[Vec2],
+) -> Vec<Vec<usize>> {
+    if others.is_empty() {
+        return vec![Vec::new(); positions.len()];
+    }
+
+    let grid = NeighbourGrid::new(radius, others);
+    let radius_squared = radius * radius;
+    positions
+        .par_iter()
+        .map(|pos| {
+            let mut found = Vec::new();
+            grid.for_each_near(*pos, |j| {
+                let offset = *pos - others[j];
+                if offset.dot(offset) < radius_squared {
+                    found.push(j);
+                }
+            });
+            found
+        })
+        .collect()
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WcsphConfig {
+    /// kernel radius, in pixels, and how big the gpu's sorting cells are
+    pub smoothing_radius: Real,
+    /// how far apart particles sit when the fluid is at rest, sets the rest density
+    pub particle_spacing: Real,
+    /// how hard squashed fluid pushes back, roughly the speed of sound squared
+    pub stiffness: Real,
+    /// pixels squared per second, evens out neighbours' velocities
+    pub viscosity: Real,
+    /// longest a substep can be in seconds, stiffer fluid needs shorter ones
+    pub max_step: Real,
+    /// how much speed a particle keeps bouncing off a wall
+    pub decay_factor: Real,
+}
+
+impl Default for WcsphConfig {
+    fn default() -> Self {
+        Self {
+            smoothing_radius: 20.,
+            particle_spacing: 8.,
+            stiffness: 1e6,
+            viscosity: 200.,
+            max_step: 1. / 240.,
+            decay_factor: 0.9,
+        }
+    }
+}
+
+impl WcsphConfig {
+    pub fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new("smoothing radius", self.smoothing_radius, 5.0..=60.),
+            Parameter::new("particle spacing", self.particle_spacing, 2.0..=30.),
+            Parameter::new("stiffness", self.stiffness, 1e4..=1e7),
+            Parameter::new("viscosity", self.viscosity, 0.0..=2000.),
+            Parameter::new("max step", self.max_step, 1e-4..=1. / 30.),
+            Parameter::new("decay factor", self.decay_factor, 0.0..=1.),
+        ]
+    }
+
+    pub fn set_parameter(&mut self, name: &str, value: Real) {
+        match name {
+            "smoothing radius" => self.smoothing_radius = value,
+            "particle spacing" => self.particle_spacing = value,
+            "stiffness" => self.stiffness = value,
+            "viscosity" => self.viscosity = value,
+            "max step" => self.max_step = value,
+            "decay factor" => self.decay_factor = value,
+            _ => {}
+        }
+    }
+
+    pub(crate) fn rest_density(&self) -> Real {
+        rest_density(&Kernel::new(self.smoothing_radius), self.particle_spacing)
+    }
+
+    /// the viscosity kernel's laplacian constant
+    pub(crate) fn laplacian(&self) -> Real {
+        40. / (PI * self.smoothing_radius.powi(5))
+    }
+
+    /// how many substeps `delta` gets split into so none is longer than `max_step`
+    pub(crate) fn substeps(&self, delta: Real) -> usize {
+        ((delta / self.max_step).ceil() as usize).clamp(1, MAX_SUBSTEPS)
+    }
+}
+
+/// what whatever's poking the fluid does to a particle at `position` going at `velocity`,
+/// the same fields the particle sim gets
+pub(crate) fn poke(interaction: Option<Interaction>, position: Vec2, velocity: Vec2) -> Vec2 {
+    let Some(Interaction {
+        tool,
+        position: at,
+        velocity: tool_velocity,
+        radius,
+        strength,
+    }) = interaction
+    else {
+        return Vec2::default();
+    };
+    match tool {
+        Tool::Attract => {
+            Point::attractor(at, radius, strength).acceleration(position, velocity, 0.)
+        }
+        Tool::Repel => Point::repulsor(at, radius, strength).acceleration(position, velocity, 0.),
+        Tool::Stir => Stir {
+            position: at,
+            radius,
+            velocity: tool_velocity,
+            rate: strength,
+        }
+        .acceleration(position, velocity, 0.),
+    }
+}
+
+/// weakly compressible sph, pressure straight from how squashed the fluid is. The same
+/// scheme `GpuSim` runs in compute shaders, for when there's no gpu and to check it against.
+/// Like it, only the fluid comes over from a scene, no bodies or walls.
+#[derive(Clone, Debug)]
+pub struct Wcsph {
+    pub config: WcsphConfig,
+    gravity: Vec2,
+    tool: Option<Interaction>,
+    positions: Vec<Vec2>,
+    velocities: Vec<Vec2>,
+    /// each particle's density and pressure as of the last substep
+    fluid: Vec<(Real, Real)>,
+    /// what it started from, for resetting
+    initial: (Vec<Vec2>, Vec<Vec2>),
+    steps: usize,
+    substeps: usize,
+}
+
+impl Wcsph {
+    pub fn new(
+        config: WcsphConfig,
+        positions: Vec<Vec2>,
+        velocities: Vec<Vec2>,
+        gravity: Vec2,
+    ) -> Self {
+        Self {
+            config,
+            gravity,
+            tool: None,
+            fluid: vec![(0., 0.); positions.len()],
+            initial: (positions.clone(), velocities.clone()),
+            positions,
+            velocities,
+            steps: 0,
+            substeps: 0,
+        }
+    }
+
+    fn substep(&mut self, delta: Real, size: winit::dpi::PhysicalSize<u32>) {
+        let config = self.config;
+        let kernel = Kernel::new(config.smoothing_radius);
+        let radius = config.smoothing_radius;
+        let rest_density = config.rest_density();
+        let laplacian = config.laplacian();
+        let (positions, velocities) = (&self.positions, &self.velocities);
+        let neighbours = neighbours(radius, positions);
+
+        // counts the particle itself, same as the rest density does
+        self.fluid = neighbours
+            .par_iter()
+            .enumerate()
+            .map(|(i, neighbours)| {
+                let density = kernel.poly6(0.)
+                    + neighbours
+                        .iter()
+                        .map(|&j| {
+                            let offset = positions[i] - positions[j];
+                            kernel.poly6(offset.dot(offset))
+                        })
+                        .sum::<Real>();
+                (density, config.stiffness * (density - rest_density).max(0.))
+            })
+            .collect();
+
+        let fluid = &self.fluid;
+        let (gravity, tool) = (self.gravity, self.tool);
+        let accelerations: Vec<Vec2> = neighbours
+            .par_iter()
+            .enumerate()
+            .map(|(i, neighbours)| {
+                let (density, pressure) = fluid[i];
+                let own = pressure / (density * density);
+                let pushed = neighbours.iter().fold(gravity, |acceleration, &j| {
+                    let offset = positions[i] - positions[j];
+                    let distance = offset.length();
+                    // something sitting right on top of it
+                    if distance < 1e-6 {
+                        return acceleration;
+                    }
+                    let (other_density, other_pressure) = fluid[j];
+                    acceleration
+                        - kernel.spiky_gradient(offset)
+                            * (own + other_pressure / (other_density * other_density))
+                        + (velocities[j] - velocities[i])
+                            * (config.viscosity * laplacian * (radius - distance) / other_density)
+                });
+                pushed + poke(tool, positions[i], velocities[i])
+            })
+            .collect();
+
+        // bounce off the window edges, losing a bit each time
+        let (width, height) = (size.width as Real, size.height as Real);
+        let bounce = |p: &mut Real, v: &mut Real, max: Real| {
+            if *p < 0. {
+                *p = 0.;
+                *v = v.abs() * config.decay_factor;
+            } else if *p > max {
+                *p = max;
+                *v = -v.abs() * config.decay_factor;
+            }
+        };
+        self.positions
+            .par_iter_mut()
+            .zip(self.velocities.par_iter_mut())
+            .zip(accelerations)
+            .for_each(|((pos, vel), acceleration)| {
+                *vel += acceleration * delta;
+                *pos += *vel * delta;
+                bounce(&mut pos.x, &mut vel.x, width);
+                bounce(&mut pos.y, &mut vel.y, height);
+            });
+    }
+}
+
+impl Simulation for Wcsph {
+    fn step(&mut self, delta: Real, size: winit::dpi::PhysicalSize<u32>) {
+        if delta <= 0. {
+            return;
+        }
+        let substeps = self.config.substeps(delta);
+        for _ in 0..substeps {
+            self.substep(delta / substeps as Real, size);
+        }
+        self.steps += 1;
+        self.substeps = substeps;
+    }
+
+    fn view(&self) -> View<'_> {
+        View::Particles(Points {
+            fluid: &self.positions,
+            ..Default::default()
+        })
+    }
+
+    fn diagnostics(&self) -> String {
+        format!(
+            "wcsph, {} particles, step {} in {} substeps",
+            self.positions.len(),
+            self.steps,
+            self.substeps
+        )
+    }
+
+    fn reset(&mut self) {
+        (self.positions, self.velocities) = self.initial.clone();
+        self.fluid = vec![(0., 0.); self.positions.len()];
+        self.steps = 0;
+        self.substeps = 0;
+    }
+
+    fn snapshot(&self) -> Box<dyn Simulation> {
+        Box::new(self.clone())
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        let mut parameters = vec![
+            Parameter::new("gravity x", self.gravity.x, -1000.0..=1000.),
+            Parameter::new("gravity y", self.gravity.y, -1000.0..=1000.),
+        ];
+        parameters.extend(self.config.parameters());
+        parameters
+    }
+
+    fn set_parameter(&mut self, name: &str, value: Real) {
+        match name {
+            "gravity x" => self.gravity.x = value,
+            "gravity y" => self.gravity.y = value,
+            _ => self.config.set_parameter(name, value),
+        }
+    }
+
+    fn interact(&mut self, interaction: Option<Interaction>, _size: winit::dpi::PhysicalSize<u32>) {
+        self.tool = interaction;
+    }
+
+    fn scalars(&self, quantity: Quantity, _size: winit::dpi::PhysicalSize<u32>) -> Vec<Real> {
+        let rest_density = self.config.rest_density();
+        match quantity {
+            Quantity::Speed => self
+                .velocities
+                .iter()
+                .map(|velocity| velocity.length())
+                .collect(),
+            Quantity::Density => self
+                .fluid
+                .iter()
+                .map(|(density, _)| density / rest_density)
+                .collect(),
+            Quantity::Pressure => self
+                .fluid
+                .iter()
+                .map(|(_, pressure)| pressure / (self.config.stiffness * rest_density))
+                .collect(),
+            // nothing gets shuffled, so the slot is the id
+            Quantity::Id => (0..self.positions.len()).map(|i| i as Real).collect(),
+        }
+    }
+
+    fn rest_spacing(&self, _size: winit::dpi::PhysicalSize<u32>) -> Real {
+        self.config.particle_spacing
+    }
+
+    fn contours(
+        &self,
+        config: &ContourConfig,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> Vec<Contour> {
+        contour::extract(&self.positions, config, size)
+    }
+
+    fn velocity_grid(&self, cell: Real, size: winit::dpi::PhysicalSize<u32>) -> VelocityGrid {
+        VelocityGrid::from_particles(&self.positions, &self.velocities, cell, size)
+    }
+}
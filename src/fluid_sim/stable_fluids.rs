@@ -0,0 +1,710 @@
+//! Stable fluids, after Jos Stam 1999 and his "Real-Time Fluid Dynamics for Games".
+//!
+//! Everything lives on a fixed grid instead of on particles. Velocity and dye get carried along
+//! by tracing each cell backwards through the flow (semi-Lagrangian advection), spread out with
+//! an implicit diffusion solve, and the velocity gets a pressure projection to keep it
+//! divergence free. None of it blows up however big the step is, it just gets blurrier.
+//!
+//! Distances are in cells and times in seconds, so velocities are cells per second. Every array
+//! has a ring of boundary cells around it, which is what the walls get written into.
+
+use crate::fluid_sim::{
+    forces::falloff,
+    real::Real,
+    simulation::{FieldView, Interaction, Parameter, Tool, count},
+    vec2::Vec2,
+};
+use rayon::prelude::*;
+
+/// how the diffusion and pressure solves get iterated
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum LinearSolver {
+    /// every cell updates from last pass's neighbours, so the rows can go in parallel
+    Jacobi,
+    /// updates in place, converges about twice as fast but has to go one cell at a time
+    #[default]
+    GaussSeidel,
+}
+
+impl LinearSolver {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "jacobi" => Some(LinearSolver::Jacobi),
+            "gauss-seidel" => Some(LinearSolver::GaussSeidel),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StableFluidsConfig {
+    /// cells across the window, the rows follow from its aspect ratio
+    pub columns: usize,
+    /// how quickly velocity spreads to its neighbours, cells squared per second
+    pub viscosity: Real,
+    /// same for the dye
+    pub diffusion: Real,
+    /// fraction of the dye that fades away every second
+    pub dissipation: Real,
+    /// passes for each diffusion and pressure solve
+    pub iterations: usize,
+    pub solver: LinearSolver,
+}
+
+impl Default for StableFluidsConfig {
+    fn default() -> Self {
+        Self {
+            columns: 160,
+            viscosity: 0.,
+            diffusion: 0.,
+            dissipation: 0.1,
+            iterations: 20,
+            solver: LinearSolver::default(),
+        }
+    }
+}
+
+impl StableFluidsConfig {
+    /// the column count and solver need the grid building again, so they aren't in here
+    pub fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new("viscosity", self.viscosity, 0.0..=1.),
+            Parameter::new("diffusion", self.diffusion, 0.0..=1.),
+            Parameter::new("dissipation", self.dissipation, 0.0..=2.),
+            Parameter::whole("iterations", self.iterations, 1..=80),
+        ]
+    }
+
+    pub fn set_parameter(&mut self, name: &str, value: Real) {
+        match name {
+            "viscosity" => self.viscosity = value,
+            "diffusion" => self.diffusion = value,
+            "dissipation" => self.dissipation = value,
+            "iterations" => self.iterations = count(value),
+            _ => {}
+        }
+    }
+}
+
+/// somewhere that keeps pouring dye in and pushing the fluid along
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Emitter {
+    /// as a fraction of the grid, (0, 0) is the top left
+    pub position: Vec2,
+    /// in cells
+    pub radius: Real,
+    /// dye added per second to every cell it covers
+    pub density: Real,
+    /// what the cells it covers get their velocity set to, cells per second
+    pub velocity: Vec2,
+}
+
+/// which velocity component an array is, since the walls flip the one pointing into them
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Mirror {
+    None,
+    X,
+    Y,
+}
+
+/// which of the sim's arrays a helper should work on
+#[derive(Copy, Clone, Debug)]
+enum Field {
+    Density,
+    VelocityX,
+    VelocityY,
+}
+
+/// grid size, and everything that only needs the size to work
+#[derive(Copy, Clone, Debug)]
+struct Dims {
+    columns: usize,
+    rows: usize,
+}
+
+impl Dims {
+    fn stride(&self) -> usize {
+        self.columns + 2
+    }
+
+    fn len(&self) -> usize {
+        self.stride() * (self.rows + 2)
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        y * self.stride() + x
+    }
+
+    /// fills in the boundary ring so the walls are solid. Velocity into a wall gets mirrored
+    /// so it cancels out there, everything else is just copied outwards.
+    fn set_boundary(&self, mirror: Mirror, field: &mut [Real]) {
+        let (columns, rows) = (self.columns, self.rows);
+        let flip_x = if mirror == Mirror::X { -1. } else { 1. };
+        let flip_y = if mirror == Mirror::Y { -1. } else { 1. };
+
+        for y in 1..=rows {
+            field[self.index(0, y)] = flip_x * field[self.index(1, y)];
+            field[self.index(columns + 1, y)] = flip_x * field[self.index(columns, y)];
+        }
+        for x in 1..=columns {
+            field[self.index(x, 0)] = flip_y * field[self.index(x, 1)];
+            field[self.index(x, rows + 1)] = flip_y * field[self.index(x, rows)];
+        }
+
+        let corner = |field: &[Real], a: usize, b: usize| 0.5 * (field[a] + field[b]);
+        field[self.index(0, 0)] = corner(field, self.index(1, 0), self.index(0, 1));
+        field[self.index(0, rows + 1)] =
+            corner(field, self.index(1, rows + 1), self.index(0, rows));
+        field[self.index(columns + 1, 0)] =
+            corner(field, self.index(columns, 0), self.index(columns + 1, 1));
+        field[self.index(columns + 1, rows + 1)] = corner(
+            field,
+            self.index(columns, rows + 1),
+            self.index(columns + 1, rows),
+        );
+    }
+
+    /// solves `c * x - a * (sum of x's neighbours) = source` for x
+    #[allow(clippy::too_many_arguments)]
+    fn solve(
+        &self,
+        mirror: Mirror,
+        x: &mut [Real],
+        source: &[Real],
+        a: Real,
+        c: Real,
+        iterations: usize,
+        solver: LinearSolver,
+    ) {
+        let stride = self.stride();
+        match solver {
+            LinearSolver::GaussSeidel => {
+                for _ in 0..iterations {
+                    for y in 1..=self.rows {
+                        for column in 1..=self.columns {
+                            let i = self.index(column, y);
+                            let around = x[i - 1] + x[i + 1] + x[i - stride] + x[i + stride];
+                            x[i] = (source[i] + a * around) / c;
+                        }
+                    }
+                    self.set_boundary(mirror, x);
+                }
+            }
+            LinearSolver::Jacobi => {
+                let mut next = x.to_vec();
+                for _ in 0..iterations {
+                    let old = &*x;
+                    next.par_chunks_mut(stride)
+                        .enumerate()
+                        .skip(1)
+                        .take(self.rows)
+                        .for_each(|(y, row)| {
+                            for (column, cell) in
+                                row.iter_mut().enumerate().skip(1).take(self.columns)
+                            {
+                                let i = y * stride + column;
+                                let around =
+                                    old[i - 1] + old[i + 1] + old[i - stride] + old[i + stride];
+                                *cell = (source[i] + a * around) / c;
+                            }
+                        });
+                    x.copy_from_slice(&next);
+                    self.set_boundary(mirror, x);
+                }
+            }
+        }
+    }
+
+    /// carries `from` along the velocity field by looking back to where each cell's contents
+    /// came from and sampling there
+    fn advect(
+        &self,
+        mirror: Mirror,
+        to: &mut [Real],
+        from: &[Real],
+        velocity_x: &[Real],
+        velocity_y: &[Real],
+        delta: Real,
+    ) {
+        let stride = self.stride();
+        let (columns, rows) = (self.columns as Real, self.rows as Real);
+        to.par_chunks_mut(stride)
+            .enumerate()
+            .skip(1)
+            .take(self.rows)
+            .for_each(|(y, row)| {
+                for (column, cell) in row.iter_mut().enumerate().skip(1).take(self.columns) {
+                    let i = y * stride + column;
+                    let back_x = (column as Real - delta * velocity_x[i]).clamp(0.5, columns + 0.5);
+                    let back_y = (y as Real - delta * velocity_y[i]).clamp(0.5, rows + 0.5);
+
+                    let (left, top) = (back_x as usize, back_y as usize);
+                    let (right_share, bottom_share) = (back_x - left as Real, back_y - top as Real);
+                    let sample = |x: usize, y: usize| from[y * stride + x];
+                    *cell = (1. - right_share)
+                        * ((1. - bottom_share) * sample(left, top)
+                            + bottom_share * sample(left, top + 1))
+                        + right_share
+                            * ((1. - bottom_share) * sample(left + 1, top)
+                                + bottom_share * sample(left + 1, top + 1));
+                }
+            });
+        self.set_boundary(mirror, to);
+    }
+
+    /// takes the divergent part out of the velocity by solving for the pressure that would
+    /// cancel it. `pressure` and `divergence` are just scratch space.
+    fn project(
+        &self,
+        velocity_x: &mut [Real],
+        velocity_y: &mut [Real],
+        pressure: &mut [Real],
+        divergence: &mut [Real],
+        iterations: usize,
+        solver: LinearSolver,
+    ) {
+        let stride = self.stride();
+        for y in 1..=self.rows {
+            for column in 1..=self.columns {
+                let i = self.index(column, y);
+                divergence[i] = -0.5
+                    * (velocity_x[i + 1] - velocity_x[i - 1] + velocity_y[i + stride]
+                        - velocity_y[i - stride]);
+                pressure[i] = 0.;
+            }
+        }
+        self.set_boundary(Mirror::None, divergence);
+        self.set_boundary(Mirror::None, pressure);
+
+        self.solve(
+            Mirror::None,
+            pressure,
+            divergence,
+            1.,
+            4.,
+            iterations,
+            solver,
+        );
+
+        for y in 1..=self.rows {
+            for column in 1..=self.columns {
+                let i = self.index(column, y);
+                velocity_x[i] -= 0.5 * (pressure[i + 1] - pressure[i - 1]);
+                velocity_y[i] -= 0.5 * (pressure[i + stride] - pressure[i - stride]);
+            }
+        }
+        self.set_boundary(Mirror::X, velocity_x);
+        self.set_boundary(Mirror::Y, velocity_y);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StableFluids {
+    pub config: StableFluidsConfig,
+    pub emitters: Vec<Emitter>,
+    dims: Dims,
+    /// whatever's poking the fluid, already turned into cells
+    poke: Option<Interaction>,
+
+    density: Vec<Real>,
+    velocity_x: Vec<Real>,
+    velocity_y: Vec<Real>,
+
+    /// last step's values, and scratch space for the solves
+    previous_density: Vec<Real>,
+    previous_x: Vec<Real>,
+    previous_y: Vec<Real>,
+}
+
+impl StableFluids {
+    /// an empty, still grid shaped like the window
+    pub fn new(config: StableFluidsConfig, size: winit::dpi::PhysicalSize<u32>) -> Self {
+        let columns = config.columns.max(1);
+        let rows = (columns as Real * size.height as Real / size.width.max(1) as Real).round();
+        let dims = Dims {
+            columns,
+            rows: (rows as usize).max(1),
+        };
+        let len = dims.len();
+
+        Self {
+            config,
+            emitters: Vec::new(),
+            dims,
+            poke: None,
+            density: vec![0.; len],
+            velocity_x: vec![0.; len],
+            velocity_y: vec![0.; len],
+            previous_density: vec![0.; len],
+            previous_x: vec![0.; len],
+            previous_y: vec![0.; len],
+        }
+    }
+
+    /// a plume coming up from the bottom with a cross current to knock it about
+    pub fn with_default_emitters(mut self) -> Self {
+        let speed = self.dims.columns as Real / 4.;
+        self.emitters = vec![
+            Emitter {
+                position: Vec2 { x: 0.5, y: 0.9 },
+                radius: self.dims.columns as Real / 40.,
+                density: 4.,
+                velocity: Vec2 { x: 0., y: -speed },
+            },
+            Emitter {
+                position: Vec2 { x: 0.05, y: 0.4 },
+                radius: self.dims.columns as Real / 60.,
+                density: 2.,
+                velocity: Vec2 {
+                    x: speed * 0.6,
+                    y: 0.,
+                },
+            },
+        ];
+        self
+    }
+
+    pub(crate) fn field(&self) -> FieldView<'_> {
+        FieldView {
+            columns: self.dims.columns,
+            rows: self.dims.rows,
+            border: 1,
+            density: &self.density,
+            velocity_x: &self.velocity_x,
+            velocity_y: &self.velocity_y,
+            obstacles: None,
+        }
+    }
+
+    /// empties the grid out, the emitters stay
+    pub(crate) fn reset(&mut self) {
+        for field in [
+            &mut self.density,
+            &mut self.velocity_x,
+            &mut self.velocity_y,
+            &mut self.previous_density,
+            &mut self.previous_x,
+            &mut self.previous_y,
+        ] {
+            field.fill(0.);
+        }
+    }
+
+    /// the grid gets stretched over the window whatever shape it is now, so the interaction
+    /// gets mapped through `size` rather than the size the grid was made for
+    pub(crate) fn poke(
+        &mut self,
+        interaction: Option<Interaction>,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) {
+        let scale = Vec2 {
+            x: self.dims.columns as Real / size.width.max(1) as Real,
+            y: self.dims.rows as Real / size.height.max(1) as Real,
+        };
+        self.poke = interaction.map(|interaction| Interaction {
+            // cell 1 is the first one inside the boundary ring, so its middle is at 0.5
+            position: interaction.position * scale + Vec2 { x: 0.5, y: 0.5 },
+            velocity: interaction.velocity * scale,
+            radius: interaction.radius * scale.x,
+            strength: match interaction.tool {
+                Tool::Stir => interaction.strength,
+                Tool::Attract | Tool::Repel => interaction.strength * scale.x,
+            },
+            ..interaction
+        });
+    }
+
+    pub(crate) fn summary(&self) -> String {
+        let dye: Real = self.density.iter().sum();
+        format!(
+            "stable fluids {}x{}: {} {:?} iterations, {dye:.1} dye",
+            self.dims.columns, self.dims.rows, self.config.iterations, self.config.solver
+        )
+    }
+
+    pub(crate) fn update(&mut self, delta: Real) {
+        let dims = self.dims;
+        let StableFluidsConfig {
+            viscosity,
+            diffusion,
+            dissipation,
+            iterations,
+            solver,
+            ..
+        } = self.config;
+
+        self.emit(delta);
+        self.push(delta);
+
+        // velocity: diffuse, make it divergence free, carry it along itself, and tidy up the
+        // divergence the advection put back in
+        std::mem::swap(&mut self.velocity_x, &mut self.previous_x);
+        std::mem::swap(&mut self.velocity_y, &mut self.previous_y);
+        self.diffuse(Mirror::X, viscosity, delta, Field::VelocityX);
+        self.diffuse(Mirror::Y, viscosity, delta, Field::VelocityY);
+        dims.project(
+            &mut self.velocity_x,
+            &mut self.velocity_y,
+            &mut self.previous_x,
+            &mut self.previous_y,
+            iterations,
+            solver,
+        );
+
+        std::mem::swap(&mut self.velocity_x, &mut self.previous_x);
+        std::mem::swap(&mut self.velocity_y, &mut self.previous_y);
+        dims.advect(
+            Mirror::X,
+            &mut self.velocity_x,
+            &self.previous_x,
+            &self.previous_x,
+            &self.previous_y,
+            delta,
+        );
+        dims.advect(
+            Mirror::Y,
+            &mut self.velocity_y,
+            &self.previous_y,
+            &self.previous_x,
+            &self.previous_y,
+            delta,
+        );
+        dims.project(
+            &mut self.velocity_x,
+            &mut self.velocity_y,
+            &mut self.previous_x,
+            &mut self.previous_y,
+            iterations,
+            solver,
+        );
+
+        // dye: diffuse, then carry it along the new velocity
+        std::mem::swap(&mut self.density, &mut self.previous_density);
+        self.diffuse(Mirror::None, diffusion, delta, Field::Density);
+        std::mem::swap(&mut self.density, &mut self.previous_density);
+        dims.advect(
+            Mirror::None,
+            &mut self.density,
+            &self.previous_density,
+            &self.velocity_x,
+            &self.velocity_y,
+            delta,
+        );
+
+        let fade = (1. - dissipation * delta).max(0.);
+        self.density.par_iter_mut().for_each(|d| *d *= fade);
+    }
+
+    /// spreads the previous values of `field` into the current ones. With no diffusion it's
+    /// just a copy, no point running the solve for that.
+    fn diffuse(&mut self, mirror: Mirror, rate: Real, delta: Real, field: Field) {
+        let (current, previous) = match field {
+            Field::Density => (&mut self.density, &self.previous_density),
+            Field::VelocityX => (&mut self.velocity_x, &self.previous_x),
+            Field::VelocityY => (&mut self.velocity_y, &self.previous_y),
+        };
+        if rate <= 0. {
+            current.copy_from_slice(previous);
+            return;
+        }
+        let a = delta * rate;
+        self.dims.solve(
+            mirror,
+            current,
+            previous,
+            a,
+            1. + 4. * a,
+            self.config.iterations,
+            self.config.solver,
+        );
+    }
+
+    /// whatever's poking the fluid gets its way with the velocities it can reach
+    fn push(&mut self, delta: Real) {
+        let Some(poke) = self.poke else {
+            return;
+        };
+        let dims = self.dims;
+        let reach = poke.radius.ceil() as isize;
+        let (cx, cy) = (poke.position.x as isize, poke.position.y as isize);
+
+        for y in (cy - reach).max(1)..=(cy + reach).min(dims.rows as isize) {
+            for x in (cx - reach).max(1)..=(cx + reach).min(dims.columns as isize) {
+                let offset = poke.position
+                    - Vec2 {
+                        x: x as Real,
+                        y: y as Real,
+                    };
+                let distance = offset.length();
+                if distance >= poke.radius || distance < 1e-6 {
+                    continue;
+                }
+                let weight = falloff(distance, poke.radius);
+                let i = dims.index(x as usize, y as usize);
+                let velocity = Vec2 {
+                    x: self.velocity_x[i],
+                    y: self.velocity_y[i],
+                };
+                let change = match poke.tool {
+                    Tool::Attract => offset / distance * (poke.strength * weight * delta),
+                    Tool::Repel => -offset / distance * (poke.strength * weight * delta),
+                    Tool::Stir => {
+                        (poke.velocity - velocity) * (poke.strength * weight * delta).min(1.)
+                    }
+                };
+                self.velocity_x[i] += change.x;
+                self.velocity_y[i] += change.y;
+            }
+        }
+    }
+
+    fn emit(&mut self, delta: Real) {
+        let dims = self.dims;
+        for emitter in &self.emitters {
+            let centre = Vec2 {
+                x: emitter.position.x * dims.columns as Real + 0.5,
+                y: emitter.position.y * dims.rows as Real + 0.5,
+            };
+            let reach = emitter.radius.ceil() as isize;
+            let (cx, cy) = (centre.x as isize, centre.y as isize);
+
+            for y in (cy - reach).max(1)..=(cy + reach).min(dims.rows as isize) {
+                for x in (cx - reach).max(1)..=(cx + reach).min(dims.columns as isize) {
+                    let cell = Vec2 {
+                        x: x as Real,
+                        y: y as Real,
+                    };
+                    if (cell - centre).length() > emitter.radius {
+                        continue;
+                    }
+                    let i = dims.index(x as usize, y as usize);
+                    self.density[i] += emitter.density * delta;
+                    self.velocity_x[i] = emitter.velocity.x;
+                    self.velocity_y[i] = emitter.velocity.y;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(solver: LinearSolver) -> StableFluids {
+        let config = StableFluidsConfig {
+            columns: 32,
+            solver,
+            iterations: 40,
+            ..Default::default()
+        };
+        StableFluids::new(config, winit::dpi::PhysicalSize::new(400, 400))
+    }
+
+    fn total_divergence(sim: &StableFluids) -> Real {
+        let dims = sim.dims;
+        let mut total = 0.;
+        for y in 1..=dims.rows {
+            for x in 1..=dims.columns {
+                let i = dims.index(x, y);
+                total += (sim.velocity_x[i + 1] - sim.velocity_x[i - 1]
+                    + sim.velocity_y[i + dims.stride()]
+                    - sim.velocity_y[i - dims.stride()])
+                .abs();
+            }
+        }
+        total
+    }
+
+    #[test]
+    fn projection_takes_out_the_divergence() {
+        for solver in [LinearSolver::Jacobi, LinearSolver::GaussSeidel] {
+            let mut sim = grid(solver);
+            // a blob blowing outwards from the middle
+            let dims = sim.dims;
+            for y in 1..=dims.rows {
+                for x in 1..=dims.columns {
+                    let offset = Vec2 {
+                        x: x as Real - 16.,
+                        y: y as Real - 16.,
+                    };
+                    let push = offset * (10. * (-offset.dot(offset) / 16.).exp());
+                    sim.velocity_x[dims.index(x, y)] = push.x;
+                    sim.velocity_y[dims.index(x, y)] = push.y;
+                }
+            }
+            let before = total_divergence(&sim);
+
+            dims.project(
+                &mut sim.velocity_x,
+                &mut sim.velocity_y,
+                &mut sim.previous_x,
+                &mut sim.previous_y,
+                sim.config.iterations,
+                solver,
+            );
+
+            let after = total_divergence(&sim);
+            assert!(after < before * 0.3, "{solver:?}: {before} -> {after}");
+        }
+    }
+
+    #[test]
+    fn emitted_dye_gets_carried_up() {
+        let mut sim = grid(LinearSolver::GaussSeidel);
+        sim.emitters.push(Emitter {
+            position: Vec2 { x: 0.5, y: 0.8 },
+            radius: 2.,
+            density: 1.,
+            velocity: Vec2 { x: 0., y: -20. },
+        });
+        for _ in 0..30 {
+            sim.update(1. / 30.);
+        }
+
+        let field = sim.field();
+        let (mut total, mut weighted_y) = (0., 0.);
+        for y in 0..field.rows {
+            for x in 0..field.columns {
+                total += field.density(x, y);
+                weighted_y += field.density(x, y) * y as Real;
+            }
+        }
+        assert!(total > 0.);
+        // the emitter sits at row 25 or so, the dye should have risen well above it
+        assert!(weighted_y / total < 0.7 * field.rows as Real);
+    }
+
+    #[test]
+    fn poking_lines_up_with_the_stretched_window() {
+        let mut sim = grid(LinearSolver::GaussSeidel);
+        let (columns, rows) = (sim.dims.columns, sim.dims.rows);
+        // the window's been made twice as wide since the grid was built
+        let size = winit::dpi::PhysicalSize::new(columns as u32 * 20, rows as u32 * 10);
+        sim.poke(
+            Some(Interaction {
+                tool: Tool::Stir,
+                position: Vec2 {
+                    x: size.width as Real * 0.25,
+                    y: size.height as Real * 0.5,
+                },
+                velocity: Vec2 { x: 200., y: 0. },
+                radius: 40.,
+                strength: 10.,
+            }),
+            size,
+        );
+        sim.push(1. / 30.);
+
+        let field = sim.field();
+        let fastest = (0..rows)
+            .flat_map(|y| (0..columns).map(move |x| (x, y)))
+            .max_by(|a, b| {
+                let speed = |(x, y)| field.velocity(x, y).length();
+                speed(*a).total_cmp(&speed(*b))
+            })
+            .unwrap();
+        assert_eq!(fastest, (columns / 4, rows / 2));
+        assert!(field.velocity(fastest.0, fastest.1).x > 0.);
+    }
+}
@@ -1,10 +1,14 @@
+use crate::fluid_sim::real::{Real, to_f32};
 use cgmath::Rad;
 use std::ops::Mul;
 
-#[derive(Copy, Clone, Debug, Default, PartialEq, PartialOrd)]
+/// laid out the same as a `vec2<f32>` unless the sims are in `f64`, so slices of them can go
+/// to the gpu as they are
+#[repr(C)]
+#[derive(bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, Default, PartialEq, PartialOrd)]
 pub struct Vec2 {
-    pub x: f32,
-    pub y: f32,
+    pub x: Real,
+    pub y: Real,
 }
 
 impl std::ops::Neg for Vec2 {
@@ -19,7 +23,7 @@ impl std::ops::Neg for Vec2 {
 }
 
 impl Vec2 {
-    pub fn rotate_degrees(&mut self, angle: cgmath::Rad<f32>) {
+    pub fn rotate_degrees(&mut self, angle: cgmath::Rad<Real>) {
         let current_angle = Rad(self.y.atan2(self.x));
         let new_angle = current_angle + angle;
         let length = ((self.x * self.x) + (self.y * self.y)).sqrt();
@@ -40,10 +44,10 @@ impl Mul for Vec2 {
     }
 }
 
-impl Mul<f32> for Vec2 {
+impl Mul<Real> for Vec2 {
     type Output = Vec2;
 
-    fn mul(self, rhs: f32) -> Self::Output {
+    fn mul(self, rhs: Real) -> Self::Output {
         Vec2 {
             x: self.x * rhs,
             y: self.y * rhs,
@@ -62,10 +66,10 @@ impl std::ops::Add for Vec2 {
     }
 }
 
-impl std::ops::Div<f32> for Vec2 {
+impl std::ops::Div<Real> for Vec2 {
     type Output = Vec2;
 
-    fn div(self, rhs: f32) -> Self::Output {
+    fn div(self, rhs: Real) -> Self::Output {
         Vec2 {
             x: self.x / rhs,
             y: self.y / rhs,
@@ -100,3 +104,39 @@ impl std::ops::MulAssign for Vec2 {
         *self = *self * rhs
     }
 }
+
+impl Vec2 {
+    pub fn dot(self, rhs: Self) -> Real {
+        self.x * rhs.x + self.y * rhs.y
+    }
+
+    /// z component of the 3d cross product, handy for torques
+    pub fn cross(self, rhs: Self) -> Real {
+        self.x * rhs.y - self.y * rhs.x
+    }
+
+    pub fn length(self) -> Real {
+        self.dot(self).sqrt()
+    }
+
+    /// same as rotate_degrees but doesn't go through atan2 and hands back a new vector
+    pub fn rotated(self, angle: Real) -> Vec2 {
+        let (sin, cos) = angle.sin_cos();
+        Vec2 {
+            x: self.x * cos - self.y * sin,
+            y: self.x * sin + self.y * cos,
+        }
+    }
+
+    /// for the gpu and the screen, which only ever get `f32`
+    pub fn to_f32(self) -> [f32; 2] {
+        [to_f32(self.x), to_f32(self.y)]
+    }
+
+    pub fn from_f32([x, y]: [f32; 2]) -> Self {
+        Vec2 {
+            x: x as Real,
+            y: y as Real,
+        }
+    }
+}
@@ -0,0 +1,47 @@
+//! Runs a sim with no window at all, for timing solvers or checking one doesn't blow up.
+
+use crate::fluid_sim::{
+    contour::{self, Contour, ContourConfig},
+    real::Real,
+    simulation::Simulation,
+};
+use std::{path::Path, time::Instant};
+
+/// how big the sim gets told its window is
+pub const SIZE: winit::dpi::PhysicalSize<u32> = winit::dpi::PhysicalSize::new(800, 600);
+const DELTA: Real = 1. / 60.;
+const REPORT_EVERY: usize = 100;
+
+pub fn run(sim: &mut dyn Simulation, steps: usize) {
+    let start = Instant::now();
+    let mut since_report = Instant::now();
+
+    for step in 1..=steps {
+        sim.step(DELTA, SIZE);
+
+        if step % REPORT_EVERY == 0 {
+            println!(
+                "step {step}: {:.2?} per step, {}",
+                since_report.elapsed() / REPORT_EVERY as u32,
+                sim.diagnostics()
+            );
+            since_report = Instant::now();
+        }
+    }
+
+    println!("{steps} steps in {:.2?}", start.elapsed());
+}
+
+/// saves the fluid's outline as an svg, for after a run
+pub fn export_outline(sim: &dyn Simulation, config: &ContourConfig, path: &Path) {
+    let contours = sim.contours(config, SIZE);
+    match std::fs::write(path, contour::to_svg(&contours, SIZE)) {
+        Ok(()) => println!(
+            "saved {} outlines holding {:.0} square pixels of fluid to {}",
+            contours.len(),
+            contours.iter().map(Contour::area).sum::<Real>(),
+            path.display()
+        ),
+        Err(error) => eprintln!("couldn't save {}: {error}", path.display()),
+    }
+}
@@ -1,6 +1,190 @@
+use fluid_sim::{
+    SORT_EVERY,
+    scene::Scene,
+    simulation::{Quantity, Setup, Solver},
+    stable_fluids::LinearSolver,
+};
+use render::{
+    Options, PARTICLE_SCALE,
+    colour::{self, Colormap, ColourRange},
+    field::FieldLayer,
+    instanced::DotPath,
+};
+use std::{fmt::Display, str::FromStr};
+
 mod fluid_sim;
+mod headless;
 mod render;
 
 fn main() {
-    pollster::block_on(render::run());
+    let mut setup = Setup::default();
+    let mut options = Options::default();
+    let mut headless = None;
+    let mut export_outline = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--scene" => {
+                let name = args.next().unwrap_or_default();
+                setup.scene = Scene::from_name(&name).unwrap_or_else(|| {
+                    eprintln!("no scene called {name:?}, using the default one");
+                    Scene::default()
+                });
+            }
+            "--solver" => {
+                let name = args.next().unwrap_or_default();
+                setup.solver = Solver::from_name(&name).unwrap_or_else(|| {
+                    eprintln!("no solver called {name:?}, using the default one");
+                    Solver::default()
+                });
+            }
+            "--grid-columns" => {
+                let columns = args.next().unwrap_or_default();
+                match columns.parse() {
+                    Ok(columns) if columns > 0 => setup.grid.columns = columns,
+                    _ => eprintln!(
+                        "{columns:?} isn't a column count, sticking with {}",
+                        setup.grid.columns
+                    ),
+                }
+            }
+            "--grid-solver" => {
+                let name = args.next().unwrap_or_default();
+                setup.grid.solver = LinearSolver::from_name(&name).unwrap_or_else(|| {
+                    eprintln!("no linear solver called {name:?}, using the default one");
+                    LinearSolver::default()
+                });
+            }
+            "--gravity" => {
+                let value = args.next().unwrap_or_default();
+                let parsed = value
+                    .split_once(',')
+                    .and_then(|(x, y)| Some((x.trim().parse().ok()?, y.trim().parse().ok()?)));
+                match parsed {
+                    Some((x, y)) => setup.gravity = Some(fluid_sim::vec2::Vec2 { x, y }),
+                    None => eprintln!("{value:?} isn't a gravity, it wants to look like 0,400"),
+                }
+            }
+            "--field" => {
+                let name = args.next().unwrap_or_default();
+                options.field_layer = FieldLayer::from_name(&name);
+                if options.field_layer.is_none() {
+                    eprintln!("no field called {name:?}, drawing the default one");
+                }
+            }
+            "--colour-by" => {
+                let name = args.next().unwrap_or_default();
+                match Quantity::from_name(&name) {
+                    Some(quantity) => options.colouring.quantity = Some(quantity),
+                    None if name == "nothing" => options.colouring.quantity = None,
+                    None => eprintln!("can't colour by {name:?}, sticking with speed"),
+                }
+            }
+            "--colormap" => {
+                let name = args.next().unwrap_or_default();
+                match Colormap::from_name(&name) {
+                    Ok(colormap) => options.colouring.colormap = colormap,
+                    Err(error) => eprintln!("{error}, using viridis"),
+                }
+            }
+            "--colour-range" => {
+                let range = args.next().unwrap_or_default();
+                match ColourRange::from_name(&range) {
+                    Ok(range) => options.colouring.range = range,
+                    Err(error) => eprintln!("{error}, fitting the range automatically"),
+                }
+            }
+            "--surface" => options.surface = true,
+            "--surface-colour" | "--surface-highlight" => {
+                let hex = args.next().unwrap_or_default();
+                match colour::linear_from_hex(&hex) {
+                    Some(linear) if arg == "--surface-colour" => {
+                        options.surface_style.colour = linear
+                    }
+                    Some(linear) => options.surface_style.highlight = linear,
+                    None => eprintln!("{hex:?} isn't a colour, it wants to look like #3377dd"),
+                }
+            }
+            "--surface-opacity" => {
+                let opacity = number(&mut args, options.surface_style.opacity);
+                options.surface_style.opacity = opacity.min(1.);
+            }
+            "--outline" => options.outline = true,
+            "--outline-level" => options.contours.level = number(&mut args, options.contours.level),
+            "--outline-cell" => options.contours.cell = number(&mut args, options.contours.cell),
+            "--export-outline" => export_outline = args.next().map(std::path::PathBuf::from),
+            "--arrows" => options.flow.arrows = true,
+            "--streamlines" => options.flow.streamlines = true,
+            "--arrow-cell" => options.flow.cell = number(&mut args, options.flow.cell),
+            "--particle-scale" => {
+                let current = options.particle_scale.unwrap_or(PARTICLE_SCALE);
+                options.particle_scale = Some(number(&mut args, current))
+            }
+            "--trails" => options.trails.on = true,
+            "--trail-length" => {
+                options.trails.length = number(&mut args, options.trails.length as f32) as usize
+            }
+            "--trail-fade" => options.trails.fade = number(&mut args, options.trails.fade),
+            "--dots" => {
+                let name = args.next().unwrap_or_default();
+                match DotPath::from_name(&name) {
+                    Some(path) => options.dots = path,
+                    None => eprintln!("no way of drawing dots called {name:?}, using storage"),
+                }
+            }
+            "--mouse-radius" => options.mouse.radius = number(&mut args, options.mouse.radius),
+            "--mouse-strength" => {
+                options.mouse.strength = number(&mut args, options.mouse.strength)
+            }
+            "--stir-rate" => options.mouse.stir_rate = number(&mut args, options.mouse.stir_rate),
+            "--bind" => {
+                let binding = args.next().unwrap_or_default();
+                if let Err(error) = options.bindings.bind_from_str(&binding) {
+                    eprintln!("{error}, leaving the keys as they were");
+                }
+            }
+            "--sort-every" => {
+                let steps = args.next().unwrap_or_default();
+                match steps.parse() {
+                    Ok(steps) => setup.sort_every = Some(steps),
+                    _ => eprintln!("{steps:?} isn't a step count, sorting every {SORT_EVERY}"),
+                }
+            }
+            "--headless" => {
+                let steps = args.next().unwrap_or_default();
+                match steps.parse() {
+                    Ok(steps) => headless = Some(steps),
+                    _ => eprintln!("{steps:?} isn't a step count, opening the window instead"),
+                }
+            }
+            _ => eprintln!("ignoring unknown argument {arg:?}"),
+        }
+    }
+
+    if let Some(steps) = headless {
+        let mut sim = setup.build(headless::SIZE);
+        headless::run(sim.as_mut(), steps);
+        if let Some(path) = export_outline {
+            headless::export_outline(sim.as_ref(), &options.contours, &path);
+        }
+        return;
+    }
+
+    pollster::block_on(render::run(setup, options));
+}
+
+/// the next argument as a positive number, or `current` if it isn't one
+fn number<T: FromStr + PartialOrd + Default + Display>(
+    args: &mut impl Iterator<Item = String>,
+    current: T,
+) -> T {
+    let value = args.next().unwrap_or_default();
+    match value.parse() {
+        Ok(number) if number > T::default() => number,
+        _ => {
+            eprintln!("{value:?} isn't a positive number, sticking with {current}");
+            current
+        }
+    }
 }
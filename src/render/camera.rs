@@ -0,0 +1,170 @@
+use crate::fluid_sim::{
+    real::{Real, to_f32},
+    simulation::Interaction,
+    vec2::Vec2,
+};
+
+/// how far in or out the view can go
+const ZOOM_RANGE: std::ops::RangeInclusive<Real> = 0.1..=50.;
+
+/// a 2d view of the sim. Sims work in window pixels, so with no pan and no zoom the camera
+/// shows exactly the window.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Camera {
+    /// how far the middle of the view is from the middle of the window, in sim pixels
+    pub offset: Vec2,
+    /// screen pixels per sim pixel
+    pub zoom: Real,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            offset: Vec2::default(),
+            zoom: 1.,
+        }
+    }
+}
+
+/// what the shaders get, the camera plus how big the dots are, laid out the way wgsl wants
+/// it
+#[repr(C)]
+#[derive(bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct CameraUniform {
+    pub view_proj: [[f32; 4]; 4],
+    /// the window in screen pixels
+    pub screen: [f32; 2],
+    pub zoom: f32,
+    /// how big the dots are in sim pixels, unless they've each got their own
+    pub point_radius: f32,
+    /// 1 when the dots each have their own radius
+    pub per_point: f32,
+    _padding: [f32; 3],
+}
+
+impl Camera {
+    /// the sim point in the middle of the view
+    pub fn centre(&self, size: winit::dpi::PhysicalSize<u32>) -> Vec2 {
+        half(size) + self.offset
+    }
+
+    /// sim pixels to clip space, with y flipped since sim pixels go down the screen
+    pub fn view_proj(&self, size: winit::dpi::PhysicalSize<u32>) -> cgmath::Matrix4<f32> {
+        let centre = self.centre(size);
+        let reach = half(size) / self.zoom;
+        cgmath::ortho(
+            to_f32(centre.x - reach.x),
+            to_f32(centre.x + reach.x),
+            to_f32(centre.y + reach.y),
+            to_f32(centre.y - reach.y),
+            -1.,
+            1.,
+        )
+    }
+
+    /// `per_point` is whether there's a radius for every dot to use instead of `point_radius`
+    pub fn uniform(
+        &self,
+        size: winit::dpi::PhysicalSize<u32>,
+        point_radius: f32,
+        per_point: bool,
+    ) -> CameraUniform {
+        CameraUniform {
+            view_proj: self.view_proj(size).into(),
+            screen: [size.width as f32, size.height as f32],
+            zoom: to_f32(self.zoom),
+            point_radius,
+            per_point: if per_point { 1. } else { 0. },
+            _padding: [0.; 3],
+        }
+    }
+
+    /// where a window pixel is in the sim
+    pub fn sim_point(&self, screen: Vec2, size: winit::dpi::PhysicalSize<u32>) -> Vec2 {
+        self.centre(size) + (screen - half(size)) / self.zoom
+    }
+
+    /// where a sim point shows up in the window
+    pub fn screen_point(&self, point: Vec2, size: winit::dpi::PhysicalSize<u32>) -> Vec2 {
+        half(size) + (point - self.centre(size)) * self.zoom
+    }
+
+    /// moves the view along with a drag of `by` screen pixels
+    pub fn pan(&mut self, by: Vec2) {
+        self.offset -= by / self.zoom;
+    }
+
+    /// zooms in by `factor`, or out under 1, keeping whatever's under `around` where it is
+    pub fn zoom_around(&mut self, around: Vec2, factor: Real, size: winit::dpi::PhysicalSize<u32>) {
+        let fixed = self.sim_point(around, size);
+        self.zoom = (self.zoom * factor).clamp(*ZOOM_RANGE.start(), *ZOOM_RANGE.end());
+        self.offset = fixed - (around - half(size)) / self.zoom - half(size);
+    }
+
+    /// the mouse works in window pixels, this puts what it's doing into the sim's, so it
+    /// reaches as far on screen however far in the view is
+    pub fn interaction(
+        &self,
+        interaction: Interaction,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> Interaction {
+        Interaction {
+            position: self.sim_point(interaction.position, size),
+            velocity: interaction.velocity / self.zoom,
+            radius: interaction.radius / self.zoom,
+            ..interaction
+        }
+    }
+}
+
+fn half(size: winit::dpi::PhysicalSize<u32>) -> Vec2 {
+    Vec2 {
+        x: size.width as Real / 2.,
+        y: size.height as Real / 2.,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Vector4, assert_abs_diff_eq};
+
+    const SIZE: winit::dpi::PhysicalSize<u32> = winit::dpi::PhysicalSize::new(200, 100);
+
+    fn clip(camera: &Camera, world: Vec2) -> (f32, f32) {
+        let [x, y] = world.to_f32();
+        let clip = camera.view_proj(SIZE) * Vector4::new(x, y, 0., 1.);
+        (clip.x / clip.w, clip.y / clip.w)
+    }
+
+    #[test]
+    fn the_default_view_is_the_window() {
+        let camera = Camera::default();
+        assert_eq!(clip(&camera, Vec2 { x: 0., y: 0. }), (-1., 1.));
+        assert_eq!(clip(&camera, Vec2 { x: 200., y: 100. }), (1., -1.));
+        let point = Vec2 { x: 30., y: 70. };
+        assert_eq!(camera.sim_point(point, SIZE), point);
+    }
+
+    #[test]
+    fn zooming_keeps_the_point_under_the_cursor() {
+        let mut camera = Camera::default();
+        let cursor = Vec2 { x: 150., y: 20. };
+        let under = camera.sim_point(cursor, SIZE);
+        camera.zoom_around(cursor, 4., SIZE);
+        camera.pan(Vec2 { x: 8., y: 0. });
+        camera.zoom_around(cursor, 0.5, SIZE);
+        assert_eq!(camera.zoom, 2.);
+
+        // the pan was 8 screen pixels at 4x, so 2 sim pixels
+        let now = camera.sim_point(cursor, SIZE);
+        assert_abs_diff_eq!(now.x, under.x - 2., epsilon = 1e-4);
+        assert_abs_diff_eq!(now.y, under.y, epsilon = 1e-4);
+
+        let back = camera.screen_point(now, SIZE);
+        assert_abs_diff_eq!(back.x, cursor.x, epsilon = 1e-3);
+        let (x, y) = clip(&camera, now);
+        assert_abs_diff_eq!(x, to_f32(cursor.x) / 100. - 1., epsilon = 1e-5);
+        assert_abs_diff_eq!(y, 1. - to_f32(cursor.y) / 50., epsilon = 1e-5);
+    }
+}
@@ -0,0 +1,525 @@
+//! Turning one number per particle into a colour: the colormaps, the range the numbers get
+//! squeezed into, and which number it is.
+
+use crate::fluid_sim::{gpu::GpuSim, simulation::Quantity};
+use rayon::prelude::*;
+use wgpu::util::DeviceExt;
+
+/// how many entries the table the shader looks colours up in has
+pub const TABLE_SIZE: usize = 256;
+
+/// a gradient from 0 to 1. The built in ones are polynomial fits, custom ones are evenly
+/// spaced stops blended in between.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Colormap {
+    /// dark purple through green to yellow, reads fine in greyscale and for most colour blind
+    /// people
+    #[default]
+    Viridis,
+    /// a fixed up rainbow, good for picking out small differences
+    Turbo,
+    /// blue through grey to red, for things with an interesting middle
+    Coolwarm,
+    /// srgb stops from 0 to 1, at least two of them
+    Custom(Vec<[f32; 3]>),
+}
+
+impl Colormap {
+    pub const BUILT_IN: [Colormap; 3] = [Colormap::Viridis, Colormap::Turbo, Colormap::Coolwarm];
+
+    /// a built in name, or hex colours split by commas for a custom one, like
+    /// `#000000,#ff8800,#ffffff`
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        if let Some(built_in) = Self::BUILT_IN
+            .into_iter()
+            .find(|colormap| colormap.name() == name)
+        {
+            return Ok(built_in);
+        }
+
+        let stops = name
+            .split(',')
+            .map(|stop| parse_hex(stop.trim()).ok_or_else(|| format!("{stop:?} isn't a colour")))
+            .collect::<Result<Vec<_>, _>>()?;
+        if stops.len() < 2 {
+            return Err(format!(
+                "{name:?} needs at least two colours to make a gradient"
+            ));
+        }
+        Ok(Colormap::Custom(stops))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Colormap::Viridis => "viridis",
+            Colormap::Turbo => "turbo",
+            Colormap::Coolwarm => "coolwarm",
+            Colormap::Custom(_) => "custom",
+        }
+    }
+
+    /// srgb colour at `t`, anything outside 0..1 gets the colour at the nearest end
+    pub fn sample(&self, t: f32) -> [f32; 3] {
+        let t = if t.is_nan() { 0. } else { t.clamp(0., 1.) };
+        let [r, g, b] = match self {
+            Colormap::Viridis => [
+                polynomial(
+                    t,
+                    &[
+                        0.277_727_33,
+                        0.105_093_04,
+                        -0.330_861_83,
+                        -4.634_230_5,
+                        6.228_27,
+                        4.776_385,
+                        -5.435_456,
+                    ],
+                ),
+                polynomial(
+                    t,
+                    &[
+                        0.005_407_344_5,
+                        1.404_613_5,
+                        0.214_847_56,
+                        -5.799_101,
+                        14.179_933,
+                        -13.745_145,
+                        4.645_852_6,
+                    ],
+                ),
+                polynomial(
+                    t,
+                    &[
+                        0.334_099_8,
+                        1.384_590_2,
+                        0.095_095_16,
+                        -19.332_441,
+                        56.690_55,
+                        -65.353_03,
+                        26.312_435,
+                    ],
+                ),
+            ],
+            Colormap::Turbo => [
+                polynomial(
+                    t,
+                    &[
+                        0.135_721_38,
+                        4.615_392_6,
+                        -42.660_324,
+                        132.131_08,
+                        -152.942_4,
+                        59.286_38,
+                    ],
+                ),
+                polynomial(
+                    t,
+                    &[
+                        0.091_402_61,
+                        2.194_188_4,
+                        4.842_966_6,
+                        -14.185_033,
+                        4.277_299,
+                        2.829_566,
+                    ],
+                ),
+                polynomial(
+                    t,
+                    &[
+                        0.106_673_3,
+                        12.641_946,
+                        -60.582_05,
+                        110.362_77,
+                        -89.903_11,
+                        27.348_25,
+                    ],
+                ),
+            ],
+            Colormap::Coolwarm => stops(
+                t,
+                &[
+                    [0.2298, 0.2987, 0.7537],
+                    [0.5543, 0.6900, 0.9955],
+                    [0.8654, 0.8654, 0.8654],
+                    [0.9567, 0.5980, 0.4773],
+                    [0.7057, 0.0156, 0.1502],
+                ],
+            ),
+            Colormap::Custom(custom) => stops(t, custom),
+        };
+        [r.clamp(0., 1.), g.clamp(0., 1.), b.clamp(0., 1.)]
+    }
+
+    /// `TABLE_SIZE` colours for the shader, already linear since the window's srgb and turns
+    /// them back on the way out. The last number is padding.
+    pub fn table(&self) -> Vec<[f32; 4]> {
+        (0..TABLE_SIZE)
+            .map(|i| {
+                let [r, g, b] = self.sample(i as f32 / (TABLE_SIZE - 1) as f32);
+                [to_linear(r), to_linear(g), to_linear(b), 1.]
+            })
+            .collect()
+    }
+}
+
+/// where the ends of the colormap sit
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ColourRange {
+    /// stretched over whatever the particles have got this frame
+    #[default]
+    Auto,
+    Fixed(f32, f32),
+}
+
+impl ColourRange {
+    /// `auto` or `<min>,<max>`
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        if name == "auto" {
+            return Ok(ColourRange::Auto);
+        }
+        let (min, max) = name
+            .split_once(',')
+            .and_then(|(min, max)| Some((min.trim().parse().ok()?, max.trim().parse().ok()?)))
+            .ok_or_else(|| format!("{name:?} wants to be auto or look like 0,500"))?;
+        if min >= max {
+            return Err(format!("{name:?} has to go from small to big"));
+        }
+        Ok(ColourRange::Fixed(min, max))
+    }
+
+    /// the actual numbers at either end for `scalars`. An automatic range with nothing to go
+    /// on, or where everything's the same, is 0..1 and around the one value respectively.
+    pub fn resolve(self, scalars: &[f32]) -> (f32, f32) {
+        match self {
+            ColourRange::Fixed(min, max) => (min, max),
+            ColourRange::Auto => {
+                let (min, max) = scalars
+                    .par_iter()
+                    .filter(|scalar| scalar.is_finite())
+                    .fold(
+                        || (f32::INFINITY, f32::NEG_INFINITY),
+                        |(min, max), &scalar| (min.min(scalar), max.max(scalar)),
+                    )
+                    .reduce(
+                        || (f32::INFINITY, f32::NEG_INFINITY),
+                        |a, b| (a.0.min(b.0), a.1.max(b.1)),
+                    );
+                if min > max {
+                    (0., 1.)
+                } else if min == max {
+                    (min - 0.5, max + 0.5)
+                } else {
+                    (min, max)
+                }
+            }
+        }
+    }
+}
+
+/// what the particles get coloured by and how
+#[derive(Clone, Debug, PartialEq)]
+pub struct Colouring {
+    /// nothing draws them all black
+    pub quantity: Option<Quantity>,
+    pub colormap: Colormap,
+    pub range: ColourRange,
+}
+
+impl Default for Colouring {
+    fn default() -> Self {
+        Self {
+            quantity: Some(Quantity::Speed),
+            colormap: Colormap::default(),
+            range: ColourRange::default(),
+        }
+    }
+}
+
+impl Colouring {
+    /// on to the next quantity, going through plain black after the last one
+    pub fn next_quantity(&mut self) {
+        let all = Quantity::ALL;
+        self.quantity = match self.quantity {
+            None => Some(all[0]),
+            Some(quantity) => all
+                .iter()
+                .position(|other| *other == quantity)
+                .and_then(|i| all.get(i + 1))
+                .copied(),
+        };
+    }
+}
+
+/// what the particle shader needs on the gpu to colour things in
+pub struct ColourBuffers {
+    /// one number per particle, sized to match the position buffer
+    pub scalars: wgpu::Buffer,
+    pub colormap: wgpu::Buffer,
+    /// which colormap's in `colormap`, it only gets sent again when it changes
+    uploaded: Colormap,
+    /// min, max, how many particles have a number and whether colouring's on
+    pub uniform: wgpu::Buffer,
+}
+
+impl ColourBuffers {
+    pub fn new(device: &wgpu::Device, particles: usize, colormap: &Colormap) -> Self {
+        Self {
+            scalars: scalar_buffer(device, particles),
+            colormap: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("colormap buffer"),
+                contents: bytemuck::cast_slice(&colormap.table()),
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            }),
+            uploaded: colormap.clone(),
+            uniform: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("colouring buffer"),
+                contents: bytemuck::cast_slice(&[0f32; 4]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }),
+        }
+    }
+
+    /// makes room for `particles`, the bind group needs making again after
+    pub fn grow(&mut self, device: &wgpu::Device, particles: usize) {
+        self.scalars = scalar_buffer(device, particles);
+    }
+
+    /// sends this frame's numbers over, or switches colouring off if there aren't any. Gives
+    /// back the range they were squeezed into.
+    pub fn upload(
+        &mut self,
+        queue: &wgpu::Queue,
+        colouring: &Colouring,
+        scalars: Option<&[f32]>,
+    ) -> Option<(f32, f32)> {
+        self.send_colormap(queue, &colouring.colormap);
+
+        let Some(scalars) = scalars.filter(|scalars| !scalars.is_empty()) else {
+            queue.write_buffer(&self.uniform, 0, bytemuck::cast_slice(&[0f32; 4]));
+            return None;
+        };
+        let fits = self.scalars.size() as usize / std::mem::size_of::<f32>();
+        let scalars = &scalars[..scalars.len().min(fits)];
+        let (min, max) = colouring.range.resolve(scalars);
+        queue.write_buffer(&self.scalars, 0, bytemuck::cast_slice(scalars));
+        queue.write_buffer(
+            &self.uniform,
+            0,
+            bytemuck::cast_slice(&[min, max, scalars.len() as f32, 1.]),
+        );
+        Some((min, max))
+    }
+
+    /// same as `upload` for a sim that keeps its particles on the gpu. It works `quantity` out
+    /// into its own buffer, which gets bound in place of `scalars`, and an automatic range gets
+    /// copied over on the gpu in `encoder` too, so there's no range to give back for that.
+    pub fn upload_resident(
+        &mut self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        colouring: &Colouring,
+        quantity: Option<Quantity>,
+        gpu: &GpuSim,
+    ) -> Option<(f32, f32)> {
+        self.send_colormap(queue, &colouring.colormap);
+
+        let Some(quantity) = quantity.filter(|_| gpu.len() > 0) else {
+            queue.write_buffer(&self.uniform, 0, bytemuck::cast_slice(&[0f32; 4]));
+            return None;
+        };
+        gpu.colour(quantity);
+        match colouring.range {
+            ColourRange::Fixed(min, max) => {
+                queue.write_buffer(
+                    &self.uniform,
+                    0,
+                    bytemuck::cast_slice(&[min, max, gpu.len() as f32, 1.]),
+                );
+                Some((min, max))
+            }
+            ColourRange::Auto => {
+                encoder.copy_buffer_to_buffer(gpu.colour_range(), 0, &self.uniform, 0, 16);
+                None
+            }
+        }
+    }
+
+    fn send_colormap(&mut self, queue: &wgpu::Queue, colormap: &Colormap) {
+        if self.uploaded != *colormap {
+            queue.write_buffer(&self.colormap, 0, bytemuck::cast_slice(&colormap.table()));
+            self.uploaded = colormap.clone();
+        }
+    }
+}
+
+fn scalar_buffer(device: &wgpu::Device, particles: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("scalar buffer"),
+        size: (particles.max(1) * std::mem::size_of::<f32>()) as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// `coefficients` from the constant term up
+fn polynomial(t: f32, coefficients: &[f32]) -> f32 {
+    coefficients
+        .iter()
+        .rev()
+        .fold(0., |total, coefficient| total * t + coefficient)
+}
+
+/// blends between evenly spaced `stops`
+fn stops(t: f32, stops: &[[f32; 3]]) -> [f32; 3] {
+    let at = t * (stops.len() - 1) as f32;
+    let below = (at as usize).min(stops.len() - 2);
+    let t = at - below as f32;
+    let (a, b) = (stops[below], stops[below + 1]);
+    [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * t)
+}
+
+/// a `#rrggbb` colour as linear rgb, for things that don't go through a colormap
+pub fn linear_from_hex(hex: &str) -> Option<[f32; 3]> {
+    parse_hex(hex).map(|srgb| srgb.map(to_linear))
+}
+
+/// `#rrggbb`, the hash is optional
+fn parse_hex(hex: &str) -> Option<[f32; 3]> {
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| Some(u8::from_str_radix(&hex[i..i + 2], 16).ok()? as f32 / 255.);
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+fn to_linear(srgb: f32) -> f32 {
+    if srgb <= 0.04045 {
+        srgb / 12.92
+    } else {
+        ((srgb + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fluid_sim::{gpu, real::Real, simulation::Simulation, sph::WcsphConfig, vec2::Vec2};
+
+    fn close(a: [f32; 3], b: [f32; 3]) -> bool {
+        a.iter().zip(b).all(|(a, b)| (a - b).abs() < 0.02)
+    }
+
+    #[test]
+    fn colormaps_end_where_they_should() {
+        // viridis' published end points, turbo's fit is looser so just its shape
+        assert!(close(Colormap::Viridis.sample(0.), [0.267, 0.005, 0.329]));
+        assert!(close(Colormap::Viridis.sample(1.), [0.993, 0.906, 0.144]));
+        let [r, g, b] = Colormap::Turbo.sample(0.);
+        assert!(r + g + b < 0.5);
+        assert!(Colormap::Turbo.sample(0.5)[1] > 0.8);
+        let [r, g, b] = Colormap::Turbo.sample(1.);
+        assert!(r > 0.4 && g < 0.1 && b < 0.1);
+        assert!(close(Colormap::Coolwarm.sample(0.5), [0.865, 0.865, 0.865]));
+        assert_eq!(Colormap::Turbo.sample(-3.), Colormap::Turbo.sample(0.));
+        assert_eq!(Colormap::Turbo.sample(f32::NAN), Colormap::Turbo.sample(0.));
+
+        for colormap in Colormap::BUILT_IN {
+            assert_eq!(Colormap::from_name(colormap.name()), Ok(colormap.clone()));
+            let table = colormap.table();
+            assert_eq!(table.len(), TABLE_SIZE);
+            assert!(table.iter().flatten().all(|c| (0. ..=1.).contains(c)));
+        }
+    }
+
+    #[test]
+    fn custom_gradients_come_from_hex() {
+        let custom = Colormap::from_name("#000000, ff0000,#ffffff").unwrap();
+        assert_eq!(custom.sample(0.), [0., 0., 0.]);
+        assert_eq!(custom.sample(0.25), [0.5, 0., 0.]);
+        assert_eq!(custom.sample(0.5), [1., 0., 0.]);
+        assert_eq!(custom.sample(1.), [1., 1., 1.]);
+
+        assert!(Colormap::from_name("#ff0000").is_err());
+        assert!(Colormap::from_name("#ff0000,#zz0000").is_err());
+        assert!(Colormap::from_name("magma").is_err());
+    }
+
+    #[test]
+    fn hex_colours_come_out_linear() {
+        assert_eq!(linear_from_hex("#ffffff"), Some([1., 1., 1.]));
+        let [r, g, b] = linear_from_hex("808000").unwrap();
+        assert!((r - 0.216).abs() < 1e-3 && r == g && b == 0.);
+        assert_eq!(linear_from_hex("#fff"), None);
+    }
+
+    #[test]
+    fn ranges_fit_the_numbers_unless_fixed() {
+        assert_eq!(
+            ColourRange::Auto.resolve(&[3., -1., f32::NAN, 7.]),
+            (-1., 7.)
+        );
+        assert_eq!(ColourRange::Auto.resolve(&[2., 2.]), (1.5, 2.5));
+        assert_eq!(ColourRange::Auto.resolve(&[]), (0., 1.));
+        assert_eq!(ColourRange::Fixed(0., 5.).resolve(&[100.]), (0., 5.));
+
+        assert_eq!(ColourRange::from_name("auto"), Ok(ColourRange::Auto));
+        assert_eq!(
+            ColourRange::from_name("0, 500"),
+            Ok(ColourRange::Fixed(0., 500.))
+        );
+        assert!(ColourRange::from_name("5,1").is_err());
+        assert!(ColourRange::from_name("lots").is_err());
+    }
+
+    #[test]
+    fn the_key_goes_through_everything_and_back() {
+        let mut colouring = Colouring::default();
+        let mut seen = Vec::new();
+        for _ in 0..=Quantity::ALL.len() {
+            colouring.next_quantity();
+            seen.push(colouring.quantity);
+        }
+        assert_eq!(seen.last(), Some(&Some(Quantity::Speed)));
+        assert!(seen.contains(&None));
+    }
+
+    #[test]
+    fn a_resident_frame_colours_without_reading_anything_back() {
+        let Some((device, queue)) = gpu::request_device(true) else {
+            eprintln!("no fallback adapter, skipping");
+            return;
+        };
+        let size = winit::dpi::PhysicalSize::new(200, 150);
+        let positions = (0..50)
+            .map(|i| Vec2 {
+                x: 50. + (i % 10) as Real * 8.,
+                y: 50. + (i / 10) as Real * 8.,
+            })
+            .collect();
+        let mut sim = GpuSim::new(
+            &device,
+            &queue,
+            WcsphConfig::default(),
+            positions,
+            vec![Vec2::default(); 50],
+            Vec2 { x: 0., y: 400. },
+            size,
+        );
+        sim.step(1. / 60., size);
+
+        let colouring = Colouring::default();
+        let mut colours = ColourBuffers::new(&device, sim.len(), &colouring.colormap);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("test frame"),
+        });
+        let range =
+            colours.upload_resident(&queue, &mut encoder, &colouring, colouring.quantity, &sim);
+        queue.submit(std::iter::once(encoder.finish()));
+        device.poll(wgpu::PollType::Wait).unwrap();
+
+        // an automatic range only ever exists on the gpu
+        assert_eq!(range, None);
+        assert_eq!(sim.readbacks(), 0);
+    }
+}
@@ -0,0 +1,271 @@
+use crate::fluid_sim::{
+    real::{Real, consts::TAU, to_f32},
+    simulation::FieldView,
+};
+
+/// which part of a grid sim gets drawn
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum FieldLayer {
+    /// how much dye is in each cell, black for none up to white
+    #[default]
+    Density,
+    /// direction as the colour, speed as the brightness
+    Velocity,
+    /// blue when still up to red at the fastest cell
+    Speed,
+    /// red spinning one way, blue the other
+    Vorticity,
+}
+
+impl FieldLayer {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "density" => Some(FieldLayer::Density),
+            "velocity" => Some(FieldLayer::Velocity),
+            "speed" => Some(FieldLayer::Speed),
+            "vorticity" => Some(FieldLayer::Vorticity),
+            _ => None,
+        }
+    }
+}
+
+/// draws a grid sim as a texture stretched over the window
+pub struct FieldRenderer {
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    columns: usize,
+    rows: usize,
+}
+
+impl FieldRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        columns: usize,
+        rows: usize,
+        camera: &wgpu::Buffer,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("field texture"),
+            size: wgpu::Extent3d {
+                width: columns as u32,
+                height: rows as u32,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("field sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("field bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("field bind group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: camera.as_entire_binding(),
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("field shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("./field.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("field pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            texture,
+            bind_group,
+            pipeline,
+            columns,
+            rows,
+        }
+    }
+
+    /// whether a field this shape can go straight into the texture we've already got
+    pub fn fits(&self, field: &FieldView) -> bool {
+        self.columns == field.columns && self.rows == field.rows
+    }
+
+    pub fn upload(&self, queue: &wgpu::Queue, field: &FieldView, layer: FieldLayer) {
+        let texels = colour_field(field, layer);
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&texels),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * self.columns as u32),
+                rows_per_image: Some(self.rows as u32),
+            },
+            wgpu::Extent3d {
+                width: self.columns as u32,
+                height: self.rows as u32,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..6, 0..1);
+    }
+}
+
+const OBSTACLE: [u8; 4] = [90, 90, 90, 255];
+
+/// turns the field into rgba texels, row by row from the top
+fn colour_field(field: &FieldView, layer: FieldLayer) -> Vec<[u8; 4]> {
+    let cells = || (0..field.rows).flat_map(|y| (0..field.columns).map(move |x| (x, y)));
+    // brightness is relative to the biggest value, so it's readable at any speed
+    let biggest = |value: &dyn Fn(usize, usize) -> Real| {
+        cells()
+            .map(|(x, y)| value(x, y).abs())
+            .fold(Real::EPSILON, Real::max)
+    };
+    let mut texels: Vec<[u8; 4]> = match layer {
+        FieldLayer::Density => cells()
+            .map(|(x, y)| {
+                let value = (field.density(x, y).clamp(0., 1.) * 255.) as u8;
+                [value, value, value, 255]
+            })
+            .collect(),
+        FieldLayer::Velocity => {
+            let fastest = biggest(&|x, y| field.velocity(x, y).length());
+            cells()
+                .map(|(x, y)| {
+                    let velocity = field.velocity(x, y);
+                    let hue = velocity.y.atan2(velocity.x) / TAU + 0.5;
+                    hsv_to_rgba(to_f32(hue), 1., to_f32(velocity.length() / fastest))
+                })
+                .collect()
+        }
+        FieldLayer::Speed => {
+            let fastest = biggest(&|x, y| field.velocity(x, y).length());
+            cells()
+                .map(|(x, y)| {
+                    let speed = field.velocity(x, y).length() / fastest;
+                    hsv_to_rgba(to_f32((1. - speed) * 2. / 3.), 1., 1.)
+                })
+                .collect()
+        }
+        FieldLayer::Vorticity => {
+            let strongest = biggest(&|x, y| field.vorticity(x, y));
+            cells()
+                .map(|(x, y)| {
+                    let spin = (field.vorticity(x, y) / strongest).clamp(-1., 1.);
+                    let fade = ((1. - spin.abs()) * 255.) as u8;
+                    if spin > 0. {
+                        [255, fade, fade, 255]
+                    } else {
+                        [fade, fade, 255, 255]
+                    }
+                })
+                .collect()
+        }
+    };
+
+    if field.obstacles.is_some() {
+        for (texel, (x, y)) in texels.iter_mut().zip(cells()) {
+            if field.is_solid(x, y) {
+                *texel = OBSTACLE;
+            }
+        }
+    }
+    texels
+}
+
+/// all three in 0..1
+fn hsv_to_rgba(hue: f32, saturation: f32, value: f32) -> [u8; 4] {
+    let channel = |n: f32| {
+        let k = (n + hue * 6.) % 6.;
+        value - value * saturation * k.min(4. - k).clamp(0., 1.)
+    };
+    let byte = |c: f32| (c.clamp(0., 1.) * 255.) as u8;
+    [byte(channel(5.)), byte(channel(3.)), byte(channel(1.)), 255]
+}
@@ -0,0 +1,50 @@
+// Stretches the field texture over the sim, which is the window before the camera moves
+
+@group(0) @binding(0)
+var field_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var field_sampler: sampler;
+
+struct Camera {
+    // sim pixels to clip space
+    view_proj: mat4x4<f32>,
+    screen: vec2<f32>,
+    // screen pixels per sim pixel
+    zoom: f32,
+    // in sim pixels, for when the points don't have their own
+    point_radius: f32,
+    // 1 if they do
+    per_point: f32,
+};
+
+@group(0) @binding(2)
+var<uniform> camera: Camera;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    // two triangles covering the sim, which is as big as the window
+    let corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+    );
+    let uv = corners[vertex_index];
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(uv * camera.screen, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(field_texture, field_sampler, in.uv);
+}
@@ -0,0 +1,165 @@
+use crate::{
+    fluid_sim::{flow::VelocityGrid, real::Real, vec2::Vec2},
+    render::lines::LineVertex,
+};
+
+/// linear rgba, the arrows are dark so they show up on the light background
+const ARROW: [f32; 4] = [0.02, 0.02, 0.05, 0.9];
+const STREAMLINE: [f32; 4] = [1., 1., 1., 0.9];
+
+/// what the flow overlay draws and how finely
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FlowConfig {
+    /// an arrow in every cell, as long as the flow's fast compared to the fastest cell
+    pub arrows: bool,
+    /// lines traced along the flow from seeds spread over the window
+    pub streamlines: bool,
+    /// how big the cells the velocities get averaged onto are, in pixels
+    pub cell: f32,
+    /// how far apart the streamline seeds are, in pixels
+    pub seed_spacing: f32,
+    /// how far a streamline goes each step, in pixels
+    pub step: f32,
+    /// the most steps a streamline takes
+    pub steps: usize,
+}
+
+impl Default for FlowConfig {
+    fn default() -> Self {
+        Self {
+            arrows: false,
+            streamlines: false,
+            cell: 32.,
+            seed_spacing: 48.,
+            step: 4.,
+            steps: 80,
+        }
+    }
+}
+
+impl FlowConfig {
+    pub fn any(&self) -> bool {
+        self.arrows || self.streamlines
+    }
+}
+
+/// an arrow through the middle of every cell with fluid in it, pairs of vertices for a line
+/// list
+pub fn arrows(grid: &VelocityGrid) -> Vec<LineVertex> {
+    let max_speed = grid.max_speed();
+    if max_speed <= 0. {
+        return Vec::new();
+    }
+    let longest = grid.cell.x.min(grid.cell.y) * 0.9;
+
+    let mut lines = Vec::new();
+    for y in 0..grid.rows {
+        for x in 0..grid.columns {
+            let Some(velocity) = grid.velocity(x, y) else {
+                continue;
+            };
+            let speed = velocity.length();
+            let length = longest * speed / max_speed;
+            if length < 1. {
+                continue;
+            }
+            let direction = velocity / speed;
+            let centre = grid.centre(x, y);
+            let tail = centre - direction * (length / 2.);
+            let tip = centre + direction * (length / 2.);
+            lines.extend([LineVertex::new(tail, ARROW), LineVertex::new(tip, ARROW)]);
+            // the head, two short lines swept back from the tip
+            for angle in [2.6, -2.6] {
+                let barb = tip + direction.rotated(angle) * (length * 0.3);
+                lines.extend([LineVertex::new(tip, ARROW), LineVertex::new(barb, ARROW)]);
+            }
+        }
+    }
+    lines
+}
+
+/// streamlines from seeds `config.seed_spacing` apart, fading out toward their ends, as
+/// pairs of vertices for a line list
+pub fn streamlines(grid: &VelocityGrid, config: &FlowConfig) -> Vec<LineVertex> {
+    let spacing = config.seed_spacing.max(4.) as Real;
+    let width = grid.columns as Real * grid.cell.x;
+    let height = grid.rows as Real * grid.cell.y;
+    let seeds_across = (width / spacing) as usize;
+    let seeds_down = (height / spacing) as usize;
+
+    let mut lines = Vec::new();
+    for y in 0..seeds_down {
+        for x in 0..seeds_across {
+            let seed = Vec2 {
+                x: (x as Real + 0.5) * spacing,
+                y: (y as Real + 0.5) * spacing,
+            };
+            let line = grid.streamline(seed, config.step as Real, config.steps);
+            let fade = |i: usize| {
+                let [r, g, b, a] = STREAMLINE;
+                [r, g, b, a * (1. - i as f32 / line.len() as f32)]
+            };
+            for (i, pair) in line.windows(2).enumerate() {
+                lines.extend([
+                    LineVertex::new(pair[0], fade(i)),
+                    LineVertex::new(pair[1], fade(i + 1)),
+                ]);
+            }
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: winit::dpi::PhysicalSize<u32> = winit::dpi::PhysicalSize::new(200, 100);
+
+    /// everything moving right at `speed`, with a still patch in the top left corner
+    fn rightward(speed: Real) -> VelocityGrid {
+        let mut positions = Vec::new();
+        let mut velocities = Vec::new();
+        for x in 0..40 {
+            for y in 0..20 {
+                positions.push(Vec2 {
+                    x: x as Real * 5. + 2.5,
+                    y: y as Real * 5. + 2.5,
+                });
+                let still = x < 10 && y < 10;
+                velocities.push(Vec2 {
+                    x: if still { 0. } else { speed },
+                    y: 0.,
+                });
+            }
+        }
+        VelocityGrid::from_particles(&positions, &velocities, 50., SIZE)
+    }
+
+    #[test]
+    fn arrows_point_along_the_flow_and_grow_with_it() {
+        let grid = rightward(100.);
+        let lines = arrows(&grid);
+        // three lines of two vertices for each of the eight cells
+        assert_eq!(lines.len(), 8 * 6);
+        for shaft in lines.chunks(6) {
+            assert!(shaft[1].position[0] > shaft[0].position[0]);
+            assert_eq!(shaft[1].position[1], shaft[0].position[1]);
+        }
+        // the cell over the still patch only gets the moving fluid round its edges
+        let length = |arrow: &[LineVertex]| arrow[1].position[0] - arrow[0].position[0];
+        assert!(length(&lines[..6]) < length(&lines[lines.len() - 6..]) / 2.);
+        assert!(arrows(&rightward(0.)).is_empty());
+    }
+
+    #[test]
+    fn streamlines_fade_toward_their_ends() {
+        let config = FlowConfig::default();
+        let lines = streamlines(&rightward(100.), &config);
+        assert!(!lines.is_empty());
+        for segment in lines.chunks(2) {
+            assert!(segment[1].colour[3] < segment[0].colour[3]);
+            assert!(segment[1].position[0] > segment[0].position[0]);
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use crate::{fluid_sim::simulation::Points, render::vertex::Vertex};
+use rayon::prelude::*;
+use wgpu::util::DeviceExt;
+
+/// the two ways the dots can get drawn, to compare how they go on different adapters
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DotPath {
+    /// six vertices a dot, each one looking its dot up in the storage buffers
+    #[default]
+    Storage,
+    /// one quad drawn once per dot, with the dots in an instance buffer built every frame
+    Instanced,
+}
+
+impl DotPath {
+    pub const ALL: [DotPath; 2] = [DotPath::Storage, DotPath::Instanced];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|path| path.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DotPath::Storage => "storage",
+            DotPath::Instanced => "instanced",
+        }
+    }
+}
+
+/// everything about one dot, for the instance buffer
+#[repr(C)]
+#[derive(bytemuck::Pod, bytemuck::Zeroable, Copy, Debug, Clone, Default, PartialEq)]
+pub struct Instance {
+    /// sim pixels
+    pub position: [f32; 2],
+    /// linear rgba
+    pub colour: [f32; 4],
+    /// sim pixels
+    pub radius: f32,
+}
+
+impl Instance {
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: 0,
+                    shader_location: 1,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32,
+                    offset: std::mem::size_of::<[f32; 6]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                },
+            ],
+        }
+    }
+}
+
+/// the colours the storage path works out in shader2.wgsl, done on the cpu instead
+pub struct Palette<'a> {
+    /// one per fluid particle, the ghosts after them don't get one
+    pub scalars: &'a [f32],
+    pub range: (f32, f32),
+    /// the colormap's table, same as the shader gets
+    pub table: Vec<[f32; 4]>,
+}
+
+impl Palette<'_> {
+    fn colour(&self, i: usize) -> [f32; 4] {
+        let Some(scalar) = self.scalars.get(i) else {
+            // body ghosts
+            return [0.2, 0.2, 0.2, 1.];
+        };
+        let (min, max) = self.range;
+        let t = ((scalar - min) / (max - min).max(1e-6)).clamp(0., 1.);
+        self.table[(t * (self.table.len() - 1) as f32).round() as usize]
+    }
+}
+
+/// a dot for every point. `radii` is empty when they're all `point_radius`, and without a
+/// palette they're all black.
+pub fn instances(
+    points: &Points,
+    radii: &[f32],
+    point_radius: f32,
+    palette: Option<&Palette>,
+) -> Vec<Instance> {
+    points
+        .fluid
+        .par_iter()
+        .chain(points.ghosts.par_iter())
+        .enumerate()
+        .map(|(i, position)| Instance {
+            position: position.to_f32(),
+            colour: palette.map_or([0., 0., 0., 1.], |palette| palette.colour(i)),
+            radius: radii.get(i).copied().unwrap_or(point_radius),
+        })
+        .collect()
+}
+
+/// the quad every dot is drawn from, as a triangle strip
+const QUAD: [Vertex; 4] = [
+    Vertex { position: [0., 0.] },
+    Vertex { position: [1., 0.] },
+    Vertex { position: [0., 1.] },
+    Vertex { position: [1., 1.] },
+];
+
+/// draws the dots as instances of one quad, from an instance buffer that grows when it needs
+/// to
+pub struct InstancedRenderer {
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    quad: wgpu::Buffer,
+    buffer: wgpu::Buffer,
+    /// how many instances fit in `buffer`
+    capacity: usize,
+    /// how many got uploaded last
+    count: usize,
+}
+
+impl InstancedRenderer {
+    /// `camera` is the same camera uniform the storage path uses
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, camera: &wgpu::Buffer) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("instanced bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("instanced bind group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera.as_entire_binding(),
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("instanced shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("./instanced.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("instanced pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[Vertex::desc(), Instance::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let quad = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("instanced quad"),
+            contents: bytemuck::cast_slice(&QUAD),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        Self {
+            pipeline,
+            bind_group,
+            quad,
+            buffer: instance_buffer(device, 0),
+            capacity: 0,
+            count: 0,
+        }
+    }
+
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, instances: &[Instance]) {
+        if instances.len() > self.capacity {
+            // some room to grow so it isn't remade every time there's a few more dots
+            self.capacity = instances.len() * 3 / 2;
+            self.buffer = instance_buffer(device, self.capacity);
+        }
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(instances));
+        self.count = instances.len();
+    }
+
+    /// the dots from `first` on, so the liquid's can be left out
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass, first: usize) {
+        if first >= self.count {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.quad.slice(..));
+        render_pass.set_vertex_buffer(1, self.buffer.slice(..));
+        render_pass.draw(0..QUAD.len() as u32, first as u32..self.count as u32);
+    }
+}
+
+fn instance_buffer(device: &wgpu::Device, instances: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("instance buffer"),
+        size: (instances.max(1) * std::mem::size_of::<Instance>()) as u64,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fluid_sim::vec2::Vec2;
+
+    #[test]
+    fn instances_get_coloured_like_the_shader_does() {
+        let fluid = [Vec2 { x: 1., y: 2. }, Vec2 { x: 3., y: 4. }];
+        let points = Points {
+            fluid: &fluid,
+            ids: &[],
+            ghosts: vec![Vec2 { x: 5., y: 6. }],
+        };
+        let palette = Palette {
+            scalars: &[0., 10.],
+            range: (0., 10.),
+            table: vec![[0., 0., 1., 1.], [0., 1., 0., 1.], [1., 0., 0., 1.]],
+        };
+
+        let coloured = instances(&points, &[3., 3., 7.], 4., Some(&palette));
+        assert_eq!(coloured.len(), 3);
+        assert_eq!(coloured[2].position, [5., 6.]);
+        assert_eq!(coloured[0].colour, [0., 0., 1., 1.]);
+        assert_eq!(coloured[1].colour, [1., 0., 0., 1.]);
+        assert_eq!(coloured[2].colour, [0.2, 0.2, 0.2, 1.]);
+        assert_eq!(coloured[2].radius, 7.);
+
+        let plain = instances(&points, &[], 4., None);
+        assert!(
+            plain
+                .iter()
+                .all(|dot| dot.radius == 4. && dot.colour == [0., 0., 0., 1.])
+        );
+        assert_eq!(DotPath::from_name("instanced"), Some(DotPath::Instanced));
+    }
+}
@@ -0,0 +1,51 @@
+// The dots again, but as instances of one quad with everything about each dot in a vertex
+// buffer instead of read out of storage buffers
+
+struct Camera {
+    // sim pixels to clip space
+    view_proj: mat4x4<f32>,
+    screen: vec2<f32>,
+    // screen pixels per sim pixel
+    zoom: f32,
+    // in sim pixels, for when the points don't have their own
+    point_radius: f32,
+    // 1 if they do
+    per_point: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+struct InstanceInput {
+    @location(1) position: vec2<f32>,
+    @location(2) colour: vec4<f32>,
+    // in sim pixels, already picked between the dot's own and the camera's
+    @location(3) radius: f32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) colour: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@location(0) corner: vec2<f32>, instance: InstanceInput) -> VertexOutput {
+    // never smaller than a pixel across, however far out the camera is
+    let radius = max(instance.radius, 0.5 / camera.zoom);
+    let position = instance.position + (corner - 0.5) * 2.0 * radius;
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(position, 0.0, 1.0);
+    out.uv = corner;
+    out.colour = instance.colour;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    if (distance(in.uv, vec2(0.5)) > 0.5) {
+        discard;
+    }
+    return in.colour;
+}
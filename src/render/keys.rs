@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use winit::keyboard::KeyCode;
+
+/// everything the keyboard can do in the viewer
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    /// stops and starts the clock
+    Pause,
+    /// one step forward, only does anything while paused
+    Step,
+    /// back to how the scene started
+    Reset,
+    /// halves the simulation speed
+    Slower,
+    /// doubles it
+    Faster,
+    /// rebuilds the scene with the next solver along
+    NextSolver,
+    /// remembers the sim as it is right now
+    Snapshot,
+    /// goes back to the remembered sim
+    Restore,
+    /// left drag switches between pulling and stirring
+    ToggleStir,
+    /// colours the particles by the next thing along, then back to plain black
+    NextColouring,
+    /// switches between dots and one smooth liquid
+    ToggleSurface,
+    /// draws a line round the fluid's surface
+    ToggleOutline,
+    /// saves the outline as an svg in the working directory
+    ExportOutline,
+    /// draws an arrow for how the fluid's moving in each patch of the window
+    ToggleArrows,
+    /// draws lines following the flow
+    ToggleStreamlines,
+    /// particles leave fading tails behind them
+    ToggleTrails,
+    /// puts the camera back to showing the whole window
+    ResetView,
+    /// shows and hides the settings window
+    ToggleOverlay,
+    Quit,
+}
+
+impl Action {
+    const ALL: [Action; 19] = [
+        Action::Pause,
+        Action::Step,
+        Action::Reset,
+        Action::Slower,
+        Action::Faster,
+        Action::NextSolver,
+        Action::Snapshot,
+        Action::Restore,
+        Action::ToggleStir,
+        Action::NextColouring,
+        Action::ToggleSurface,
+        Action::ToggleOutline,
+        Action::ExportOutline,
+        Action::ToggleArrows,
+        Action::ToggleStreamlines,
+        Action::ToggleTrails,
+        Action::ResetView,
+        Action::ToggleOverlay,
+        Action::Quit,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Pause => "pause",
+            Action::Step => "step",
+            Action::Reset => "reset",
+            Action::Slower => "slower",
+            Action::Faster => "faster",
+            Action::NextSolver => "next-solver",
+            Action::Snapshot => "snapshot",
+            Action::Restore => "restore",
+            Action::ToggleStir => "toggle-stir",
+            Action::NextColouring => "next-colouring",
+            Action::ToggleSurface => "toggle-surface",
+            Action::ToggleOutline => "toggle-outline",
+            Action::ExportOutline => "export-outline",
+            Action::ToggleArrows => "toggle-arrows",
+            Action::ToggleStreamlines => "toggle-streamlines",
+            Action::ToggleTrails => "toggle-trails",
+            Action::ResetView => "reset-view",
+            Action::ToggleOverlay => "toggle-overlay",
+            Action::Quit => "quit",
+        }
+    }
+}
+
+/// which key does what. Each key does one thing, but one thing can have more than one key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bindings(HashMap<KeyCode, Action>);
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings(HashMap::from([
+            (KeyCode::Space, Action::Pause),
+            (KeyCode::Period, Action::Step),
+            (KeyCode::KeyR, Action::Reset),
+            (KeyCode::Minus, Action::Slower),
+            (KeyCode::Equal, Action::Faster),
+            (KeyCode::Tab, Action::NextSolver),
+            (KeyCode::F5, Action::Snapshot),
+            (KeyCode::F9, Action::Restore),
+            (KeyCode::KeyS, Action::ToggleStir),
+            (KeyCode::KeyC, Action::NextColouring),
+            (KeyCode::KeyL, Action::ToggleSurface),
+            (KeyCode::KeyO, Action::ToggleOutline),
+            (KeyCode::F6, Action::ExportOutline),
+            (KeyCode::KeyV, Action::ToggleArrows),
+            (KeyCode::KeyB, Action::ToggleStreamlines),
+            (KeyCode::KeyT, Action::ToggleTrails),
+            (KeyCode::Digit0, Action::ResetView),
+            (KeyCode::F1, Action::ToggleOverlay),
+            (KeyCode::Escape, Action::Quit),
+        ]))
+    }
+}
+
+impl Bindings {
+    pub fn action(&self, key: KeyCode) -> Option<Action> {
+        self.0.get(&key).copied()
+    }
+
+    /// points `key` at `action`, taking it off whatever it did before
+    pub fn bind(&mut self, key: KeyCode, action: Action) {
+        self.0.insert(key, action);
+    }
+
+    /// takes `<key>=<action>`, like `p=pause` or `f2=next-solver`
+    pub fn bind_from_str(&mut self, binding: &str) -> Result<(), String> {
+        let (key, action) = binding
+            .split_once('=')
+            .ok_or_else(|| format!("{binding:?} wants to look like <key>=<action>"))?;
+        let key = key_from_name(key.trim()).ok_or_else(|| format!("no key called {key:?}"))?;
+        let action = Action::from_name(action.trim())
+            .ok_or_else(|| format!("no action called {action:?}"))?;
+        self.bind(key, action);
+        Ok(())
+    }
+}
+
+/// letters, digits, f keys and the handful of others worth binding, lower case
+fn key_from_name(name: &str) -> Option<KeyCode> {
+    const LETTERS: [KeyCode; 26] = [
+        KeyCode::KeyA,
+        KeyCode::KeyB,
+        KeyCode::KeyC,
+        KeyCode::KeyD,
+        KeyCode::KeyE,
+        KeyCode::KeyF,
+        KeyCode::KeyG,
+        KeyCode::KeyH,
+        KeyCode::KeyI,
+        KeyCode::KeyJ,
+        KeyCode::KeyK,
+        KeyCode::KeyL,
+        KeyCode::KeyM,
+        KeyCode::KeyN,
+        KeyCode::KeyO,
+        KeyCode::KeyP,
+        KeyCode::KeyQ,
+        KeyCode::KeyR,
+        KeyCode::KeyS,
+        KeyCode::KeyT,
+        KeyCode::KeyU,
+        KeyCode::KeyV,
+        KeyCode::KeyW,
+        KeyCode::KeyX,
+        KeyCode::KeyY,
+        KeyCode::KeyZ,
+    ];
+    const DIGITS: [KeyCode; 10] = [
+        KeyCode::Digit0,
+        KeyCode::Digit1,
+        KeyCode::Digit2,
+        KeyCode::Digit3,
+        KeyCode::Digit4,
+        KeyCode::Digit5,
+        KeyCode::Digit6,
+        KeyCode::Digit7,
+        KeyCode::Digit8,
+        KeyCode::Digit9,
+    ];
+    const F_KEYS: [KeyCode; 12] = [
+        KeyCode::F1,
+        KeyCode::F2,
+        KeyCode::F3,
+        KeyCode::F4,
+        KeyCode::F5,
+        KeyCode::F6,
+        KeyCode::F7,
+        KeyCode::F8,
+        KeyCode::F9,
+        KeyCode::F10,
+        KeyCode::F11,
+        KeyCode::F12,
+    ];
+
+    let name = name.to_lowercase();
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if c.is_ascii_lowercase() {
+            return Some(LETTERS[(c as u8 - b'a') as usize]);
+        }
+        if c.is_ascii_digit() {
+            return Some(DIGITS[(c as u8 - b'0') as usize]);
+        }
+    }
+    if let Some(number) = name.strip_prefix('f').and_then(|n| n.parse::<usize>().ok()) {
+        return F_KEYS.get(number.checked_sub(1)?).copied();
+    }
+
+    Some(match name.as_str() {
+        "space" => KeyCode::Space,
+        "tab" => KeyCode::Tab,
+        "enter" => KeyCode::Enter,
+        "escape" => KeyCode::Escape,
+        "backspace" => KeyCode::Backspace,
+        "minus" | "-" => KeyCode::Minus,
+        "equal" | "=" => KeyCode::Equal,
+        "comma" | "," => KeyCode::Comma,
+        "period" | "." => KeyCode::Period,
+        "slash" | "/" => KeyCode::Slash,
+        "left" => KeyCode::ArrowLeft,
+        "right" => KeyCode::ArrowRight,
+        "up" => KeyCode::ArrowUp,
+        "down" => KeyCode::ArrowDown,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings_can_be_moved_about() {
+        let mut bindings = Bindings::default();
+        assert_eq!(bindings.action(KeyCode::Space), Some(Action::Pause));
+
+        bindings.bind_from_str("p=pause").unwrap();
+        bindings.bind_from_str("space=step").unwrap();
+        bindings.bind_from_str("F2 = next-solver").unwrap();
+        assert_eq!(bindings.action(KeyCode::KeyP), Some(Action::Pause));
+        assert_eq!(bindings.action(KeyCode::Space), Some(Action::Step));
+        assert_eq!(bindings.action(KeyCode::F2), Some(Action::NextSolver));
+
+        assert!(bindings.bind_from_str("p").is_err());
+        assert!(bindings.bind_from_str("f13=pause").is_err());
+        assert!(bindings.bind_from_str("p=explode").is_err());
+    }
+
+    #[test]
+    fn every_action_has_a_name_that_finds_it_again() {
+        for action in Action::ALL {
+            assert_eq!(Action::from_name(action.name()), Some(action));
+        }
+    }
+}
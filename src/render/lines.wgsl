@@ -0,0 +1,39 @@
+// Plain coloured lines in sim pixels, for the arrows, streamlines and trails
+
+struct Camera {
+    // sim pixels to clip space
+    view_proj: mat4x4<f32>,
+    screen: vec2<f32>,
+    // screen pixels per sim pixel
+    zoom: f32,
+    // in sim pixels, for when the points don't have their own
+    point_radius: f32,
+    // 1 if they do
+    per_point: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) colour: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) colour: vec4<f32>,
+};
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(in.position, 0.0, 1.0);
+    out.colour = in.colour;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.colour;
+}
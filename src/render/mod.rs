@@ -1,17 +1,73 @@
+pub mod camera;
+pub mod colour;
+pub mod field;
+pub mod flow;
+pub mod instanced;
+pub mod keys;
+pub mod lines;
+pub mod mouse;
+pub mod overlay;
+pub mod surface;
+pub mod trail;
 pub mod vertex;
 
-use crate::fluid_sim::FluidSim;
-use rayon::prelude::*;
-use std::time::Instant;
-use wgpu::{util::DeviceExt, Backends, DeviceDescriptor, RequestAdapterOptions, TextureUsages};
+use crate::fluid_sim::{
+    contour::{self, Contour, ContourConfig},
+    real::{Real, to_f32},
+    simulation::{Quantity, Setup, Simulation, Solver, View},
+    vec2::Vec2,
+};
+use camera::Camera;
+use colour::{Colormap, ColourBuffers, Colouring};
+use field::{FieldLayer, FieldRenderer};
+use flow::FlowConfig;
+use instanced::{DotPath, InstancedRenderer, Palette};
+use keys::{Action, Bindings};
+use lines::LineRenderer;
+use mouse::{Mouse, MouseConfig};
+use overlay::{Overlay, Panel};
+use std::{borrow::Cow, time::Instant};
+use surface::{SurfaceRenderer, SurfaceStyle};
+use trail::{TrailConfig, Trails};
+use vertex::Vertex;
+use wgpu::{Backends, DeviceDescriptor, RequestAdapterOptions, TextureUsages, util::DeviceExt};
 use winit::{
     event::*,
     event_loop::EventLoop,
-    keyboard::{KeyCode, PhysicalKey},
+    keyboard::PhysicalKey,
     window::{Window, WindowBuilder},
 };
 
-const PARTICLE_SIZE: f32 = 5.;
+/// how big the dots are against the gap between particles, 1 and neighbours at rest touch
+pub const PARTICLE_SCALE: f32 = 0.6;
+/// how much one line of the mouse wheel zooms by
+const ZOOM_PER_LINE: f32 = 1.1;
+/// how far a single step goes while paused, before the speed gets applied
+const SINGLE_STEP: f32 = 1. / 60.;
+
+/// how the viewer looks and behaves, everything the command line can set that isn't the sim
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Options {
+    /// what was asked for on the command line, otherwise it depends on the solver
+    pub field_layer: Option<FieldLayer>,
+    /// how big the dots are against the gap between particles, when the command line said
+    pub particle_scale: Option<f32>,
+    pub mouse: MouseConfig,
+    pub bindings: Bindings,
+    pub colouring: Colouring,
+    /// start off drawing the particles as a liquid rather than dots
+    pub surface: bool,
+    pub surface_style: SurfaceStyle,
+    /// start off with a line round the fluid
+    pub outline: bool,
+    pub contours: ContourConfig,
+    /// which of the arrows and streamlines to start off with, and how dense they are
+    pub flow: FlowConfig,
+    /// whether the particles start off leaving tails, and how long
+    pub trails: TrailConfig,
+    /// which way the dots get drawn
+    pub dots: DotPath,
+}
 
 struct BigRenderBoy<'a> {
     size: winit::dpi::PhysicalSize<u32>,
@@ -22,16 +78,73 @@ struct BigRenderBoy<'a> {
     queue: wgpu::Queue,
     color: wgpu::Color,
     render_pipeline: wgpu::RenderPipeline,
-    fluid_sim: crate::fluid_sim::FluidSim,
+    sim: Box<dyn Simulation>,
+    /// what the sim was built from, so it can be rebuilt with a different solver
+    setup: Setup,
+    /// saved with F5, brought back with F9
+    snapshot: Option<Box<dyn Simulation>>,
     particle_pos_buffer: wgpu::Buffer,
+    /// the positions buffer is a gpu sim's own rather than one that gets written every frame
+    resident: bool,
+    /// how many particles fit in the buffers, they get remade if a sim needs more
+    particle_capacity: usize,
+    /// a radius for every particle, for sims where they aren't all the same size
+    radii_buffer: wgpu::Buffer,
+    particle_scale: f32,
+    colours: ColourBuffers,
+    colouring: Colouring,
+    /// the built in colormaps and whatever custom one the command line asked for
+    colormaps: Vec<Colormap>,
+    /// the numbers at either end of the colormap last frame, for the legend
+    colour_range: Option<(f32, f32)>,
+    /// only made the first time the particles get drawn as a liquid
+    surface_renderer: Option<SurfaceRenderer>,
+    /// whether they're drawn as a liquid right now
+    liquid: bool,
+    surface_style: SurfaceStyle,
+    outline: bool,
+    contour_config: ContourConfig,
+    /// the outline for this frame, empty while it's switched off
+    contours: Vec<Contour>,
+    flow: FlowConfig,
+    /// only made the first time the arrows or streamlines get switched on
+    flow_lines: Option<LineRenderer>,
+    trail_config: TrailConfig,
+    /// where the particles have been, only kept while the trails are on
+    trails: Trails,
+    /// only made the first time the trails get switched on
+    trail_lines: Option<LineRenderer>,
+    dots: DotPath,
+    /// only made the first time the dots get drawn instanced
+    instanced: Option<InstancedRenderer>,
+    screen_bind_group_layout: wgpu::BindGroupLayout,
+    /// only made once there's a grid sim to draw
+    field: Option<FieldRenderer>,
+    /// what was asked for on the command line, otherwise it depends on the solver
+    field_layer: Option<FieldLayer>,
+    mouse: Mouse,
+    bindings: Bindings,
+    paused: bool,
+    /// set by the step key, used up by the next frame
+    step_once: bool,
+    /// how many simulated seconds go by per real one
+    speed: f32,
+    /// the quit key got pressed, the event loop should stop
+    quitting: bool,
+    overlay: Overlay,
+    fps: f32,
+    /// how many particles or cells the last frame drew, for the overlay
+    drawn: String,
     last_frame_time: Instant,
-    screen_size: wgpu::Buffer,
+    camera: Camera,
+    /// the camera as the shaders see it, written every frame
+    camera_uniform: wgpu::Buffer,
     screen_bind_group: wgpu::BindGroup,
     count: usize,
 }
 
 impl<'a> BigRenderBoy<'a> {
-    pub async fn new(window: &'a Window) -> BigRenderBoy<'a> {
+    pub async fn new(window: &'a Window, setup: Setup, options: Options) -> BigRenderBoy<'a> {
         let size = window.inner_size();
 
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
@@ -85,24 +198,20 @@ impl<'a> BigRenderBoy<'a> {
 
         surface.configure(&device, &config);
 
-        let uniform_data = [size.width as f32, size.height as f32, PARTICLE_SIZE];
-        let screen_size_and_particle_size =
-            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("screen size buffer"),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-                contents: bytemuck::cast_slice(&uniform_data),
-            });
-
-        let initial_screen_size = [size.width as f32, size.height as f32];
-        queue.write_buffer(
-            &screen_size_and_particle_size,
-            0,
-            bytemuck::cast_slice(&initial_screen_size),
-        );
+        let camera = Camera::default();
+        let camera_uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("camera buffer"),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            contents: bytemuck::cast_slice(&[camera.uniform(size, 0., false)]),
+        });
 
-        let fluid_sim = FluidSim::new_rand(size);
-        let particles = fluid_sim.get_particles_vertexes();
-        let particle_data = bytemuck::cast_slice(&particles);
+        let sim = setup.build_on(size, &device, &queue);
+        // grid sims don't have any particles and gpu ones bring their own buffer, the first
+        // frame fills it in
+        let particles = match sim.view() {
+            View::Particles(particles) => particles.len(),
+            View::Field(_) | View::Resident(_) => 0,
+        };
 
         let color = wgpu::Color {
             r: 0.768627,
@@ -111,11 +220,13 @@ impl<'a> BigRenderBoy<'a> {
             a: 1.,
         };
 
-        let particle_pos_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Storage Buffer Pos"),
-            contents: particle_data,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-        });
+        let particle_pos_buffer = positions_buffer(&device, particles);
+        let radii_buffer = radii_buffer(&device, particles);
+        let colours = ColourBuffers::new(&device, particles, &options.colouring.colormap);
+        let mut colormaps = Colormap::BUILT_IN.to_vec();
+        if !colormaps.contains(&options.colouring.colormap) {
+            colormaps.push(options.colouring.colormap.clone());
+        }
 
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("the one and only shader one shall ever need"),
@@ -146,23 +257,58 @@ impl<'a> BigRenderBoy<'a> {
                         },
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
                 ],
             });
 
-        let screen_particle_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("screen bind group"),
-            layout: &screen_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: screen_size_and_particle_size.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: particle_pos_buffer.as_entire_binding(),
-                },
-            ],
-        });
+        let screen_particle_bind_group = particle_bind_group(
+            &device,
+            &screen_bind_group_layout,
+            &camera_uniform,
+            &particle_pos_buffer,
+            &radii_buffer,
+            &colours.scalars,
+            &colours,
+        );
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: None,
@@ -208,6 +354,8 @@ impl<'a> BigRenderBoy<'a> {
             cache: None,
         });
 
+        let overlay = Overlay::new(&device, config.format);
+
         let last_frame_time = Instant::now();
 
         let count = 0;
@@ -221,26 +369,362 @@ impl<'a> BigRenderBoy<'a> {
             queue,
             color,
             render_pipeline,
-            fluid_sim,
+            sim,
+            setup,
+            snapshot: None,
             particle_pos_buffer,
+            resident: false,
+            particle_capacity: particles,
+            radii_buffer,
+            particle_scale: options.particle_scale.unwrap_or(PARTICLE_SCALE),
+            colours,
+            colouring: options.colouring,
+            colormaps,
+            colour_range: None,
+            surface_renderer: None,
+            liquid: options.surface,
+            surface_style: options.surface_style,
+            outline: options.outline,
+            contour_config: options.contours,
+            contours: Vec::new(),
+            flow: options.flow,
+            flow_lines: None,
+            trail_config: options.trails,
+            trails: Trails::default(),
+            trail_lines: None,
+            dots: options.dots,
+            instanced: None,
+            screen_bind_group_layout,
+            field: None,
+            field_layer: options.field_layer,
+            mouse: Mouse::new(options.mouse),
+            bindings: options.bindings,
+            paused: false,
+            step_once: false,
+            speed: 1.,
+            quitting: false,
+            overlay,
+            fps: 0.,
+            drawn: String::new(),
             last_frame_time,
-            screen_size: screen_size_and_particle_size,
+            camera,
+            camera_uniform,
             screen_bind_group: screen_particle_bind_group,
             count,
         }
     }
 
     pub fn window(&self) -> &Window {
-        &self.window
+        self.window
+    }
+
+    /// the lattice boltzmann density barely changes, the swirls are what's interesting there
+    fn field_layer(&self) -> FieldLayer {
+        self.field_layer.unwrap_or(match self.setup.solver {
+            Solver::Lbm => FieldLayer::Vorticity,
+            _ => FieldLayer::Density,
+        })
+    }
+
+    /// swaps in a fresh sim with a different solver, starting the scene over
+    fn switch_solver(&mut self, solver: Solver) {
+        self.setup.solver = solver;
+        self.sim = self.setup.build_on(self.size, &self.device, &self.queue);
+        println!("switched to {}", self.setup.solver.name());
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let particles = self.fluid_sim.get_particles_vertexes();
-        self.queue.write_buffer(
-            &self.particle_pos_buffer,
-            0,
-            bytemuck::cast_slice(&particles),
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("the one and only"),
+            });
+
+        self.camera.pan(self.mouse.take_pan());
+        if let Some((cursor, lines)) = self.mouse.take_scroll() {
+            self.camera
+                .zoom_around(cursor, ZOOM_PER_LINE.powf(lines) as Real, self.size);
+        }
+        self.contours = if self.outline {
+            self.sim.contours(&self.contour_config, self.size)
+        } else {
+            Vec::new()
+        };
+
+        // the overlay goes first so whatever gets changed in it shows up this frame
+        let (mut solver, mut reset, mut export) = (self.setup.solver, false, false);
+        let show_settings = self.overlay.visible;
+        let pixels_per_point = self.window.scale_factor() as f32;
+        self.overlay.prepare(
+            &self.device,
+            &self.queue,
+            &mut encoder,
+            self.size,
+            pixels_per_point,
+            |context| {
+                overlay::outline(
+                    context,
+                    &self.contours,
+                    &self.camera,
+                    self.size,
+                    pixels_per_point,
+                );
+                if show_settings {
+                    overlay::settings(
+                        context,
+                        Panel {
+                            sim: self.sim.as_mut(),
+                            solver: &mut solver,
+                            paused: &mut self.paused,
+                            speed: &mut self.speed,
+                            mouse: &mut self.mouse,
+                            colouring: &mut self.colouring,
+                            colormaps: &self.colormaps,
+                            colour_range: self.colour_range,
+                            liquid: &mut self.liquid,
+                            surface: &mut self.surface_style,
+                            outline: &mut self.outline,
+                            contours: &mut self.contour_config,
+                            export: &mut export,
+                            flow: &mut self.flow,
+                            trails: &mut self.trail_config,
+                            dots: &mut self.dots,
+                            reset: &mut reset,
+                            fps: self.fps,
+                            drawn: &self.drawn,
+                        },
+                    );
+                }
+                if let (Some(quantity), Some(range)) = (self.colouring.quantity, self.colour_range)
+                {
+                    overlay::legend(context, quantity, &self.colouring.colormap, range);
+                }
+            },
         );
+        if solver != self.setup.solver {
+            self.switch_solver(solver);
+        }
+        if reset {
+            self.sim.reset();
+        }
+        if export {
+            self.export_outline();
+        }
+
+        let field_layer = self.field_layer();
+        let sim_view = self.sim.view();
+        self.drawn = match &sim_view {
+            View::Particles(particles) => format!("{} particles", particles.len()),
+            View::Resident(gpu) => format!("{} particles on the gpu", gpu.len()),
+            View::Field(field) => format!("{}x{} cells", field.columns, field.rows),
+        };
+        match &sim_view {
+            View::Particles(particles) => {
+                // coming off a gpu sim, the buffer being drawn was its own so there needs to
+                // be one of ours again
+                if particles.len() > self.particle_capacity || self.resident {
+                    if particles.len() > self.particle_capacity {
+                        self.radii_buffer = radii_buffer(&self.device, particles.len());
+                        self.colours.grow(&self.device, particles.len());
+                        self.particle_capacity = particles.len();
+                    }
+                    self.particle_pos_buffer =
+                        positions_buffer(&self.device, self.particle_capacity);
+                    self.resident = false;
+                    self.screen_bind_group = particle_bind_group(
+                        &self.device,
+                        &self.screen_bind_group_layout,
+                        &self.camera_uniform,
+                        &self.particle_pos_buffer,
+                        &self.radii_buffer,
+                        &self.colours.scalars,
+                        &self.colours,
+                    );
+                    if let Some(surface) = &mut self.surface_renderer {
+                        surface.bind_particles(&self.device, &self.particle_pos_buffer);
+                    }
+                }
+                // the fluid goes over as it sits in the sim, no copy into vertices first
+                self.queue.write_buffer(
+                    &self.particle_pos_buffer,
+                    0,
+                    &position_bytes(particles.fluid),
+                );
+                if !particles.ghosts.is_empty() {
+                    self.queue.write_buffer(
+                        &self.particle_pos_buffer,
+                        (particles.fluid.len() * std::mem::size_of::<Vertex>()) as u64,
+                        &position_bytes(&particles.ghosts),
+                    );
+                }
+                // the liquid's all one colour
+                let scalars = self
+                    .colouring
+                    .quantity
+                    .filter(|_| !self.liquid)
+                    .map(|quantity| scalars(self.sim.as_ref(), quantity, self.size));
+                self.colour_range =
+                    self.colours
+                        .upload(&self.queue, &self.colouring, scalars.as_deref());
+
+                let radii: Vec<f32> = self
+                    .sim
+                    .particle_radii(self.size)
+                    .into_iter()
+                    .map(|radius| to_f32(radius) * self.particle_scale)
+                    .collect();
+                self.queue
+                    .write_buffer(&self.radii_buffer, 0, bytemuck::cast_slice(&radii));
+                let point_radius =
+                    to_f32(self.sim.rest_spacing(self.size)) / 2. * self.particle_scale;
+                let uniform = self
+                    .camera
+                    .uniform(self.size, point_radius, !radii.is_empty());
+                self.queue
+                    .write_buffer(&self.camera_uniform, 0, bytemuck::cast_slice(&[uniform]));
+
+                if self.dots == DotPath::Instanced {
+                    let palette =
+                        scalars
+                            .as_deref()
+                            .zip(self.colour_range)
+                            .map(|(scalars, range)| Palette {
+                                scalars,
+                                range,
+                                table: self.colouring.colormap.table(),
+                            });
+                    let dots =
+                        instanced::instances(particles, &radii, point_radius, palette.as_ref());
+                    self.instanced
+                        .get_or_insert_with(|| {
+                            InstancedRenderer::new(
+                                &self.device,
+                                self.config.format,
+                                &self.camera_uniform,
+                            )
+                        })
+                        .upload(&self.device, &self.queue, &dots);
+                }
+
+                if self.trail_config.on {
+                    self.trails.record(particles, self.trail_config.length);
+                    let lines = self.trails.lines(&self.trail_config);
+                    self.trail_lines
+                        .get_or_insert_with(|| {
+                            LineRenderer::new(
+                                &self.device,
+                                self.config.format,
+                                &self.camera_uniform,
+                            )
+                        })
+                        .upload(&self.device, &self.queue, &lines);
+                } else {
+                    self.trails.clear();
+                }
+            }
+            View::Resident(gpu) => {
+                if !self.resident || self.particle_pos_buffer != *gpu.positions() {
+                    self.particle_pos_buffer = gpu.positions().clone();
+                    self.resident = true;
+                    if gpu.len() > self.particle_capacity {
+                        self.radii_buffer = radii_buffer(&self.device, gpu.len());
+                        self.colours.grow(&self.device, gpu.len());
+                        self.particle_capacity = gpu.len();
+                    }
+                    self.screen_bind_group = particle_bind_group(
+                        &self.device,
+                        &self.screen_bind_group_layout,
+                        &self.camera_uniform,
+                        &self.particle_pos_buffer,
+                        &self.radii_buffer,
+                        gpu.colours(),
+                        &self.colours,
+                    );
+                    if let Some(surface) = &mut self.surface_renderer {
+                        surface.bind_particles(&self.device, &self.particle_pos_buffer);
+                    }
+                }
+                // worked out on the gpu, waiting on a copy back every frame would stall it
+                self.colour_range = self.colours.upload_resident(
+                    &self.queue,
+                    &mut encoder,
+                    &self.colouring,
+                    self.colouring.quantity.filter(|_| !self.liquid),
+                    gpu,
+                );
+                let point_radius =
+                    to_f32(self.sim.rest_spacing(self.size)) / 2. * self.particle_scale;
+                let uniform = self.camera.uniform(self.size, point_radius, false);
+                self.queue
+                    .write_buffer(&self.camera_uniform, 0, bytemuck::cast_slice(&[uniform]));
+                // the tails would need every frame copied back
+                self.trails.clear();
+            }
+            View::Field(field) => {
+                self.colour_range = None;
+                self.trails.clear();
+                let uniform = self.camera.uniform(self.size, 0., false);
+                self.queue
+                    .write_buffer(&self.camera_uniform, 0, bytemuck::cast_slice(&[uniform]));
+                if !self
+                    .field
+                    .as_ref()
+                    .is_some_and(|renderer| renderer.fits(field))
+                {
+                    self.field = Some(FieldRenderer::new(
+                        &self.device,
+                        self.config.format,
+                        field.columns,
+                        field.rows,
+                        &self.camera_uniform,
+                    ));
+                }
+                if let Some(renderer) = &self.field {
+                    renderer.upload(&self.queue, field, field_layer);
+                }
+            }
+        }
+        // the liquid's blobs go into their own texture first, the ghosts of any bodies don't
+        // count so they still get drawn as dots on top
+        let mut first_dot = 0;
+        if let (View::Particles(_) | View::Resident(_), true) = (&sim_view, self.liquid) {
+            let surface = self.surface_renderer.get_or_insert_with(|| {
+                SurfaceRenderer::new(
+                    &self.device,
+                    self.config.format,
+                    self.size,
+                    &self.particle_pos_buffer,
+                )
+            });
+            surface.upload(
+                &self.queue,
+                &self.surface_style,
+                self.camera.view_proj(self.size).into(),
+            );
+            first_dot = match &sim_view {
+                View::Particles(particles) => particles.fluid.len(),
+                View::Resident(gpu) => gpu.len(),
+                View::Field(_) => 0,
+            };
+            surface.splat(&mut encoder, first_dot);
+        }
+
+        if self.flow.any() {
+            let grid = self.sim.velocity_grid(self.flow.cell as Real, self.size);
+            let mut lines = Vec::new();
+            if self.flow.streamlines {
+                lines.extend(flow::streamlines(&grid, &self.flow));
+            }
+            if self.flow.arrows {
+                lines.extend(flow::arrows(&grid));
+            }
+            self.flow_lines
+                .get_or_insert_with(|| {
+                    LineRenderer::new(&self.device, self.config.format, &self.camera_uniform)
+                })
+                .upload(&self.device, &self.queue, &lines);
+        }
+
         // I think this is here so that it can start writing into the buffer as soon as possible.
         // The last function doesn't start writing until it gets called to submit?
         self.queue.submit([]);
@@ -249,33 +733,66 @@ impl<'a> BigRenderBoy<'a> {
         let view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("the one and only"),
-            });
 
         {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Main render pass"),
-                color_attachments: &vec![Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(self.color),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
+            // egui wants a pass it can hang on to, dropping it at the end of the block is enough
+            let mut render_pass = encoder
+                .begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Main render pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(self.color),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                })
+                .forget_lifetime();
+
+            match &sim_view {
+                View::Particles(particles) => {
+                    if let (Some(surface), true) = (&self.surface_renderer, self.liquid) {
+                        surface.draw(&mut render_pass);
+                    }
+                    if let (Some(trails), true) = (&self.trail_lines, self.trail_config.on) {
+                        trails.draw(&mut render_pass);
+                    }
+                    match (&self.instanced, self.dots) {
+                        (Some(instanced), DotPath::Instanced) => {
+                            instanced.draw(&mut render_pass, first_dot);
+                        }
+                        _ => {
+                            render_pass.set_pipeline(&self.render_pipeline);
+                            render_pass.set_bind_group(0, &self.screen_bind_group, &[]);
 
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.screen_bind_group, &[]);
+                            let num_particles = particles.len() as u32;
+                            render_pass.draw((first_dot as u32 * 6)..(num_particles * 6), 0..1);
+                        }
+                    }
+                }
+                View::Resident(gpu) => {
+                    if let (Some(surface), true) = (&self.surface_renderer, self.liquid) {
+                        surface.draw(&mut render_pass);
+                    }
+                    render_pass.set_pipeline(&self.render_pipeline);
+                    render_pass.set_bind_group(0, &self.screen_bind_group, &[]);
+                    render_pass.draw((first_dot as u32 * 6)..(gpu.len() as u32 * 6), 0..1);
+                }
+                View::Field(_) => {
+                    if let Some(renderer) = &self.field {
+                        renderer.draw(&mut render_pass);
+                    }
+                }
+            }
+            if let (Some(flow), true) = (&self.flow_lines, self.flow.any()) {
+                flow.draw(&mut render_pass);
+            }
 
-            let num_particles = particles.len() as u32;
-            render_pass.draw(0..(num_particles * 6), 0..1);
+            self.overlay.paint(&mut render_pass);
         }
 
         self.queue.submit(std::iter::once(encoder.finish()));
@@ -283,6 +800,19 @@ impl<'a> BigRenderBoy<'a> {
         Ok(())
     }
 
+    /// writes the fluid's outline as it is right now to an svg named after the time
+    fn export_outline(&self) {
+        let contours = self.sim.contours(&self.contour_config, self.size);
+        let seconds = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        let path = format!("outline-{seconds}.svg");
+        match std::fs::write(&path, contour::to_svg(&contours, self.size)) {
+            Ok(()) => println!("saved {} outlines to {path}", contours.len()),
+            Err(error) => eprintln!("couldn't save {path}: {error}"),
+        }
+    }
+
     fn update(&mut self, delta: &std::time::Duration) {
         if self.count <= 20 {
             self.count += 1;
@@ -290,8 +820,21 @@ impl<'a> BigRenderBoy<'a> {
             self.count = 0;
         }
 
-        let dt = delta.as_secs_f32();
-        self.fluid_sim.update(dt, self.size);
+        let dt = if self.paused {
+            if !std::mem::take(&mut self.step_once) {
+                return;
+            }
+            SINGLE_STEP
+        } else {
+            delta.as_secs_f32()
+        };
+        // the mouse gets real time so a drag feels the same at any speed
+        let interaction = self
+            .mouse
+            .interaction(dt)
+            .map(|interaction| self.camera.interaction(interaction, self.size));
+        self.sim.interact(interaction, self.size);
+        self.sim.step((dt * self.speed) as Real, self.size);
     }
 
     fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -300,83 +843,247 @@ impl<'a> BigRenderBoy<'a> {
             self.config.width = new_size.width;
             self.config.height = new_size.height;
             self.surface.configure(&self.device, &self.config);
+            if let Some(surface) = &mut self.surface_renderer {
+                surface.resize(&self.device, new_size);
+            }
         }
+    }
 
-        let new_screen_size = [new_size.width as f32, new_size.height as f32, PARTICLE_SIZE];
-        self.queue
-            .write_buffer(&self.screen_size, 0, bytemuck::cast_slice(&new_screen_size));
+    fn input(&mut self, event: &WindowEvent) -> bool {
+        if self.overlay.input(event, self.window.scale_factor() as f32) {
+            return true;
+        }
+        if self.mouse.input(event) {
+            return true;
+        }
+
+        let WindowEvent::KeyboardInput {
+            event:
+                KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(key),
+                    repeat: false,
+                    ..
+                },
+            ..
+        } = event
+        else {
+            return false;
+        };
+
+        let Some(action) = self.bindings.action(*key) else {
+            return false;
+        };
+        self.act(action);
+        true
     }
 
-    fn input(&self, event: &WindowEvent) -> bool {
-        match event {
-            _ => false,
+    fn act(&mut self, action: Action) {
+        match action {
+            Action::Pause => {
+                self.paused = !self.paused;
+                println!("{}", if self.paused { "paused" } else { "running" });
+            }
+            // only means anything paused, latching it while running would cost a step on
+            // the next pause
+            Action::Step => {
+                if self.paused {
+                    self.step_once = true;
+                }
+            }
+            Action::Reset => self.sim.reset(),
+            Action::Slower | Action::Faster => {
+                let factor = if action == Action::Faster { 2. } else { 0.5 };
+                self.speed = (self.speed * factor).clamp(1. / 64., 16.);
+                println!("speed x{}", self.speed);
+            }
+            Action::NextSolver => self.switch_solver(self.setup.solver.next()),
+            Action::Snapshot => self.snapshot = Some(self.sim.snapshot()),
+            Action::Restore => {
+                if let Some(snapshot) = &self.snapshot {
+                    self.sim = snapshot.snapshot();
+                }
+            }
+            Action::ToggleStir => {
+                self.mouse.stirring = !self.mouse.stirring;
+                println!(
+                    "left drag {}",
+                    if self.mouse.stirring {
+                        "stirs"
+                    } else {
+                        "pulls"
+                    }
+                );
+            }
+            Action::NextColouring => {
+                self.colouring.next_quantity();
+                println!(
+                    "colouring by {}",
+                    self.colouring
+                        .quantity
+                        .map_or("nothing", |quantity| quantity.name())
+                );
+            }
+            Action::ToggleSurface => self.liquid = !self.liquid,
+            Action::ToggleOutline => self.outline = !self.outline,
+            Action::ExportOutline => self.export_outline(),
+            Action::ToggleArrows => self.flow.arrows = !self.flow.arrows,
+            Action::ToggleStreamlines => self.flow.streamlines = !self.flow.streamlines,
+            Action::ToggleTrails => self.trail_config.on = !self.trail_config.on,
+            Action::ResetView => self.camera = Camera::default(),
+            Action::ToggleOverlay => self.overlay.visible = !self.overlay.visible,
+            Action::Quit => self.quitting = true,
         }
     }
 }
 
-pub async fn run() {
+pub async fn run(setup: Setup, options: Options) {
     env_logger::init();
     let event_loop = EventLoop::new().unwrap();
     let window = WindowBuilder::new().build(&event_loop).unwrap();
 
-    let mut state = BigRenderBoy::new(&window).await;
+    let mut state = BigRenderBoy::new(&window, setup, options).await;
 
     _ = event_loop.run(move |event, control_flow| match event {
         winit::event::Event::WindowEvent {
             ref event,
             window_id,
         } if window_id == state.window().id() => {
-            if !state.input(event) {
-                match event {
-                    WindowEvent::CloseRequested
-                    | WindowEvent::KeyboardInput {
-                        event:
-                            KeyEvent {
-                                state: ElementState::Pressed,
-                                physical_key: PhysicalKey::Code(KeyCode::Escape),
-                                ..
-                            },
-                        ..
-                    } => control_flow.exit(),
-                    WindowEvent::Resized(physical_size) => {
-                        state.resize(*physical_size);
+            if state.input(event) {
+                if state.quitting {
+                    control_flow.exit();
+                }
+                return;
+            }
+            match event {
+                WindowEvent::CloseRequested => control_flow.exit(),
+                WindowEvent::Resized(physical_size) => {
+                    state.resize(*physical_size);
+                }
+                WindowEvent::RedrawRequested => {
+                    state.window().request_redraw();
+
+                    let now = Instant::now();
+                    let delta = now - state.last_frame_time;
+                    state.last_frame_time = now;
+                    state.update(&delta);
+
+                    let fps = 1.0 / delta.as_secs_f32();
+                    state.fps = fps;
+                    let fps_string = format!("FPS: {}", fps);
+                    if state.count == 20 {
+                        state.window.set_title(&fps_string);
+                        println!("{fps_string} {}", state.sim.diagnostics());
                     }
-                    WindowEvent::RedrawRequested => {
-                        state.window().request_redraw();
-
-                        let now = Instant::now();
-                        let delta = now - state.last_frame_time;
-                        state.last_frame_time = now;
-                        state.update(&delta);
-
-                        let fps = 1.0 / delta.as_secs_f32();
-                        let fps_string = format!("FPS: {}", fps);
-                        match state.count {
-                            20 => {
-                                state.window.set_title(&fps_string);
-                                println!("{fps_string}");
-                            }
-                            _ => (),
-                        }
 
-                        match state.render() {
-                            Ok(()) => {}
-                            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
-                                state.resize(state.size);
-                            }
-                            Err(wgpu::SurfaceError::OutOfMemory | wgpu::SurfaceError::Other) => {
-                                eprintln!("oh fuck we out of space");
-                                control_flow.exit();
-                            }
-                            Err(wgpu::SurfaceError::Timeout) => {
-                                eprintln!("oh fuck you slow");
-                            }
+                    match state.render() {
+                        Ok(()) => {}
+                        Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                            state.resize(state.size);
+                        }
+                        Err(wgpu::SurfaceError::OutOfMemory | wgpu::SurfaceError::Other) => {
+                            eprintln!("oh fuck we out of space");
+                            control_flow.exit();
+                        }
+                        Err(wgpu::SurfaceError::Timeout) => {
+                            eprintln!("oh fuck you slow");
                         }
                     }
-                    _ => {}
                 }
+                _ => {}
             }
         }
         _ => {}
     });
 }
+
+fn particle_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    camera: &wgpu::Buffer,
+    particles: &wgpu::Buffer,
+    radii: &wgpu::Buffer,
+    scalars: &wgpu::Buffer,
+    colours: &ColourBuffers,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("screen bind group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: particles.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: scalars.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: colours.colormap.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: colours.uniform.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: radii.as_entire_binding(),
+            },
+        ],
+    })
+}
+
+// the sims' positions get cast straight into the buffer the dots read as vertices
+#[cfg(not(feature = "f64"))]
+const _: () = assert!(std::mem::size_of::<Vec2>() == std::mem::size_of::<Vertex>());
+
+/// `positions` laid out like the vertices the dots read, which they already are in `f32`
+#[cfg(not(feature = "f64"))]
+fn position_bytes(positions: &[Vec2]) -> Cow<'_, [u8]> {
+    Cow::Borrowed(bytemuck::cast_slice(positions))
+}
+
+/// narrowed straight into the bytes, so it's the one allocation a frame
+#[cfg(feature = "f64")]
+fn position_bytes(positions: &[Vec2]) -> Cow<'_, [u8]> {
+    let mut bytes = Vec::with_capacity(positions.len() * std::mem::size_of::<Vertex>());
+    for position in positions {
+        bytes.extend_from_slice(bytemuck::bytes_of(&position.to_f32()));
+    }
+    Cow::Owned(bytes)
+}
+
+/// what the sim has for `quantity` at each particle, narrowed for the colour buffer
+fn scalars(
+    sim: &dyn Simulation,
+    quantity: Quantity,
+    size: winit::dpi::PhysicalSize<u32>,
+) -> Vec<f32> {
+    sim.scalars(quantity, size)
+        .into_iter()
+        .map(to_f32)
+        .collect()
+}
+
+fn positions_buffer(device: &wgpu::Device, particles: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Storage Buffer Pos"),
+        size: (particles.max(1) * std::mem::size_of::<Vertex>()) as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn radii_buffer(device: &wgpu::Device, particles: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("radii buffer"),
+        size: (particles.max(1) * std::mem::size_of::<f32>()) as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
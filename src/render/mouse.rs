@@ -0,0 +1,133 @@
+use crate::fluid_sim::{
+    real::Real,
+    simulation::{Interaction, Tool},
+    vec2::Vec2,
+};
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+
+/// how far the mouse reaches and how hard it pokes
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MouseConfig {
+    /// window pixels
+    pub radius: f32,
+    /// pixels per second squared in the middle when pulling or pushing
+    pub strength: f32,
+    /// how many times a second stirred fluid catches up with the cursor
+    pub stir_rate: f32,
+}
+
+impl Default for MouseConfig {
+    fn default() -> Self {
+        Self {
+            radius: 120.,
+            strength: 3000.,
+            stir_rate: 8.,
+        }
+    }
+}
+
+/// keeps track of the cursor and buttons between frames. Left drag pulls the fluid in, or
+/// stirs it when `stirring` is on, and right drag pushes it away.
+#[derive(Clone, Debug, Default)]
+pub struct Mouse {
+    pub config: MouseConfig,
+    pub stirring: bool,
+    /// window pixels, nothing when it's outside the window
+    cursor: Option<Vec2>,
+    /// where the cursor was at the last frame, for how fast it's going
+    last: Option<Vec2>,
+    left: bool,
+    right: bool,
+    middle: bool,
+    /// screen pixels dragged with the middle button since the camera last moved
+    pan: Vec2,
+    /// wheel lines scrolled since the camera last zoomed, up is positive
+    scroll: f32,
+}
+
+impl Mouse {
+    pub fn new(config: MouseConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// gives back true if the event was the mouse's
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            // physical pixels, the same as the window size the sim gets, so it still lines up
+            // after the window's been resized
+            WindowEvent::CursorMoved { position, .. } => {
+                let cursor = Vec2 {
+                    x: position.x as Real,
+                    y: position.y as Real,
+                };
+                if let (true, Some(last)) = (self.middle, self.cursor) {
+                    self.pan += cursor - last;
+                }
+                self.cursor = Some(cursor);
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    // about how far a line goes on most systems
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 40.,
+                };
+            }
+            WindowEvent::CursorLeft { .. } => self.cursor = None,
+            WindowEvent::MouseInput { state, button, .. } => {
+                let pressed = *state == ElementState::Pressed;
+                match button {
+                    MouseButton::Left => self.left = pressed,
+                    MouseButton::Right => self.right = pressed,
+                    MouseButton::Middle => self.middle = pressed,
+                    _ => return false,
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// how far the middle button's dragged in screen pixels since last time
+    pub fn take_pan(&mut self) -> Vec2 {
+        std::mem::take(&mut self.pan)
+    }
+
+    /// where the cursor is and how many lines the wheel's gone since last time, if it's
+    /// moved at all
+    pub fn take_scroll(&mut self) -> Option<(Vec2, f32)> {
+        let scroll = std::mem::take(&mut self.scroll);
+        (scroll != 0.).then_some((self.cursor?, scroll))
+    }
+
+    /// what the mouse is doing to the fluid this frame, `delta` is the time since the last
+    /// one
+    pub fn interaction(&mut self, delta: f32) -> Option<Interaction> {
+        let last = std::mem::replace(&mut self.last, self.cursor);
+        let position = self.cursor?;
+
+        let tool = match (self.left, self.right) {
+            (_, true) => Tool::Repel,
+            (true, false) if self.stirring => Tool::Stir,
+            (true, false) => Tool::Attract,
+            (false, false) => return None,
+        };
+        let velocity = match last {
+            Some(last) if delta > 0. => (position - last) / delta as Real,
+            _ => Vec2::default(),
+        };
+
+        Some(Interaction {
+            tool,
+            position,
+            velocity,
+            radius: self.config.radius as Real,
+            strength: match tool {
+                Tool::Stir => self.config.stir_rate as Real,
+                Tool::Attract | Tool::Repel => self.config.strength as Real,
+            },
+        })
+    }
+}
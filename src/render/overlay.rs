@@ -0,0 +1,550 @@
+use crate::{
+    fluid_sim::{
+        contour::{Contour, ContourConfig},
+        real::to_f32,
+        simulation::{Quantity, Simulation, Solver},
+    },
+    render::{
+        camera::Camera,
+        colour::{Colormap, ColourRange, Colouring},
+        flow::FlowConfig,
+        instanced::DotPath,
+        mouse::Mouse,
+        surface::SurfaceStyle,
+        trail::TrailConfig,
+    },
+};
+use std::time::Instant;
+use winit::{
+    event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::Key,
+};
+
+/// egui drawn over the top of the sim, the settings window and the colour legend. egui-winit
+/// wants a newer winit than this, so the window events get turned into egui ones by hand.
+pub struct Overlay {
+    pub visible: bool,
+    context: egui::Context,
+    renderer: egui_wgpu::Renderer,
+    /// everything that's happened since the last frame, in egui's terms
+    events: Vec<egui::Event>,
+    modifiers: egui::Modifiers,
+    /// in points, egui wants to know where a click happened
+    pointer: egui::Pos2,
+    start: Instant,
+    /// what the last frame drew, waiting for the render pass
+    paint_jobs: Vec<egui::ClippedPrimitive>,
+    screen: egui_wgpu::ScreenDescriptor,
+    /// textures egui is finished with, they go once the frame that used them last is drawn
+    to_free: Vec<egui::TextureId>,
+}
+
+impl Overlay {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        Self {
+            visible: true,
+            context: egui::Context::default(),
+            renderer: egui_wgpu::Renderer::new(device, format, None, 1, false),
+            events: Vec::new(),
+            modifiers: egui::Modifiers::default(),
+            pointer: egui::Pos2::ZERO,
+            start: Instant::now(),
+            paint_jobs: Vec::new(),
+            screen: egui_wgpu::ScreenDescriptor {
+                size_in_pixels: [1, 1],
+                pixels_per_point: 1.,
+            },
+            to_free: Vec::new(),
+        }
+    }
+
+    /// hands the event to egui. Gives back true if egui wants it to itself, like a click on
+    /// a slider, so the sim doesn't get poked through the window.
+    pub fn input(&mut self, event: &WindowEvent, pixels_per_point: f32) -> bool {
+        if !self.visible {
+            return false;
+        }
+
+        match event {
+            // the cursor position always goes through, the mouse tool needs it too
+            WindowEvent::CursorMoved { position, .. } => {
+                self.pointer = egui::pos2(
+                    position.x as f32 / pixels_per_point,
+                    position.y as f32 / pixels_per_point,
+                );
+                self.events.push(egui::Event::PointerMoved(self.pointer));
+                false
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.events.push(egui::Event::PointerGone);
+                false
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button {
+                    MouseButton::Left => egui::PointerButton::Primary,
+                    MouseButton::Right => egui::PointerButton::Secondary,
+                    MouseButton::Middle => egui::PointerButton::Middle,
+                    _ => return false,
+                };
+                let pressed = *state == ElementState::Pressed;
+                self.events.push(egui::Event::PointerButton {
+                    pos: self.pointer,
+                    button,
+                    pressed,
+                    modifiers: self.modifiers,
+                });
+                // letting go always has to reach the sim too, or a drag would stick
+                pressed && self.context.wants_pointer_input()
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let (unit, delta) = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => {
+                        (egui::MouseWheelUnit::Line, egui::vec2(*x, *y))
+                    }
+                    MouseScrollDelta::PixelDelta(delta) => (
+                        egui::MouseWheelUnit::Point,
+                        egui::vec2(delta.x as f32, delta.y as f32) / pixels_per_point,
+                    ),
+                };
+                self.events.push(egui::Event::MouseWheel {
+                    unit,
+                    delta,
+                    modifiers: self.modifiers,
+                });
+                self.context.is_pointer_over_area()
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                let state = modifiers.state();
+                self.modifiers = egui::Modifiers {
+                    alt: state.alt_key(),
+                    ctrl: state.control_key(),
+                    shift: state.shift_key(),
+                    mac_cmd: false,
+                    command: state.control_key() || state.super_key(),
+                };
+                false
+            }
+            WindowEvent::KeyboardInput { event, .. } => {
+                let pressed = event.state == ElementState::Pressed;
+                let key = match &event.logical_key {
+                    Key::Named(named) => egui::Key::from_name(&format!("{named:?}")),
+                    Key::Character(character) => egui::Key::from_name(character),
+                    _ => None,
+                };
+                if let Some(key) = key {
+                    self.events.push(egui::Event::Key {
+                        key,
+                        physical_key: None,
+                        pressed,
+                        repeat: event.repeat,
+                        modifiers: self.modifiers,
+                    });
+                }
+                if let Some(text) = &event.text
+                    && pressed
+                    && text.chars().all(|c| !c.is_control())
+                {
+                    self.events.push(egui::Event::Text(text.to_string()));
+                }
+                self.context.wants_keyboard_input()
+            }
+            WindowEvent::Focused(focused) => {
+                self.events.push(egui::Event::WindowFocused(*focused));
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// runs `ui` and gets what it drew ready for `paint`
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        size: winit::dpi::PhysicalSize<u32>,
+        pixels_per_point: f32,
+        ui: impl FnMut(&egui::Context),
+    ) {
+        for id in self.to_free.drain(..) {
+            self.renderer.free_texture(&id);
+        }
+        self.paint_jobs.clear();
+
+        let mut input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(
+                egui::Pos2::ZERO,
+                egui::vec2(size.width as f32, size.height as f32) / pixels_per_point,
+            )),
+            time: Some(self.start.elapsed().as_secs_f64()),
+            modifiers: self.modifiers,
+            events: std::mem::take(&mut self.events),
+            focused: true,
+            ..Default::default()
+        };
+        input
+            .viewports
+            .entry(egui::ViewportId::ROOT)
+            .or_default()
+            .native_pixels_per_point = Some(pixels_per_point);
+
+        let output = self.context.run(input, ui);
+        self.paint_jobs = self
+            .context
+            .tessellate(output.shapes, output.pixels_per_point);
+        self.screen = egui_wgpu::ScreenDescriptor {
+            size_in_pixels: [size.width, size.height],
+            pixels_per_point: output.pixels_per_point,
+        };
+
+        for (id, delta) in &output.textures_delta.set {
+            self.renderer.update_texture(device, queue, *id, delta);
+        }
+        self.to_free = output.textures_delta.free;
+        // egui-wgpu hands back command buffers for paint callbacks, there aren't any of those
+        let _ =
+            self.renderer
+                .update_buffers(device, queue, encoder, &self.paint_jobs, &self.screen);
+    }
+
+    pub fn paint(&self, render_pass: &mut wgpu::RenderPass<'static>) {
+        if !self.paint_jobs.is_empty() {
+            self.renderer
+                .render(render_pass, &self.paint_jobs, &self.screen);
+        }
+    }
+}
+
+/// everything the settings window can see and change
+pub struct Panel<'a> {
+    pub sim: &'a mut dyn Simulation,
+    pub solver: &'a mut Solver,
+    pub paused: &'a mut bool,
+    pub speed: &'a mut f32,
+    pub mouse: &'a mut Mouse,
+    pub colouring: &'a mut Colouring,
+    /// what the colormap box offers
+    pub colormaps: &'a [Colormap],
+    /// what the colours covered last frame, a fixed range starts from here
+    pub colour_range: Option<(f32, f32)>,
+    /// drawing the particles as one liquid instead of dots
+    pub liquid: &'a mut bool,
+    pub surface: &'a mut SurfaceStyle,
+    /// drawing a line round the fluid
+    pub outline: &'a mut bool,
+    pub contours: &'a mut ContourConfig,
+    /// set when the save svg button gets pressed
+    pub export: &'a mut bool,
+    /// the arrows and streamlines
+    pub flow: &'a mut FlowConfig,
+    pub trails: &'a mut TrailConfig,
+    /// which way the dots get drawn
+    pub dots: &'a mut DotPath,
+    /// set when the reset button gets pressed
+    pub reset: &'a mut bool,
+    pub fps: f32,
+    /// how many particles or cells there are
+    pub drawn: &'a str,
+}
+
+/// the settings window: what's running, how it's going, and a slider for every knob the sim
+/// has. Anything changed goes straight into the running sim.
+pub fn settings(context: &egui::Context, panel: Panel) {
+    egui::Window::new("settings")
+        .default_pos(egui::pos2(10., 10.))
+        .show(context, |ui| {
+            ui.label(format!("{:.0} fps, {}", panel.fps, panel.drawn));
+            ui.label(panel.sim.diagnostics());
+            ui.separator();
+
+            egui::ComboBox::from_label("solver")
+                .selected_text(panel.solver.name())
+                .show_ui(ui, |ui| {
+                    for solver in Solver::ALL {
+                        ui.selectable_value(panel.solver, solver, solver.name());
+                    }
+                });
+            ui.horizontal(|ui| {
+                ui.checkbox(panel.paused, "paused");
+                if ui.button("reset").clicked() {
+                    *panel.reset = true;
+                }
+            });
+            ui.add(
+                egui::Slider::new(panel.speed, 1. / 64.0..=16.)
+                    .logarithmic(true)
+                    .text("speed"),
+            );
+            ui.separator();
+
+            for parameter in panel.sim.parameters() {
+                let mut value = parameter.value;
+                let logarithmic = *parameter.range.start() > 0.
+                    && parameter.range.end() / parameter.range.start() >= 100.;
+                let slider = egui::Slider::new(&mut value, parameter.range)
+                    .text(parameter.name)
+                    .logarithmic(logarithmic);
+                let slider = if parameter.whole {
+                    slider.integer()
+                } else {
+                    slider
+                };
+                if ui.add(slider).changed() {
+                    panel.sim.set_parameter(parameter.name, value);
+                }
+            }
+            ui.separator();
+
+            let mouse = panel.mouse;
+            ui.checkbox(&mut mouse.stirring, "left drag stirs");
+            ui.add(egui::Slider::new(&mut mouse.config.radius, 10.0..=400.).text("mouse radius"));
+            ui.add(
+                egui::Slider::new(&mut mouse.config.strength, 100.0..=20000.)
+                    .logarithmic(true)
+                    .text("mouse strength"),
+            );
+            ui.add(egui::Slider::new(&mut mouse.config.stir_rate, 0.5..=30.).text("stir rate"));
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                ui.checkbox(panel.outline, "outline");
+                if ui.button("save svg").clicked() {
+                    *panel.export = true;
+                }
+            });
+            if *panel.outline {
+                let contours = panel.contours;
+                ui.add(egui::Slider::new(&mut contours.level, 0.05..=3.).text("outline level"));
+                ui.add(egui::Slider::new(&mut contours.cell, 1.0..=16.).text("outline cell"));
+                ui.add(egui::Slider::new(&mut contours.radius, 2.0..=40.).text("outline blob"));
+            }
+            let flow = panel.flow;
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut flow.arrows, "arrows");
+                ui.checkbox(&mut flow.streamlines, "streamlines");
+            });
+            if flow.any() {
+                ui.add(egui::Slider::new(&mut flow.cell, 8.0..=128.).text("arrow cell"));
+            }
+            if flow.streamlines {
+                ui.add(
+                    egui::Slider::new(&mut flow.seed_spacing, 8.0..=200.).text("streamline gap"),
+                );
+                ui.add(egui::Slider::new(&mut flow.steps, 1..=400).text("streamline steps"));
+            }
+            let trails = panel.trails;
+            ui.checkbox(&mut trails.on, "trails");
+            if trails.on {
+                ui.add(egui::Slider::new(&mut trails.length, 2..=120).text("trail length"));
+                ui.add(egui::Slider::new(&mut trails.fade, 0.25..=4.).text("trail fade"));
+            }
+            ui.checkbox(panel.liquid, "draw as a liquid");
+            if *panel.liquid {
+                surface_settings(ui, panel.surface);
+            } else {
+                egui::ComboBox::from_label("dots")
+                    .selected_text(panel.dots.name())
+                    .show_ui(ui, |ui| {
+                        for path in DotPath::ALL {
+                            ui.selectable_value(panel.dots, path, path.name());
+                        }
+                    });
+                colour_settings(ui, panel.colouring, panel.colormaps, panel.colour_range);
+            }
+        });
+}
+
+fn surface_settings(ui: &mut egui::Ui, style: &mut SurfaceStyle) {
+    ui.horizontal(|ui| {
+        ui.color_edit_button_rgb(&mut style.colour);
+        ui.label("liquid");
+        ui.color_edit_button_rgb(&mut style.highlight);
+        ui.label("rim");
+    });
+    ui.add(egui::Slider::new(&mut style.opacity, 0.0..=1.).text("opacity"));
+    ui.add(egui::Slider::new(&mut style.threshold, 0.05..=3.).text("threshold"));
+    ui.add(egui::Slider::new(&mut style.rim, 0.0..=3.).text("rim width"));
+    ui.add(egui::Slider::new(&mut style.radius, 2.0..=40.).text("blob radius"));
+}
+
+fn colour_settings(
+    ui: &mut egui::Ui,
+    colouring: &mut Colouring,
+    colormaps: &[Colormap],
+    last_range: Option<(f32, f32)>,
+) {
+    egui::ComboBox::from_label("colour by")
+        .selected_text(colouring.quantity.map_or("nothing", Quantity::name))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(&mut colouring.quantity, None, "nothing");
+            for quantity in Quantity::ALL {
+                ui.selectable_value(&mut colouring.quantity, Some(quantity), quantity.name());
+            }
+        });
+    egui::ComboBox::from_label("colormap")
+        .selected_text(colouring.colormap.name())
+        .show_ui(ui, |ui| {
+            for colormap in colormaps {
+                ui.selectable_value(&mut colouring.colormap, colormap.clone(), colormap.name());
+            }
+        });
+
+    let mut auto = colouring.range == ColourRange::Auto;
+    if ui.checkbox(&mut auto, "automatic range").changed() {
+        colouring.range = if auto {
+            ColourRange::Auto
+        } else {
+            let (min, max) = last_range.unwrap_or((0., 1.));
+            ColourRange::Fixed(min, max)
+        };
+    }
+    if let ColourRange::Fixed(min, max) = &mut colouring.range {
+        ui.horizontal(|ui| {
+            let speed = ((*max - *min).abs() * 0.01).max(1e-3);
+            ui.add(egui::DragValue::new(min).speed(speed).prefix("min "));
+            ui.add(egui::DragValue::new(max).speed(speed).prefix("max "));
+        });
+        // the shader copes with an empty range, but it's no use to anyone
+        if *max <= *min {
+            *max = *min + 1e-3;
+        }
+    }
+}
+
+/// lines round the fluid, behind everything else egui draws. `contours` are in sim pixels
+/// and go where the camera puts them.
+pub fn outline(
+    context: &egui::Context,
+    contours: &[Contour],
+    camera: &Camera,
+    size: winit::dpi::PhysicalSize<u32>,
+    pixels_per_point: f32,
+) {
+    let painter = context.layer_painter(egui::LayerId::background());
+    let stroke = egui::Stroke::new(1.5, egui::Color32::from_rgb(20, 20, 20));
+    for contour in contours {
+        let points = contour
+            .points
+            .iter()
+            .map(|point| {
+                let on_screen = camera.screen_point(*point, size);
+                egui::pos2(
+                    to_f32(on_screen.x) / pixels_per_point,
+                    to_f32(on_screen.y) / pixels_per_point,
+                )
+            })
+            .collect();
+        painter.add(egui::Shape::closed_line(points, stroke));
+    }
+}
+
+/// a strip of the colormap in the bottom right corner with the numbers at either end
+pub fn legend(context: &egui::Context, quantity: Quantity, colormap: &Colormap, range: (f32, f32)) {
+    const SEGMENTS: usize = 64;
+    let to_colour = |t: f32| {
+        let [r, g, b] = colormap.sample(t).map(|c| (c * 255.).round() as u8);
+        egui::Color32::from_rgb(r, g, b)
+    };
+
+    egui::Area::new(egui::Id::new("legend"))
+        .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-10., -10.))
+        .interactable(false)
+        .show(context, |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.label(quantity.name());
+                let (rect, _) = ui.allocate_exact_size(egui::vec2(200., 14.), egui::Sense::hover());
+                let mut mesh = egui::Mesh::default();
+                let width = rect.width() / SEGMENTS as f32;
+                for i in 0..SEGMENTS {
+                    let left = rect.left() + i as f32 * width;
+                    let segment = egui::Rect::from_min_max(
+                        egui::pos2(left, rect.top()),
+                        egui::pos2(left + width, rect.bottom()),
+                    );
+                    let t = (i as f32 + 0.5) / SEGMENTS as f32;
+                    mesh.add_colored_rect(segment, to_colour(t));
+                }
+                ui.painter().add(mesh);
+                ui.horizontal(|ui| {
+                    ui.label(format!("{:.3}", range.0));
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        ui.label(format!("{:.3}", range.1));
+                    });
+                });
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fluid_sim::simulation::Setup;
+
+    #[test]
+    fn settings_window_draws_for_every_solver() {
+        let context = egui::Context::default();
+        let size = winit::dpi::PhysicalSize::new(200, 150);
+        for solver in Solver::ALL {
+            let mut sim = Setup {
+                solver,
+                ..Default::default()
+            }
+            .build(size);
+            let (mut chosen, mut paused, mut speed, mut reset) = (solver, false, 1., false);
+            let mut mouse = Mouse::default();
+            let mut colouring = Colouring::default();
+            let mut style = SurfaceStyle::default();
+            let (mut outline, mut contours, mut export) = (true, ContourConfig::default(), false);
+            let mut flow = FlowConfig {
+                arrows: true,
+                streamlines: true,
+                ..Default::default()
+            };
+            let mut trails = TrailConfig {
+                on: true,
+                ..Default::default()
+            };
+
+            let output = context.run(egui::RawInput::default(), |context| {
+                settings(
+                    context,
+                    Panel {
+                        sim: sim.as_mut(),
+                        solver: &mut chosen,
+                        paused: &mut paused,
+                        speed: &mut speed,
+                        mouse: &mut mouse,
+                        colouring: &mut colouring,
+                        colormaps: &Colormap::BUILT_IN,
+                        colour_range: Some((0., 1.)),
+                        liquid: &mut (solver == Solver::Lbm),
+                        surface: &mut style,
+                        outline: &mut outline,
+                        contours: &mut contours,
+                        export: &mut export,
+                        flow: &mut flow,
+                        trails: &mut trails,
+                        dots: &mut DotPath::Instanced,
+                        reset: &mut reset,
+                        fps: 60.,
+                        drawn: "",
+                    },
+                )
+            });
+            assert!(!output.shapes.is_empty());
+            assert_eq!(chosen, solver);
+        }
+    }
+
+    #[test]
+    fn legend_draws_for_every_colormap() {
+        let context = egui::Context::default();
+        let custom = Colormap::from_name("#000000,#ffffff").unwrap();
+        for colormap in Colormap::BUILT_IN.iter().chain([&custom]) {
+            let output = context.run(egui::RawInput::default(), |context| {
+                legend(context, Quantity::Speed, colormap, (0., 250.))
+            });
+            assert!(!output.shapes.is_empty());
+        }
+    }
+}
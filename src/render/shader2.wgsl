@@ -1,22 +1,60 @@
-// Uniforms to control the canvas and point size
+// Where the camera's looking and how big the points are
+struct Camera {
+    // sim pixels to clip space
+    view_proj: mat4x4<f32>,
+    screen: vec2<f32>,
+    // screen pixels per sim pixel
+    zoom: f32,
+    // in sim pixels, for when the points don't have their own
+    point_radius: f32,
+    // 1 if they do
+    per_point: f32,
+};
+
 @group(0) @binding(0)
-var<uniform> screen_and_size: vec3<f32>; // screen.x, screen.y, point_size
+var<uniform> camera: Camera;
 
 // Your list of points, now in a storage buffer
 @group(0) @binding(1)
 var<storage, read> point_data: array<vec2<f32>>;
 
+// One number per particle to colour it by, the ghosts on the end don't get one
+@group(0) @binding(2)
+var<storage, read> scalars: array<f32>;
+
+// The colormap, already linear
+@group(0) @binding(3)
+var<storage, read> colormap: array<vec4<f32>>;
+
+// min, max, how many points have a scalar, 1 if colouring is on at all
+@group(0) @binding(4)
+var<uniform> colouring: vec4<f32>;
+
+// A radius per point in sim pixels, only read when camera.per_point is set
+@group(0) @binding(5)
+var<storage, read> radii: array<f32>;
+
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) uv: vec2<f32>, // To draw a circle in the fragment shader
+    @location(1) colour: vec4<f32>,
 };
 
+fn point_colour(point_index: u32) -> vec4<f32> {
+    if (colouring.w == 0.0) {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+    // body ghosts
+    if (f32(point_index) >= colouring.z) {
+        return vec4<f32>(0.2, 0.2, 0.2, 1.0);
+    }
+    let t = clamp((scalars[point_index] - colouring.x) / max(colouring.y - colouring.x, 1e-6), 0.0, 1.0);
+    let entries = arrayLength(&colormap);
+    return colormap[u32(round(t * f32(entries - 1u)))];
+}
+
 @vertex
 fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
-    // Deconstruct uniforms
-    let screen = vec2<f32>(screen_and_size.x, screen_and_size.y);
-    let point_size = screen_and_size.z;
-
     // Define the 4 corners of a square, which will form two triangles.
     // (0,0), (1,0), (0,1) and (1,0), (1,1), (0,1) in a triangle list.
     let quad_offsets = array<vec2<f32>, 6>(
@@ -36,18 +74,21 @@ fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
     let corner_offset = quad_offsets[corner_index];
     let point_center = point_data[point_index];
 
-    // Calculate the vertex position in pixel space
-    let offset = (corner_offset - 0.5) * point_size;
-    let final_position = point_center + offset;
+    var radius = camera.point_radius;
+    if (camera.per_point != 0.0) {
+        radius = radii[point_index];
+    }
+    // never smaller than a pixel across, however far out the camera is
+    radius = max(radius, 0.5 / camera.zoom);
 
-    // Convert from pixel space to clip space
-    let zero_to_one = final_position / screen;
-    let zero_to_two = zero_to_one * 2.0;
-    let clip_space = zero_to_two - 1.0;
+    // Calculate the vertex position in sim pixels
+    let offset = (corner_offset - 0.5) * 2.0 * radius;
+    let final_position = point_center + offset;
 
     var out: VertexOutput;
-    out.clip_position = vec4<f32>(clip_space.x, -clip_space.y, 0.0, 1.0);
+    out.clip_position = camera.view_proj * vec4<f32>(final_position, 0.0, 1.0);
     out.uv = corner_offset; // Pass the corner offset as UV coordinates
+    out.colour = point_colour(point_index);
     return out;
 }
 
@@ -61,7 +102,6 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
         discard;
     }
 
-    // Return a solid color for the circle
-    return vec4<f32>(0.0, 0.0, 0.0, 1.0); 
+    return in.colour;
 }
 
@@ -0,0 +1,47 @@
+// Adds a soft blob for every particle into the offscreen density texture that surface.wgsl
+// turns into liquid. The target blends additively, so the blobs sum up where they overlap.
+
+struct Splat {
+    // sim pixels to clip space, the same as the camera's
+    view_proj: mat4x4<f32>,
+    // blob radius in sim pixels
+    radius: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> splat: Splat;
+
+@group(0) @binding(1)
+var<storage, read> point_data: array<vec2<f32>>;
+
+struct SplatOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // -1 to 1 across the blob
+    @location(0) offset: vec2<f32>,
+};
+
+@vertex
+fn vs_splat(@builtin(vertex_index) vertex_index: u32) -> SplatOutput {
+    let corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(-1.0, 1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+    );
+    let corner = corners[vertex_index % 6u];
+    let position = point_data[vertex_index / 6u] + corner * splat.radius;
+
+    var out: SplatOutput;
+    out.clip_position = splat.view_proj * vec4<f32>(position, 0.0, 1.0);
+    out.offset = corner;
+    return out;
+}
+
+@fragment
+fn fs_splat(in: SplatOutput) -> @location(0) vec4<f32> {
+    // the poly6 shape, 1 in the middle and smoothly down to 0 at the radius
+    let falloff = max(1.0 - dot(in.offset, in.offset), 0.0);
+    return vec4<f32>(falloff * falloff * falloff, 0.0, 0.0, 0.0);
+}
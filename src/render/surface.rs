@@ -0,0 +1,338 @@
+use wgpu::util::DeviceExt;
+
+/// summed blobs go in here, one float a pixel that has to be able to blend
+const DENSITY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R16Float;
+
+/// how the liquid looks when the particles are drawn as one surface
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SurfaceStyle {
+    /// linear rgb
+    pub colour: [f32; 3],
+    /// 1 hides whatever's behind the liquid, 0 is invisible
+    pub opacity: f32,
+    /// linear rgb of the rim just inside the edge
+    pub highlight: [f32; 3],
+    /// how much blob has to pile up before it counts as liquid. A lone particle's blob is 1
+    /// in the middle, so anything under 1 still draws stray drops.
+    pub threshold: f32,
+    /// how far past the threshold the rim fades out over, 0 for no rim
+    pub rim: f32,
+    /// how far each particle's blob reaches, in pixels
+    pub radius: f32,
+}
+
+impl Default for SurfaceStyle {
+    fn default() -> Self {
+        Self {
+            colour: [0.02, 0.17, 0.69],
+            opacity: 0.85,
+            highlight: [0.6, 0.85, 1.],
+            threshold: 0.5,
+            rim: 0.6,
+            radius: 12.,
+        }
+    }
+}
+
+/// draws the particles as a liquid. Each one splats a soft blob into an offscreen texture
+/// that adds up where they overlap, then a pass over the whole window fills in wherever
+/// there's enough.
+pub struct SurfaceRenderer {
+    splat_pipeline: wgpu::RenderPipeline,
+    splat_layout: wgpu::BindGroupLayout,
+    splat_bind_group: wgpu::BindGroup,
+    /// the camera and blob radius
+    splat_uniform: wgpu::Buffer,
+    surface_pipeline: wgpu::RenderPipeline,
+    surface_layout: wgpu::BindGroupLayout,
+    surface_bind_group: wgpu::BindGroup,
+    /// the style as surface.wgsl wants it
+    style_uniform: wgpu::Buffer,
+    density: wgpu::TextureView,
+}
+
+impl SurfaceRenderer {
+    /// `particles` is the position buffer the dots are drawn from
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        size: winit::dpi::PhysicalSize<u32>,
+        particles: &wgpu::Buffer,
+    ) -> Self {
+        let splat_uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("splat uniform"),
+            contents: bytemuck::cast_slice(&[0f32; 20]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let style_uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("surface style uniform"),
+            contents: bytemuck::cast_slice(&[0f32; 12]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let splat_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("splat bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let surface_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("surface bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let splat_pipeline = pipeline(
+            device,
+            &splat_layout,
+            include_str!("./splat.wgsl"),
+            "splat",
+            wgpu::ColorTargetState {
+                format: DENSITY_FORMAT,
+                blend: Some(wgpu::BlendState {
+                    color: additive,
+                    alpha: additive,
+                }),
+                write_mask: wgpu::ColorWrites::RED,
+            },
+        );
+        let surface_pipeline = pipeline(
+            device,
+            &surface_layout,
+            include_str!("./surface.wgsl"),
+            "surface",
+            wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            },
+        );
+
+        let splat_bind_group = splat_bind_group(device, &splat_layout, &splat_uniform, particles);
+        let density = density_texture(device, size);
+        let surface_bind_group =
+            surface_bind_group(device, &surface_layout, &density, &style_uniform);
+
+        Self {
+            splat_pipeline,
+            splat_layout,
+            splat_bind_group,
+            splat_uniform,
+            surface_pipeline,
+            surface_layout,
+            surface_bind_group,
+            style_uniform,
+            density,
+        }
+    }
+
+    /// the density texture has to match the window pixel for pixel
+    pub fn resize(&mut self, device: &wgpu::Device, size: winit::dpi::PhysicalSize<u32>) {
+        self.density = density_texture(device, size);
+        self.surface_bind_group = surface_bind_group(
+            device,
+            &self.surface_layout,
+            &self.density,
+            &self.style_uniform,
+        );
+    }
+
+    /// for when the position buffer gets remade
+    pub fn bind_particles(&mut self, device: &wgpu::Device, particles: &wgpu::Buffer) {
+        self.splat_bind_group =
+            splat_bind_group(device, &self.splat_layout, &self.splat_uniform, particles);
+    }
+
+    pub fn upload(&self, queue: &wgpu::Queue, style: &SurfaceStyle, view_proj: [[f32; 4]; 4]) {
+        queue.write_buffer(&self.splat_uniform, 0, bytemuck::cast_slice(&view_proj));
+        let radius = [style.radius, 0., 0., 0.];
+        queue.write_buffer(&self.splat_uniform, 64, bytemuck::cast_slice(&radius));
+        let [r, g, b] = style.colour;
+        let [hr, hg, hb] = style.highlight;
+        let uniform = [
+            [r, g, b, style.opacity],
+            [hr, hg, hb, 0.],
+            [style.threshold, style.rim, 0., 0.],
+        ];
+        queue.write_buffer(&self.style_uniform, 0, bytemuck::cast_slice(&uniform));
+    }
+
+    /// piles up the blobs for the first `count` particles, in a pass of its own that has to
+    /// happen before the one `draw` goes in
+    pub fn splat(&self, encoder: &mut wgpu::CommandEncoder, count: usize) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("splat pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.density,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.splat_pipeline);
+        render_pass.set_bind_group(0, &self.splat_bind_group, &[]);
+        render_pass.draw(0..(count as u32 * 6), 0..1);
+    }
+
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        render_pass.set_pipeline(&self.surface_pipeline);
+        render_pass.set_bind_group(0, &self.surface_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+/// both passes are a vertex shader making their own quads plus a fragment shader, named
+/// `vs_<name>` and `fs_<name>`
+fn pipeline(
+    device: &wgpu::Device,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    source: &str,
+    name: &str,
+    target: wgpu::ColorTargetState,
+) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(name),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(name),
+        layout: Some(&layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some(&format!("vs_{name}")),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some(&format!("fs_{name}")),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            targets: &[Some(target)],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+fn density_texture(
+    device: &wgpu::Device,
+    size: winit::dpi::PhysicalSize<u32>,
+) -> wgpu::TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some("surface density texture"),
+            size: wgpu::Extent3d {
+                width: size.width.max(1),
+                height: size.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DENSITY_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+fn splat_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    uniform: &wgpu::Buffer,
+    particles: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("splat bind group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: particles.as_entire_binding(),
+            },
+        ],
+    })
+}
+
+fn surface_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    density: &wgpu::TextureView,
+    style: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("surface bind group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(density),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: style.as_entire_binding(),
+            },
+        ],
+    })
+}
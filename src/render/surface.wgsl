@@ -0,0 +1,41 @@
+// Fills in the liquid wherever the blobs splat.wgsl added up get over a threshold, with a
+// lighter rim just inside the edge.
+
+@group(0) @binding(0)
+var density: texture_2d<f32>;
+
+struct Style {
+    // linear rgb and how opaque the liquid is
+    colour: vec4<f32>,
+    // linear rgb of the rim, the last one's unused
+    highlight: vec4<f32>,
+    // threshold, how far past it the rim reaches, both in summed blobs, then unused
+    levels: vec4<f32>,
+};
+
+@group(0) @binding(1)
+var<uniform> style: Style;
+
+@vertex
+fn vs_surface(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    // one big triangle that covers the screen, the bits hanging off the edge get clipped
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_surface(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let value = textureLoad(density, vec2<i32>(position.xy), 0).r;
+    let threshold = style.levels.x;
+
+    // about a pixel of soft edge so it doesn't look jagged, however steep the density is
+    let pixel = max(fwidth(value), 1e-4);
+    let inside = clamp((value - threshold) / pixel + 0.5, 0.0, 1.0);
+    if (inside <= 0.0) {
+        discard;
+    }
+
+    let rim = 1.0 - smoothstep(threshold, threshold + max(style.levels.y, 1e-4), value);
+    let colour = mix(style.colour.rgb, style.highlight.rgb, rim);
+    return vec4<f32>(colour, style.colour.a * inside);
+}
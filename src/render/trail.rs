@@ -0,0 +1,165 @@
+use crate::{
+    fluid_sim::{real::Real, simulation::Points, vec2::Vec2},
+    render::lines::LineVertex,
+};
+use rayon::prelude::*;
+use std::collections::VecDeque;
+
+/// anything that moves further than this in a frame got teleported, by a reset or a wrap,
+/// and shouldn't leave a line right across the window
+const JUMP: Real = 40.;
+
+/// the fading tails particles leave behind them
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TrailConfig {
+    pub on: bool,
+    /// how many frames back each tail goes
+    pub length: usize,
+    /// how quickly a tail fades toward its end, 1 is evenly and bigger only keeps the newest
+    /// bit solid
+    pub fade: f32,
+    /// linear rgba at the particle end
+    pub colour: [f32; 4],
+}
+
+impl Default for TrailConfig {
+    fn default() -> Self {
+        Self {
+            on: false,
+            length: 24,
+            fade: 1.5,
+            colour: [0.05, 0.2, 0.75, 0.6],
+        }
+    }
+}
+
+/// where every particle was for the last few frames, newest first. Each frame is in id order,
+/// so a tail follows its particle when the sim shuffles them about.
+#[derive(Clone, Debug, Default)]
+pub struct Trails {
+    frames: VecDeque<Vec<Vec2>>,
+}
+
+impl Trails {
+    /// adds a frame, dropping the oldest ones past `length`. A frame where nothing moved,
+    /// like while paused, doesn't count, and a different number of particles starts over.
+    pub fn record(&mut self, particles: &Points, length: usize) {
+        let frame = particles.by_id();
+        if let Some(newest) = self.frames.front() {
+            if newest.len() != frame.len() {
+                self.frames.clear();
+            } else if *newest == frame {
+                return;
+            }
+        }
+        self.frames.truncate(length.max(1) - 1);
+        self.frames.push_front(frame);
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// every tail as pairs of vertices for a line list, getting fainter the older it is
+    pub fn lines(&self, config: &TrailConfig) -> Vec<LineVertex> {
+        let Some(newest) = self.frames.front() else {
+            return Vec::new();
+        };
+        let frames = self.frames.len();
+        let [r, g, b, a] = config.colour;
+        let fade = |age: usize| {
+            let left = 1. - age as f32 / frames as f32;
+            [r, g, b, a * left.powf(config.fade)]
+        };
+
+        (0..newest.len())
+            .into_par_iter()
+            .flat_map_iter(|i| {
+                (0..frames - 1).filter_map(move |age| {
+                    let (from, to) = (self.frames[age][i], self.frames[age + 1][i]);
+                    ((to - from).length() < JUMP).then(|| {
+                        [
+                            LineVertex::new(from, fade(age)),
+                            LineVertex::new(to, fade(age + 1)),
+                        ]
+                    })
+                })
+            })
+            .flatten_iter()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: Real) -> [Vec2; 2] {
+        [Vec2 { x, y: 10. }, Vec2 { x, y: 20. }]
+    }
+
+    fn points(positions: &[Vec2]) -> Points<'_> {
+        Points {
+            fluid: positions,
+            ids: &[],
+            ghosts: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn tails_fade_and_stop_at_their_length() {
+        let config = TrailConfig::default();
+        let mut trails = Trails::default();
+        for x in 0..10 {
+            trails.record(&points(&at(x as Real)), 4);
+            // paused, nothing moved
+            trails.record(&points(&at(x as Real)), 4);
+        }
+
+        let lines = trails.lines(&config);
+        // three segments between the four frames kept, for each of the two particles
+        assert_eq!(lines.len(), 2 * 3 * 2);
+        for segment in lines.chunks(2) {
+            assert!(segment[1].colour[3] < segment[0].colour[3]);
+            assert!(segment[1].position[0] < segment[0].position[0]);
+            assert!(segment[1].position[0] >= 6.);
+        }
+    }
+
+    #[test]
+    fn teleports_and_new_particles_break_the_tails() {
+        let config = TrailConfig::default();
+        let mut trails = Trails::default();
+        trails.record(&points(&at(0.)), 10);
+        trails.record(&points(&at(1.)), 10);
+        trails.record(&points(&at(500.)), 10);
+        trails.record(&points(&at(501.)), 10);
+        // 0 to 1 and 500 to 501, but not the jump in between
+        assert_eq!(trails.lines(&config).len(), 2 * 2 * 2);
+
+        trails.record(&points(&at(502.)[..1]), 10);
+        assert!(trails.lines(&config).is_empty());
+    }
+
+    #[test]
+    fn tails_follow_ids_when_the_slots_get_shuffled() {
+        let config = TrailConfig::default();
+        let mut trails = Trails::default();
+        trails.record(&points(&at(0.)), 10);
+        let [low, high] = at(1.);
+        trails.record(
+            &Points {
+                fluid: &[high, low],
+                ids: &[1, 0],
+                ghosts: Vec::new(),
+            },
+            10,
+        );
+
+        let lines = trails.lines(&config);
+        assert_eq!(lines.len(), 2 * 2);
+        for segment in lines.chunks(2) {
+            assert_eq!(segment[0].position[1], segment[1].position[1]);
+        }
+    }
+}
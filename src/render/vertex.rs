@@ -1,3 +1,5 @@
+/// a point in sim pixels, the sims' positions get cast straight to these and the instanced
+/// dots' quad is made of them
 #[repr(C)]
 #[derive(bytemuck::Pod, bytemuck::Zeroable, Copy, Debug, Clone, Default)]
 pub struct Vertex {
@@ -5,7 +7,6 @@ pub struct Vertex {
 }
 
 impl Vertex {
-    #[allow(dead_code)]
     pub fn desc() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,